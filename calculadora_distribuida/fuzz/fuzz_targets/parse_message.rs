@@ -0,0 +1,14 @@
+#![no_main]
+
+use calculadora_distribuida::protocol::parse_message;
+use libfuzzer_sys::fuzz_target;
+
+// `parse_message` no debe entrar en pánico con ninguna entrada, y todo
+// mensaje que acepta debe volver a parsearse igual tras escribirlo.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data)
+        && let Ok(msg) = parse_message(line)
+    {
+        assert_eq!(parse_message(&msg.to_string()), Ok(msg));
+    }
+});
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Comando del canal de administración (ver `parse_command`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `AUTH <usuario> <contraseña>`, como en el protocolo de líneas.
+    Auth { user: String, password: String },
+    /// `CLIENTS`: lista las conexiones activas.
+    Clients,
+    /// `KILL <id>`: cierra la conexión `id`.
+    Kill(u64),
+    /// `RESET`: vuelve el valor a 0.
+    Reset,
+    /// `SET <valor>`: reemplaza el valor.
+    Set(u64),
+    /// `SNAPSHOT`: guarda el estado actual como instantánea.
+    Snapshot,
+    /// `RESTORE <id>`: vuelve al valor de la instantánea `id`.
+    Restore(u64),
+    /// `STATS`: estado general del servidor.
+    Stats,
+}
+
+/// Nombres de los comandos de administración.
+const COMMANDS: [&str; 8] = [
+    "AUTH", "CLIENTS", "KILL", "RESET", "SET", "SNAPSHOT", "RESTORE", "STATS",
+];
+
+/// Interpreta una línea del canal de administración.
+///
+/// # Errores
+/// - `"unknown command"` si la línea no empieza con un comando conocido.
+/// - `"parsing error"` si los argumentos del comando no son válidos.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let parsing_error = || "parsing error".to_string();
+    let number = |s: &str| s.parse::<u64>().map_err(|_| parsing_error());
+    match parts.as_slice() {
+        ["AUTH", user, password] => Ok(Command::Auth {
+            user: user.to_string(),
+            password: password.to_string(),
+        }),
+        ["CLIENTS"] => Ok(Command::Clients),
+        ["KILL", id] => number(id).map(Command::Kill),
+        ["RESET"] => Ok(Command::Reset),
+        ["SET", value] => number(value).map(Command::Set),
+        ["SNAPSHOT"] => Ok(Command::Snapshot),
+        ["RESTORE", id] => number(id).map(Command::Restore),
+        ["STATS"] => Ok(Command::Stats),
+        [name, ..] if COMMANDS.contains(name) => Err(parsing_error()),
+        _ => Err("unknown command".to_string()),
+    }
+}
+
+/// Una conexión activa, como la informa `CLIENTS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub peer: String,
+    /// `lines`, `http` o `admin`.
+    pub protocol: &'static str,
+    /// Pedidos recibidos: líneas, pedidos HTTP o mensajes WebSocket.
+    pub requests: u64,
+    /// Tiempo desde el último pedido, o desde la conexión si no hubo.
+    pub idle: Duration,
+}
+
+impl fmt::Display for ClientInfo {
+    /// `CLIENT <id> <peer> <protocolo> requests=<n> idle_ms=<ms>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CLIENT {} {} {} requests={} idle_ms={}",
+            self.id,
+            self.peer,
+            self.protocol,
+            self.requests,
+            self.idle.as_millis()
+        )
+    }
+}
+
+/// Estado general del servidor, como lo informa `STATS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub value: u64,
+    pub version: u64,
+    /// Conexiones activas, incluida la de administración que pregunta.
+    pub clients: usize,
+    /// Pedidos recibidos desde el inicio, por todas las conexiones.
+    pub requests: u64,
+    pub uptime: Duration,
+}
+
+impl fmt::Display for Stats {
+    /// `STATS value=<v> version=<n> clients=<n> requests=<n> uptime_s=<s>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "STATS value={} version={} clients={} requests={} uptime_s={}",
+            self.value,
+            self.version,
+            self.clients,
+            self.requests,
+            self.uptime.as_secs()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("CLIENTS"), Ok(Command::Clients));
+        assert_eq!(parse_command(" KILL 3 "), Ok(Command::Kill(3)));
+        assert_eq!(parse_command("SET 42"), Ok(Command::Set(42)));
+        assert_eq!(parse_command("RESET"), Ok(Command::Reset));
+        assert_eq!(parse_command("STATS"), Ok(Command::Stats));
+        assert_eq!(parse_command("SNAPSHOT"), Ok(Command::Snapshot));
+        assert_eq!(parse_command("RESTORE 7"), Ok(Command::Restore(7)));
+        assert_eq!(parse_command("RESTORE"), Err("parsing error".to_string()));
+        assert_eq!(
+            parse_command("AUTH ana secreto"),
+            Ok(Command::Auth {
+                user: "ana".to_string(),
+                password: "secreto".to_string()
+            })
+        );
+        assert_eq!(parse_command("KILL x"), Err("parsing error".to_string()));
+        assert_eq!(parse_command("SET"), Err("parsing error".to_string()));
+        assert_eq!(parse_command("GET"), Err("unknown command".to_string()));
+        assert_eq!(parse_command(""), Err("unknown command".to_string()));
+    }
+
+    #[test]
+    fn test_display() {
+        let client = ClientInfo {
+            id: 4,
+            peer: "127.0.0.1".to_string(),
+            protocol: "lines",
+            requests: 12,
+            idle: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            client.to_string(),
+            "CLIENT 4 127.0.0.1 lines requests=12 idle_ms=1500"
+        );
+        let stats = Stats {
+            value: 7,
+            version: 3,
+            clients: 2,
+            requests: 40,
+            uptime: Duration::from_secs(61),
+        };
+        assert_eq!(
+            stats.to_string(),
+            "STATS value=7 version=3 clients=2 requests=40 uptime_s=61"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::fs;
+
+/// Usuarios habilitados para operar sobre el servidor.
+///
+/// Se cargan de un archivo con una entrada `usuario:contraseña` por línea;
+/// las líneas vacías y las que empiezan con `#` se ignoran.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Users {
+    passwords: HashMap<String, String>,
+}
+
+impl Users {
+    /// Lee los usuarios del archivo `path`.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("No se pudo leer el archivo de usuarios {}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Interpreta el contenido de un archivo de usuarios.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut passwords = HashMap::new();
+        for (i, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (user, password) = line
+                .split_once(':')
+                .filter(|(u, p)| is_token(u) && is_token(p))
+                .ok_or_else(|| format!("linea {}: se esperaba 'usuario:contraseña'", i + 1))?;
+            passwords.insert(user.to_string(), password.to_string());
+        }
+        Ok(Users { passwords })
+    }
+
+    /// Indica si el par usuario/contraseña es válido.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        self.passwords.get(user).is_some_and(|p| p == password)
+    }
+}
+
+/// Un usuario o contraseña no puede ser vacío ni contener espacios, porque
+/// viajan separados por espacios en el mensaje `AUTH`.
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && !s.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_verify() {
+        let users = Users::parse("# usuarios\nana:secreto\n\nbeto:1234\n").unwrap();
+        assert!(users.verify("ana", "secreto"));
+        assert!(users.verify("beto", "1234"));
+        assert!(!users.verify("ana", "1234"));
+        assert!(!users.verify("carla", "x"));
+    }
+
+    #[test]
+    fn test_parse_invalid_line() {
+        let err = Users::parse("ana:secreto\nsolo_usuario\n").unwrap_err();
+        assert!(err.starts_with("linea 2:"), "{}", err);
+        assert!(Users::parse("ana:con espacio\n").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+/// Alfabeto de base64 estándar (RFC 4648).
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Codifica `data` en base64 estándar con relleno `=`.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut bytes = [0; 3];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodifica base64 estándar con relleno `=`; `None` si `s` no es
+/// base64 válido.
+pub fn decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let chunks = s.as_bytes().chunks(4);
+    let last = chunks.len().saturating_sub(1);
+    for (i, chunk) in chunks.enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && i != last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            n = n << 6 | u32::from(value(c)?);
+        }
+        n <<= 6 * padding as u32;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"a"), "YQ==");
+        assert_eq!(encode(b"ab"), "YWI=");
+        assert_eq!(encode(b"abc"), "YWJj");
+        assert_eq!(encode(b"ana:secreta"), "YW5hOnNlY3JldGE=");
+        for len in 0..20 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            assert_eq!(decode(&encode(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("").unwrap(), b"");
+        assert_eq!(decode("YQ==").unwrap(), b"a");
+        assert_eq!(decode("YWI=").unwrap(), b"ab");
+        assert_eq!(decode("YWJj").unwrap(), b"abc");
+        assert_eq!(decode("YWJjZA==").unwrap(), b"abcd");
+        assert!(decode("YQ=").is_none());
+        assert!(decode("Y===").is_none());
+        assert!(decode("YQ==YWJj").is_none());
+        assert!(decode("YW*j").is_none());
+    }
+}
@@ -0,0 +1,761 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use calculadora_distribuida::calculator::{Arithmetic, NumericType};
+use calculadora_distribuida::operator::Operator;
+use calculadora_distribuida::protocol::{Message, Operation, PROTOCOL_VERSION, parse_message};
+use calculadora_distribuida::simulation::XorShift;
+use calculadora_distribuida::state::{Backend, SharedState, State};
+
+/// Ayuda de la línea de comando.
+const USAGE: &str = "\
+Uso: bench <direccion> [opciones]
+       bench --local [opciones]
+
+Opciones:
+  -c, --clients <n>          clientes concurrentes (por defecto 4)
+  -n, --requests <n>         mensajes por cliente (por defecto 1000)
+  -d, --duration <seg>       en lugar de --requests, enviar durante <seg> segundos
+  -r, --rate <n>             mensajes por segundo entre todos los clientes (0 = sin limite)
+  -m, --mix <mezcla>         proporcion de cada mensaje (por defecto +:45,-:45,get:10)
+      --max-operand <n>      operandos entre 1 y <n> (por defecto 10)
+      --auth <usuario>:<contraseña>
+      --seed <n>             semilla de la generacion de operaciones
+  -h, --help                 muestra esta ayuda
+
+La mezcla es una lista de <mensaje>:<peso> con mensajes +, -, *, / y get.
+Al terminar se verifica que la version del servidor haya aumentado una vez por
+operacion exitosa y, si la mezcla solo usa + y - con aritmetica wrapping, que
+el valor final sea el esperado. La verificacion asume que ningun otro cliente
+modifica el valor durante la medicion.
+
+Con --local no hay servidor: los clientes son hilos que operan directamente
+sobre el estado compartido, una vez con cada backend (mutex, atomic y
+aggregate) y con aritmetica u8 wrapping, para compararlos bajo contencion sin
+el costo de la red.";
+
+/// Backends que compara `--local`; el primero es la referencia.
+const BACKENDS: [Backend; 3] = [Backend::Mutex, Backend::Atomic, Backend::Aggregate];
+
+/// Mensaje que envía un cliente simulado.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Request {
+    Op(Operator),
+    Get,
+}
+
+/// Cuándo deja de enviar mensajes cada cliente.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    Requests(u64),
+    Duration(Duration),
+}
+
+/// Argumentos de línea de comando del benchmark.
+#[derive(Debug, Clone, PartialEq)]
+struct Args {
+    /// Vacía con `--local`.
+    address: String,
+    /// Medir los backends de estado en el proceso en lugar de un servidor.
+    local: bool,
+    clients: usize,
+    stop: Stop,
+    /// Mensajes por segundo entre todos los clientes; `None` sin límite.
+    rate: Option<f64>,
+    mix: Vec<(Request, u32)>,
+    max_operand: u64,
+    credentials: Option<(String, String)>,
+    seed: u64,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            address: String::new(),
+            local: false,
+            clients: 4,
+            stop: Stop::Requests(1000),
+            rate: None,
+            mix: vec![
+                (Request::Op(Operator::Add), 45),
+                (Request::Op(Operator::Sub), 45),
+                (Request::Get, 10),
+            ],
+            max_operand: 10,
+            credentials: None,
+            seed: 1,
+        }
+    }
+}
+
+/// Lo que midió un cliente simulado.
+#[derive(Debug, Default)]
+struct Report {
+    /// Latencia de cada mensaje, respondido con éxito o no.
+    latencies: Vec<Duration>,
+    /// Operaciones que el servidor aplicó (`OK`).
+    applied: u64,
+    /// Suma de los operandos de `+` menos los de `-` aplicados.
+    net_sum: i128,
+    /// Errores por motivo.
+    errors: BTreeMap<String, u64>,
+}
+
+impl Report {
+    fn merge(&mut self, other: Report) {
+        self.latencies.extend(other.latencies);
+        self.applied += other.applied;
+        self.net_sum += other.net_sum;
+        for (motivo, n) in other.errors {
+            *self.errors.entry(motivo).or_default() += n;
+        }
+    }
+}
+
+/// Valor y versión del servidor, y sus reglas aritméticas si las anunció.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    value: u64,
+    version: Option<u64>,
+    /// Tipo numérico, sólo si el servidor usa aritmética wrapping.
+    wrapping: Option<NumericType>,
+}
+
+/// Punto de entrada del benchmark.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    match parse_args(&args).and_then(|args| run(&args)) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", Message::Err(e));
+            process::exit(2);
+        }
+    }
+}
+
+/// Ejecuta el benchmark e imprime el reporte.
+///
+/// Retorna `Ok(false)` si la verificación de consistencia falló.
+fn run(args: &Args) -> Result<bool, String> {
+    if args.local {
+        return run_local(args);
+    }
+    let mut control = Connection::open(args)?;
+    let before = control.snapshot()?;
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..args.clients)
+        .map(|i| {
+            let args = args.clone();
+            thread::spawn(move || {
+                let mut conn = Connection::open(&args)?;
+                simulate_client(&args, i, |message| conn.request(&message.to_string()))
+            })
+        })
+        .collect();
+    let mut report = Report::default();
+    for handle in handles {
+        let client = handle
+            .join()
+            .map_err(|_| "Un cliente simulado termino con panic".to_string())??;
+        report.merge(client);
+    }
+    let elapsed = start.elapsed();
+
+    let after = control.snapshot()?;
+    let check = check_consistency(args, &before, &after, &report);
+    print_report(args, &report, elapsed, &check);
+    Ok(check.iter().all(|(ok, _)| *ok))
+}
+
+/// Mide cada backend de estado con clientes que son hilos del proceso (ver
+/// `USAGE`), imprime un reporte por backend y la comparación de throughput.
+///
+/// Retorna `Ok(false)` si la verificación de consistencia de alguno falló.
+fn run_local(args: &Args) -> Result<bool, String> {
+    let mut consistent = true;
+    let mut throughputs = Vec::new();
+    for backend in BACKENDS {
+        let state = SharedState::new(backend, State::default(), Arithmetic::default())?;
+        let before = local_snapshot(&state)?;
+
+        let start = Instant::now();
+        let reports: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..args.clients)
+                .map(|i| {
+                    let state = &state;
+                    scope.spawn(move || {
+                        simulate_client(args, i, |message| local_request(state, message))
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join()).collect()
+        });
+        let mut report = Report::default();
+        for client in reports {
+            report.merge(client.map_err(|_| "Un cliente simulado termino con panic")??);
+        }
+        let elapsed = start.elapsed();
+
+        let after = local_snapshot(&state)?;
+        let checks = check_consistency(args, &before, &after, &report);
+        println!("backend:       {}", backend);
+        print_report(args, &report, elapsed, &checks);
+        println!();
+        consistent &= checks.iter().all(|(ok, _)| *ok);
+        throughputs.push(report.latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON));
+    }
+    let reference = throughputs[0].max(f64::EPSILON);
+    for (backend, throughput) in BACKENDS.iter().zip(&throughputs).skip(1) {
+        println!(
+            "{}/{}: {:.2}x mensajes/s",
+            backend,
+            BACKENDS[0],
+            throughput / reference
+        );
+    }
+    Ok(consistent)
+}
+
+/// Responde un mensaje como lo haría el servidor con aritmética u8
+/// wrapping, directamente sobre `state`.
+fn local_request(state: &SharedState, message: &Message) -> Result<Message, String> {
+    match message {
+        Message::Op(op, _) if let Some(accumulated) = state.accumulate(op) => {
+            accumulated.map(|()| Message::Ok(None))
+        }
+        Message::Op(op, _) => state.update(
+            |s| match Arithmetic::default().apply(s.value, op) {
+                Ok(value) => {
+                    s.value = value;
+                    s.version += 1;
+                    Message::Ok(Some(s.version))
+                }
+                Err(motivo) => Message::Err(motivo),
+            },
+            |_, _| {},
+        ),
+        _ => state.update(|s| Message::Value(s.value, Some(s.version)), |_, _| {}),
+    }
+}
+
+/// Valor y versión de un estado local, que siempre usa u8 wrapping.
+fn local_snapshot(state: &SharedState) -> Result<Snapshot, String> {
+    let current = state.load()?;
+    Ok(Snapshot {
+        value: current.value,
+        version: Some(current.version),
+        wrapping: Some(NumericType::U8),
+    })
+}
+
+/// Conexión de un cliente simulado.
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    capabilities: Vec<String>,
+}
+
+impl Connection {
+    /// Conecta, negocia con `HELLO` y, si se indicaron credenciales, se
+    /// autentica.
+    fn open(args: &Args) -> Result<Connection, String> {
+        let stream =
+            TcpStream::connect(&args.address).map_err(|e| format!("No se pudo conectar: {}", e))?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+        let mut conn = Connection {
+            stream,
+            reader,
+            capabilities: Vec::new(),
+        };
+
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: vec!["versions".to_string()],
+        };
+        if let Message::Hello { capabilities, .. } = conn.request(&hello.to_string())? {
+            conn.capabilities = capabilities;
+        }
+        if let Some((user, password)) = &args.credentials {
+            let auth = Message::Auth {
+                user: user.clone(),
+                password: password.clone(),
+            };
+            match conn.request(&auth.to_string())? {
+                Message::Ok(_) => {}
+                other => return Err(format!("AUTH rechazado: {}", other)),
+            }
+        }
+        Ok(conn)
+    }
+
+    /// Envía una línea y retorna la respuesta del servidor.
+    fn request(&mut self, line: &str) -> Result<Message, String> {
+        self.stream
+            .write_all(format!("{}\n", line).as_bytes())
+            .map_err(|e| format!("Error enviando: {}", e))?;
+        let mut resp = String::new();
+        let n = self
+            .reader
+            .read_line(&mut resp)
+            .map_err(|e| format!("Error leyendo respuesta: {}", e))?;
+        if n == 0 {
+            return Err("El servidor cerro la conexion".to_string());
+        }
+        parse_message(&resp)
+    }
+
+    /// Consulta el valor y la versión actuales.
+    fn snapshot(&mut self) -> Result<Snapshot, String> {
+        let (value, version) = match self.request("GET")? {
+            Message::Value(value, version) => (value, version),
+            other => return Err(format!("Respuesta inesperada a GET: {}", other)),
+        };
+        let capability = |key: &str| {
+            self.capabilities
+                .iter()
+                .find_map(|c| c.strip_prefix(key)?.strip_prefix('='))
+        };
+        let wrapping = match capability("overflow") {
+            Some("wrapping") => capability("numeric").and_then(|t| t.parse().ok()),
+            _ => None,
+        };
+        Ok(Snapshot {
+            value,
+            version,
+            wrapping,
+        })
+    }
+}
+
+/// Envía mensajes según la mezcla hasta cumplir la condición de corte,
+/// respetando la tasa configurada. `send` envía cada mensaje y retorna la
+/// respuesta.
+fn simulate_client(
+    args: &Args,
+    index: usize,
+    mut send: impl FnMut(&Message) -> Result<Message, String>,
+) -> Result<Report, String> {
+    let mut rng = XorShift::new(args.seed.wrapping_add(index as u64));
+    let interval = args
+        .rate
+        .map(|rate| Duration::from_secs_f64(args.clients as f64 / rate));
+    let mut report = Report::default();
+
+    let start = Instant::now();
+    let mut sent: u64 = 0;
+    loop {
+        match args.stop {
+            Stop::Requests(n) if sent >= n => break,
+            Stop::Duration(d) if start.elapsed() >= d => break,
+            _ => {}
+        }
+        if let Some(interval) = interval {
+            let due = start + interval.mul_f64(sent as f64);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+
+        let request = pick(&args.mix, &mut rng);
+        let message = match request {
+            Request::Op(op) => Message::Op(
+                Operation {
+                    op,
+                    operand: 1 + rng.next_u64() % args.max_operand,
+                },
+                None,
+            ),
+            Request::Get => Message::Get,
+        };
+        let sent_at = Instant::now();
+        let response = send(&message)?;
+        report.latencies.push(sent_at.elapsed());
+        sent += 1;
+
+        match (message, response) {
+            (Message::Op(op, _), Message::Ok(_)) => {
+                report.applied += 1;
+                match op.op {
+                    Operator::Add => report.net_sum += i128::from(op.operand),
+                    Operator::Sub => report.net_sum -= i128::from(op.operand),
+                    _ => {}
+                }
+            }
+            (Message::Get, Message::Value(..)) => {}
+            (_, Message::Err(motivo)) => {
+                *report.errors.entry(error_kind(&motivo)).or_default() += 1;
+            }
+            (_, other) => {
+                *report
+                    .errors
+                    .entry(format!("respuesta inesperada: {}", other))
+                    .or_default() += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Agrupa los errores que incluyen datos variables, como el tiempo de
+/// espera de `rate limited; retry after <n> ms`.
+fn error_kind(motivo: &str) -> String {
+    motivo.split(';').next().unwrap_or(motivo).to_string()
+}
+
+/// Elige un mensaje de la mezcla, con probabilidad proporcional a su peso.
+fn pick(mix: &[(Request, u32)], rng: &mut XorShift) -> Request {
+    let total: u64 = mix.iter().map(|(_, w)| u64::from(*w)).sum();
+    let mut n = rng.next_u64() % total;
+    for (request, weight) in mix {
+        if n < u64::from(*weight) {
+            return *request;
+        }
+        n -= u64::from(*weight);
+    }
+    mix[mix.len() - 1].0
+}
+
+/// Verifica el estado final contra lo que informaron los clientes.
+///
+/// Retorna cada verificación realizada con su resultado y descripción.
+fn check_consistency(
+    args: &Args,
+    before: &Snapshot,
+    after: &Snapshot,
+    report: &Report,
+) -> Vec<(bool, String)> {
+    let mut checks = Vec::new();
+    if let (Some(v0), Some(v1)) = (before.version, after.version) {
+        let expected = v0 + report.applied;
+        checks.push((
+            v1 == expected,
+            format!(
+                "version {} -> {} (esperada {}, {} operaciones aplicadas)",
+                v0, v1, expected, report.applied
+            ),
+        ));
+    }
+
+    let additive = args
+        .mix
+        .iter()
+        .all(|(r, _)| matches!(r, Request::Get | Request::Op(Operator::Add | Operator::Sub)));
+    if let Some(numeric_type) = before.wrapping.filter(|_| additive) {
+        let expected = expected_value(before.value, report.net_sum, numeric_type);
+        checks.push((
+            after.value == expected,
+            format!("valor {} (esperado {})", after.value, expected),
+        ));
+    }
+    checks
+}
+
+/// Valor que resulta de sumar `net_sum` a `initial` con aritmética wrapping
+/// sobre `numeric_type`.
+fn expected_value(initial: u64, net_sum: i128, numeric_type: NumericType) -> u64 {
+    let modulus = i128::from(numeric_type.max()) + 1;
+    (i128::from(initial) + net_sum).rem_euclid(modulus) as u64
+}
+
+/// Percentil `p` (entre 0 y 100) de latencias ya ordenadas.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn print_report(args: &Args, report: &Report, elapsed: Duration, checks: &[(bool, String)]) {
+    let mut latencies = report.latencies.clone();
+    latencies.sort();
+    let total = latencies.len();
+    let errors: u64 = report.errors.values().sum();
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+
+    println!("clientes:      {}", args.clients);
+    println!(
+        "mensajes:      {} ({} operaciones aplicadas, {} errores)",
+        total, report.applied, errors
+    );
+    println!("duracion:      {:.3} s", elapsed.as_secs_f64());
+    println!(
+        "throughput:    {:.1} mensajes/s",
+        total as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+    for (name, p) in [("p50", 50.0), ("p90", 90.0), ("p99", 99.0)] {
+        println!(
+            "latencia {}:  {:.3} ms",
+            name,
+            ms(percentile(&latencies, p))
+        );
+    }
+    println!(
+        "latencia max:  {:.3} ms",
+        ms(latencies.last().copied().unwrap_or_default())
+    );
+    for (motivo, n) in &report.errors {
+        println!("error:         {} x{}", Message::Err(motivo.clone()), n);
+    }
+    if checks.is_empty() {
+        println!("consistencia:  no verificable (el servidor no informa version)");
+    }
+    for (ok, description) in checks {
+        let status = if *ok { "ok" } else { "INCONSISTENTE" };
+        println!("consistencia:  {}: {}", status, description);
+    }
+}
+
+/// Interpreta los argumentos (sin el nombre del programa).
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut iter = args.iter();
+    let mut parsed = match iter.next() {
+        Some(flag) if flag == "--local" => Args {
+            local: true,
+            ..Args::default()
+        },
+        Some(address) if !address.starts_with('-') => Args {
+            address: address.clone(),
+            ..Args::default()
+        },
+        _ => return Err("Se esperaba la direccion del servidor o --local".to_string()),
+    };
+
+    while let Some(flag) = iter.next() {
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("Falta el valor de {}", flag))?;
+        let invalid = || format!("{}: valor invalido '{}'", flag, value);
+        match flag.as_str() {
+            "-c" | "--clients" => {
+                parsed.clients = value.parse().ok().filter(|n| *n > 0).ok_or_else(invalid)?
+            }
+            "-n" | "--requests" => {
+                parsed.stop = Stop::Requests(value.parse().map_err(|_| invalid())?)
+            }
+            "-d" | "--duration" => {
+                let secs = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| s.is_finite() && *s > 0.0)
+                    .ok_or_else(invalid)?;
+                parsed.stop = Stop::Duration(Duration::from_secs_f64(secs));
+            }
+            "-r" | "--rate" => {
+                let rate = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|r| r.is_finite() && *r >= 0.0)
+                    .ok_or_else(invalid)?;
+                parsed.rate = (rate > 0.0).then_some(rate);
+            }
+            "-m" | "--mix" => {
+                parsed.mix = parse_mix(value).map_err(|e| format!("{}: {}", flag, e))?
+            }
+            "--max-operand" => {
+                parsed.max_operand = value.parse().ok().filter(|n| *n > 0).ok_or_else(invalid)?
+            }
+            "--seed" => parsed.seed = value.parse().map_err(|_| invalid())?,
+            "--auth" if parsed.local => return Err("--auth no se usa con --local".to_string()),
+            "--auth" => {
+                let (user, password) = value
+                    .split_once(':')
+                    .filter(|(u, p)| !u.is_empty() && !p.is_empty())
+                    .ok_or_else(invalid)?;
+                parsed.credentials = Some((user.to_string(), password.to_string()));
+            }
+            _ => return Err(format!("Opcion desconocida: {}", flag)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Interpreta una mezcla `<mensaje>:<peso>,...`, por ejemplo `+:40,get:60`.
+fn parse_mix(s: &str) -> Result<Vec<(Request, u32)>, String> {
+    let mix = s
+        .split(',')
+        .map(|entry| {
+            let (name, weight) = entry
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| format!("se esperaba <mensaje>:<peso> en '{}'", entry))?;
+            let request = match name {
+                "get" => Request::Get,
+                op => Request::Op(
+                    op.parse()
+                        .map_err(|_| format!("mensaje desconocido '{}'", op))?,
+                ),
+            };
+            let weight = weight
+                .parse::<u32>()
+                .map_err(|_| format!("peso invalido '{}'", weight))?;
+            Ok((request, weight))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if mix.iter().all(|(_, w)| *w == 0) {
+        return Err("la mezcla no tiene pesos positivos".to_string());
+    }
+    Ok(mix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse_args(&to_args(&[
+            "127.0.0.1:1",
+            "-c",
+            "8",
+            "--duration",
+            "2.5",
+            "--rate",
+            "100",
+            "--mix",
+            "*:1,get:3",
+        ]))
+        .unwrap();
+        assert_eq!(args.clients, 8);
+        assert_eq!(args.stop, Stop::Duration(Duration::from_millis(2500)));
+        assert_eq!(args.rate, Some(100.0));
+        assert_eq!(
+            args.mix,
+            [(Request::Op(Operator::Mul), 1), (Request::Get, 3)]
+        );
+
+        let local = parse_args(&to_args(&["--local", "-c", "2"])).unwrap();
+        assert!(local.local);
+        assert_eq!(local.clients, 2);
+        assert!(parse_args(&to_args(&["--local", "--auth", "a:b"])).is_err());
+
+        assert!(parse_args(&[]).is_err());
+        assert!(parse_args(&to_args(&["a", "--clients", "0"])).is_err());
+        assert!(parse_args(&to_args(&["a", "--foo", "1"])).is_err());
+        assert!(parse_args(&to_args(&["a", "--rate"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_mix() {
+        assert!(parse_mix("+:1,%:2").is_err());
+        assert!(parse_mix("+:0").is_err());
+        assert!(parse_mix("+").is_err());
+        assert_eq!(
+            parse_mix(" /:2 , -:0").unwrap(),
+            [
+                (Request::Op(Operator::Div), 2),
+                (Request::Op(Operator::Sub), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pick_respects_weights() {
+        let mix = [(Request::Get, 0), (Request::Op(Operator::Add), 1)];
+        let mut rng = XorShift::new(7);
+        assert!((0..100).all(|_| pick(&mix, &mut rng) == Request::Op(Operator::Add)));
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_expected_value_wraps() {
+        assert_eq!(expected_value(250, 10, NumericType::U8), 4);
+        assert_eq!(expected_value(5, -10, NumericType::U8), 251);
+        assert_eq!(expected_value(0, -1, NumericType::U64), u64::MAX);
+    }
+
+    #[test]
+    fn test_local_backends_are_consistent() {
+        let args = Args {
+            local: true,
+            mix: vec![
+                (Request::Op(Operator::Add), 1),
+                (Request::Op(Operator::Sub), 1),
+            ],
+            stop: Stop::Requests(200),
+            ..Args::default()
+        };
+        for backend in BACKENDS {
+            let state = SharedState::new(backend, State::default(), Arithmetic::default()).unwrap();
+            let before = local_snapshot(&state).unwrap();
+            let mut report = Report::default();
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..4)
+                    .map(|i| {
+                        let (args, state) = (&args, &state);
+                        scope.spawn(move || {
+                            simulate_client(args, i, |m| local_request(state, m)).unwrap()
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    report.merge(handle.join().unwrap());
+                }
+            });
+            let after = local_snapshot(&state).unwrap();
+            assert_eq!(after.version, Some(800));
+            let checks = check_consistency(&args, &before, &after, &report);
+            assert!(
+                checks.iter().all(|(ok, _)| *ok),
+                "{}: {:?}",
+                backend,
+                checks
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let args = Args::default();
+        let before = Snapshot {
+            value: 0,
+            version: Some(3),
+            wrapping: Some(NumericType::U8),
+        };
+        let report = Report {
+            applied: 2,
+            net_sum: -1,
+            ..Report::default()
+        };
+        let after = Snapshot {
+            value: 255,
+            version: Some(5),
+            ..before
+        };
+        assert!(
+            check_consistency(&args, &before, &after, &report)
+                .iter()
+                .all(|(ok, _)| *ok)
+        );
+
+        let lost_update = Snapshot {
+            version: Some(4),
+            ..after
+        };
+        let checks = check_consistency(&args, &before, &lost_update, &report);
+        assert!(!checks[0].0, "{}", checks[0].1);
+    }
+}
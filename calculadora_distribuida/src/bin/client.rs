@@ -1,72 +1,1151 @@
 use std::env;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
+use std::fmt;
+use std::fs::{self, File};
+#[cfg(unix)]
+use std::io::IsTerminal;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use calculadora_distribuida::protocol::{Message, parse_message};
+use calculadora_distribuida::calculator::Arithmetic;
+use calculadora_distribuida::datagram::{self, MAX_OPS_PER_DATAGRAM, Outbox};
+use calculadora_distribuida::expression::compile_line;
+#[cfg(unix)]
+use calculadora_distribuida::line_editor::{self, Edit, LineEditor, RawMode};
+use calculadora_distribuida::protocol::{
+    MAX_BATCH_SIZE, Message, Operation, PROTOCOL_VERSION, parse_message,
+};
+use calculadora_distribuida::repl::{self, Command};
+use calculadora_distribuida::report::{self, FileReport, Format, LineResult, Report};
+use calculadora_distribuida::transport::Stream;
 
 /// Punto de entrada del cliente.
-/// Ejecuta el cliente y maneja errores generales.
-fn main() {
-    if let Err(e) = run_client() {
-        eprintln!("{}", e);
+/// Ejecuta el cliente y termina con el código de salida que corresponde
+/// (ver `Failure::exit_code` y `EXIT_PARTIAL`).
+fn main() -> ExitCode {
+    match run_client() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+/// Código de salida cuando alguna línea del archivo no se pudo aplicar.
+const EXIT_PARTIAL: u8 = 1;
+/// Código de salida por argumentos inválidos o un archivo ilegible.
+const EXIT_USAGE: u8 = 2;
+/// Código de salida cuando no se pudo conectar o se cortó la comunicación.
+const EXIT_CONNECTION: u8 = 3;
+/// Código de salida cuando el servidor rechazó la sesión o respondió algo
+/// inesperado.
+const EXIT_SERVER: u8 = 4;
+
+/// Motivo por el que el cliente termina sin completar su trabajo.
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    Usage(String),
+    Connection(String),
+    Server(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Usage(_) => EXIT_USAGE,
+            Failure::Connection(_) => EXIT_CONNECTION,
+            Failure::Server(_) => EXIT_SERVER,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Usage(m) | Failure::Connection(m) | Failure::Server(m) => write!(f, "{}", m),
+        }
     }
 }
 
 /// Ejecuta la lógica principal del cliente.
 ///
-/// - Inicializa la conexión con el servidor y abre el archivo de operaciones.
-/// - Envía todas las operaciones del archivo al servidor.
-/// - Solicita el valor final al servidor y lo imprime.
+/// - Lee los archivos de operaciones, si se indicaron (ver
+///   `expand_inputs`).
+/// - Se conecta al servidor y negocia la versión del protocolo y las
+///   capacidades con `HELLO`, y compila los archivos con la aritmética que
+///   anunció el servidor (ver `compile`).
+/// - Si se indicó `--auth`, se autentica ante el servidor.
+/// - Envía las operaciones de cada archivo al servidor, en orden, solicita
+///   el valor al terminar cada uno e imprime el resultado (ver
+///   `print_reports`). Con `--parallel`, cada archivo usa su propia
+///   conexión y se envían todos a la vez (ver `send_parallel`).
+/// - Sin archivos, entra en modo interactivo leyendo comandos de `stdin`.
+/// - Con `--udp`, envía las operaciones de los archivos por datagramas
+///   (ver `send_datagrams`), sin valor final; sólo pregunta con `HELLO`
+///   cuántas operaciones caben en cada uno (ver `datagram_size`).
+/// - Con `--check` o `--dry-run`, valida o simula los archivos sin
+///   contactar al servidor (ver `check` y `Simulation`).
 ///
-/// Retorna `Ok(())` si todo fue exitoso, o `Err(String)` con un mensaje de error.
-fn run_client() -> Result<(), String> {
-    let (mut stream, file) = init_client()?;
-    read_file(file, &mut stream)?;
-    get_final_value(&mut stream)?;
-    Ok(())
+/// Retorna el código de salida: `EXIT_PARTIAL` si alguna línea falló, o
+/// `Err(Failure)` si no se pudo completar el trabajo.
+fn run_client() -> Result<ExitCode, Failure> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = parse_args(&args).map_err(Failure::Usage)?;
+    let inputs = expand_inputs(&args.files)
+        .and_then(|files| {
+            files
+                .into_iter()
+                .map(|file| Ok((read_file(&file)?, file)))
+                .collect::<Result<Vec<_>, String>>()
+        })
+        .map_err(Failure::Usage)?;
+    let compile_all = |arithmetic| -> Vec<(Vec<CompiledLine>, String)> {
+        inputs
+            .iter()
+            .map(|(source, file)| (compile(source, arithmetic), file.clone()))
+            .collect()
+    };
+    let address = || {
+        args.address
+            .as_deref()
+            .ok_or_else(|| Failure::Usage("Se esperaba la direccion".to_string()))
+    };
+    let file_report = |file: &String, report| FileReport {
+        file: file.clone(),
+        report,
+    };
+
+    let mut rejected = 0;
+    let reports: Vec<FileReport> = match args.mode {
+        Mode::Check => compile_all(args.arithmetic)
+            .iter()
+            .map(|(lines, file)| file_report(file, check(lines)))
+            .collect(),
+        Mode::DryRun => {
+            let mut simulation = Simulation::new(args.arithmetic);
+            compile_all(args.arithmetic)
+                .iter()
+                .map(|(lines, file)| file_report(file, simulation.run(lines)))
+                .collect()
+        }
+        Mode::Udp => {
+            let compiled = compile_all(args.arithmetic);
+            let socket = udp_connect(address()?)?;
+            let chunks = pack_lines(
+                compiled.iter().flat_map(|(lines, _)| lines),
+                datagram_size(&socket)?,
+            );
+            rejected = send_datagrams(&socket, chunks)?;
+            compiled
+                .iter()
+                .map(|(lines, file)| {
+                    let report = Report {
+                        lines: line_results(lines, Vec::new()),
+                        value: None,
+                    };
+                    file_report(file, report)
+                })
+                .collect()
+        }
+        Mode::Connect if inputs.is_empty() => {
+            let (mut stream, info) = open_session(address()?, args.credentials.as_ref())?;
+            run_stdin_repl(&mut stream, info.arithmetic).map_err(Failure::Connection)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Mode::Connect if args.parallel => {
+            send_parallel(address()?, args.credentials.as_ref(), &inputs)?
+        }
+        Mode::Connect => {
+            let (mut stream, info) = open_session(address()?, args.credentials.as_ref())?;
+            compile_all(info.arithmetic)
+                .iter()
+                .map(|(lines, file)| {
+                    Ok(file_report(
+                        file,
+                        send_file(lines, &mut stream, info.batch)?,
+                    ))
+                })
+                .collect::<Result<_, Failure>>()?
+        }
+    };
+    print_reports(&reports, args.format);
+    let errors: usize = reports.iter().map(|f| f.report.errors()).sum();
+    Ok(exit_code(errors + rejected))
+}
+
+/// Lo que el cliente necesita saber del servidor, según las capacidades
+/// que anunció en `HELLO`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ServerInfo {
+    /// Operaciones por lote (`LINES`), si soporta lotes (ver `batch_size`).
+    batch: Option<usize>,
+    /// Reglas aritméticas del servidor, para compilar las expresiones.
+    arithmetic: Arithmetic,
+}
+
+/// Se conecta al servidor, negocia el protocolo (ver `handshake`) y se
+/// autentica si hay `credentials`. Retorna la conexión y lo que anunció el
+/// servidor (ver `ServerInfo`).
+///
+/// # Errores
+/// Retorna `Err(Failure)` si no se puede conectar, el servidor rechaza la
+/// sesión o exige credenciales que no se indicaron.
+fn open_session(
+    address: &str,
+    credentials: Option<&Credentials>,
+) -> Result<(Stream, ServerInfo), Failure> {
+    let mut stream = Stream::connect(address)
+        .map_err(|e| Failure::Connection(format!("No se pudo conectar: {}", e)))?;
+    let capabilities = handshake(&mut stream)?;
+    let has = |c: &str| capabilities.iter().any(|s| s == c);
+    let info = ServerInfo {
+        batch: batch_size(&capabilities)?,
+        arithmetic: server_arithmetic(&capabilities)?,
+    };
+    match credentials {
+        Some((user, password)) => authenticate(user, password, &mut stream)?,
+        None if has("auth") => {
+            return Err(Failure::Server(
+                "El servidor requiere autenticacion (--auth <usuario>:<contraseña>)".into(),
+            ));
+        }
+        None => {}
+    }
+    Ok((stream, info))
+}
+
+/// Reglas aritméticas que anunció el servidor con `numeric=` y `overflow=`;
+/// las que falten son las por defecto, como en un servidor anterior a esas
+/// capacidades.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si el servidor anuncia un tipo o una política que
+/// el cliente no conoce: no podría calcular las constantes como él.
+fn server_arithmetic(capabilities: &[String]) -> Result<Arithmetic, Failure> {
+    let mut arithmetic = Arithmetic::default();
+    for capability in capabilities {
+        let invalid = |e: String| Failure::Server(format!("ERROR \"{}: {}\"", capability, e));
+        if let Some(numeric_type) = capability.strip_prefix("numeric=") {
+            arithmetic.numeric_type = numeric_type.parse().map_err(invalid)?;
+        } else if let Some(overflow) = capability.strip_prefix("overflow=") {
+            arithmetic.overflow = overflow.parse().map_err(invalid)?;
+        }
+    }
+    Ok(arithmetic)
+}
+
+/// Operaciones por lote según lo que anunció el servidor: `None` si no
+/// soporta `batch`; si no, `MAX_BATCH_SIZE`, o su `burst=` si es menor. Con
+/// limitación de tasa cada operación consume un token, y un lote de más
+/// operaciones que la capacidad del bucket se rechazaría siempre.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si `burst=` no es un entero positivo.
+fn batch_size(capabilities: &[String]) -> Result<Option<usize>, Failure> {
+    if !capabilities.iter().any(|c| c == "batch") {
+        return Ok(None);
+    }
+    Ok(Some(
+        burst(capabilities)?.map_or(MAX_BATCH_SIZE, |n| n.min(MAX_BATCH_SIZE)),
+    ))
+}
+
+/// Capacidad del token bucket que anunció el servidor con `burst=`, o
+/// `None` si no limita la tasa.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si `burst=` no es un entero positivo.
+fn burst(capabilities: &[String]) -> Result<Option<usize>, Failure> {
+    let Some(capability) = capabilities.iter().find(|c| c.starts_with("burst=")) else {
+        return Ok(None);
+    };
+    capability["burst=".len()..]
+        .parse()
+        .ok()
+        .filter(|&n| n > 0)
+        .map(Some)
+        .ok_or_else(|| {
+            Failure::Server(format!(
+                "ERROR \"{}: se esperaba un entero positivo\"",
+                capability
+            ))
+        })
+}
+
+/// Envía las líneas de un archivo y consulta el valor al terminar.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si falla la comunicación con el servidor.
+fn send_file(
+    lines: &[CompiledLine],
+    stream: &mut (impl Read + Write),
+    batch: Option<usize>,
+) -> Result<Report, Failure> {
+    let results = send_lines(lines, stream, batch).map_err(Failure::Connection)?;
+    Ok(Report {
+        lines: results,
+        value: Some(get_final_value(stream)?),
+    })
+}
+
+/// Envía cada archivo por su propia conexión, todos a la vez, y retorna sus
+/// reportes en el orden de `inputs`. Cada archivo se compila con la
+/// aritmética que anunció el servidor en su conexión.
+///
+/// Las operaciones de distintos archivos se intercalan en el servidor, así
+/// que el valor de cada reporte es el que el servidor tenía al terminar ese
+/// archivo, no el resultado de aplicarlo sólo a él.
+///
+/// # Errores
+/// Retorna el `Err(Failure)` del primer archivo (en orden) que no se pudo
+/// enviar; los demás se envían igual.
+fn send_parallel(
+    address: &str,
+    credentials: Option<&Credentials>,
+    inputs: &[(Vec<SourceLine>, String)],
+) -> Result<Vec<FileReport>, Failure> {
+    thread::scope(|s| {
+        let handles: Vec<_> = inputs
+            .iter()
+            .map(|(source, file)| {
+                s.spawn(move || {
+                    let (mut stream, info) = open_session(address, credentials)?;
+                    let lines = compile(source, info.arithmetic);
+                    Ok(FileReport {
+                        file: file.clone(),
+                        report: send_file(&lines, &mut stream, info.batch)?,
+                    })
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    Err(Failure::Connection(
+                        "El envio de un archivo termino inesperadamente".to_string(),
+                    ))
+                })
+            })
+            .collect()
+    })
+}
+
+/// `EXIT_PARTIAL` si hubo errores, o éxito.
+fn exit_code(errors: usize) -> ExitCode {
+    match errors {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(EXIT_PARTIAL),
+    }
+}
+
+/// Imprime el resultado de procesar el archivo.
+///
+/// Con `--format` se imprime el reporte completo por `stdout` (ver
+/// `Report::render`). Sin él, sólo el valor final por `stdout` y cada línea
+/// con error por `stderr`, como `ERROR "linea <n>, <motivo>"`.
+fn print_report(report: &Report, format: Option<Format>) {
+    if let Some(format) = format {
+        print!("{}", report.render(format));
+        return;
+    }
+    for line in &report.lines {
+        if let Err(motivo) = &line.result {
+            eprintln!("ERROR \"linea {}, {}\"", line.line, motivo);
+        }
+    }
+    if let Some(value) = report.value {
+        println!("{}", value);
+    }
+}
+
+/// Imprime el resultado de procesar los archivos.
+///
+/// Con un solo archivo, como `print_report`. Con varios y `--format`, el
+/// reporte de todos por `stdout` (ver `report::render_files`); sin él, un
+/// resumen por archivo por `stdout`, `<archivo>: <n> lineas, <n> errores,
+/// valor <v>`, y cada línea con error por `stderr`, como
+/// `ERROR "<archivo>, linea <n>, <motivo>"`.
+fn print_reports(reports: &[FileReport], format: Option<Format>) {
+    if let [single] = reports {
+        print_report(&single.report, format);
+        return;
+    }
+    if let Some(format) = format {
+        print!("{}", report::render_files(reports, format));
+        return;
+    }
+    for FileReport { file, report } in reports {
+        for line in &report.lines {
+            if let Err(motivo) = &line.result {
+                eprintln!("ERROR \"{}, linea {}, {}\"", file, line.line, motivo);
+            }
+        }
+        let value = report.value.map(|v| format!(", valor {}", v));
+        println!(
+            "{}: {} lineas, {} errores{}",
+            file,
+            report.lines.len(),
+            report.errors(),
+            value.unwrap_or_default()
+        );
+    }
+}
+
+/// Credenciales `(usuario, contraseña)` para el mensaje `AUTH`.
+type Credentials = (String, String);
+
+/// Qué hace el cliente con el archivo de operaciones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Lo envía por una conexión; sin archivo, entra en modo interactivo.
+    Connect,
+    /// Lo envía por datagramas UDP.
+    Udp,
+    /// Sólo valida sus líneas, sin contactar al servidor.
+    Check,
+    /// Simula su resultado localmente, sin contactar al servidor.
+    DryRun,
+}
+
+/// Argumentos de línea de comando del cliente.
+#[derive(Debug, PartialEq, Eq)]
+struct Args {
+    /// `None` en los modos que no contactan al servidor.
+    address: Option<String>,
+    /// Archivos, patrones o `-`, sin expandir (ver `expand_inputs`).
+    files: Vec<String>,
+    credentials: Option<Credentials>,
+    mode: Mode,
+    /// Enviar cada archivo por su propia conexión, todos a la vez.
+    parallel: bool,
+    /// Formato del reporte; `None` imprime sólo el valor final.
+    format: Option<Format>,
+    /// Reglas aritméticas de `--check`, `--dry-run` y `--udp`; al
+    /// conectarse se usan las que anuncia el servidor.
+    arithmetic: Arithmetic,
+}
+
+/// Interpreta los argumentos (sin el nombre del programa).
+///
+/// Uso: `client <direccion> [<archivo>...] [--auth <usuario>:<contraseña>]
+/// [--format text|json|csv] [--udp | --parallel]`, donde la dirección es
+/// `host:puerto` o `unix:<ruta>`. Con `--udp` la dirección es la de `--udp`
+/// del servidor. Cada archivo puede ser un patrón o `-` (ver
+/// `expand_inputs`).
+///
+/// `client <archivo>... --check|--dry-run [--format ...]` no contacta al
+/// servidor. `--check`, `--dry-run` y `--udp` admiten además
+/// `--numeric-type` y `--overflow`, con los mismos valores que el servidor,
+/// para calcular las constantes de las expresiones (y simular, con
+/// `--dry-run`); al conectarse se usan las que anuncia el servidor.
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut credentials = None;
+    let mut mode = Mode::Connect;
+    let mut parallel = false;
+    let mut format = None;
+    let mut arithmetic: Option<Arithmetic> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |usage: &str| iter.next().ok_or(format!("Se esperaba {}", usage));
+        match arg.as_str() {
+            "--udp" | "--check" | "--dry-run" if mode != Mode::Connect => {
+                return Err("Solo se admite uno de --udp, --check y --dry-run".to_string());
+            }
+            "--udp" => mode = Mode::Udp,
+            "--check" => mode = Mode::Check,
+            "--dry-run" => mode = Mode::DryRun,
+            "--parallel" => parallel = true,
+            "--format" => format = Some(value("--format text|json|csv")?.parse()?),
+            "--auth" => {
+                credentials = Some(parse_credentials(value("--auth <usuario>:<contraseña>")?)?)
+            }
+            "--numeric-type" => {
+                arithmetic.get_or_insert_default().numeric_type =
+                    value("--numeric-type <tipo>")?.parse()?
+            }
+            "--overflow" => {
+                arithmetic.get_or_insert_default().overflow =
+                    value("--overflow <politica>")?.parse()?
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if credentials.is_some() && mode != Mode::Connect {
+        return Err("--auth no se usa con --udp, --check ni --dry-run".to_string());
+    }
+    if format.is_some() && mode == Mode::Udp {
+        return Err("--udp no admite --format: el servidor no informa resultados".to_string());
+    }
+    if arithmetic.is_some() && mode == Mode::Connect {
+        return Err(
+            "--numeric-type y --overflow solo se usan con --check, --dry-run y --udp".to_string(),
+        );
+    }
+    if parallel && mode != Mode::Connect {
+        return Err("--parallel no se usa con --udp, --check ni --dry-run".to_string());
+    }
+    let (address, files) = match (mode, positional.split_first()) {
+        (Mode::Check | Mode::DryRun, Some(_)) => (None, positional),
+        (Mode::Check | Mode::DryRun, None) => {
+            return Err("--check y --dry-run esperan al menos un archivo".to_string());
+        }
+        (_, Some((address, files))) => (Some(address.clone()), files.to_vec()),
+        (_, None) => {
+            return Err(
+                "Se esperaba direccion y, opcionalmente, archivos como argumentos".to_string(),
+            );
+        }
+    };
+    if files.is_empty() && (mode == Mode::Udp || parallel || format.is_some()) {
+        return Err("--udp, --parallel y --format requieren al menos un archivo".to_string());
+    }
+    Ok(Args {
+        address,
+        files,
+        credentials,
+        mode,
+        parallel,
+        format,
+        arithmetic: arithmetic.unwrap_or_default(),
+    })
+}
+
+/// Interpreta credenciales con el formato `usuario:contraseña`.
+fn parse_credentials(s: &str) -> Result<Credentials, String> {
+    match s.split_once(':') {
+        Some((user, password)) if !user.is_empty() && !password.is_empty() => {
+            Ok((user.to_string(), password.to_string()))
+        }
+        _ => Err("Se esperaba --auth <usuario>:<contraseña>".to_string()),
+    }
+}
+
+/// Capacidades del protocolo que el cliente anuncia en `HELLO`.
+const CLIENT_CAPABILITIES: [&str; 2] = ["versions", "batch"];
+
+/// Negocia la versión del protocolo con `HELLO` y retorna las capacidades
+/// que anunció el servidor.
+///
+/// Un servidor anterior al handshake responde `HELLO` con `parsing error`
+/// o `unexpected message`; en ese caso se continúa sin capacidades
+/// opcionales. Cualquier otro error (como `server busy`) termina la sesión.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si falla la comunicación o el servidor usa otra
+/// versión del protocolo.
+fn handshake(stream: &mut (impl Read + Write)) -> Result<Vec<String>, Failure> {
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        capabilities: CLIENT_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+    };
+    stream
+        .write_all(format!("{}\n", hello).as_bytes())
+        .map_err(|e| Failure::Connection(format!("Error enviando HELLO: {}", e)))?;
+
+    hello_capabilities(&read_response(stream).map_err(Failure::Connection)?)
+}
+
+/// Capacidades de la respuesta `reply` a un `HELLO` (ver `handshake`).
+///
+/// # Errores
+/// Retorna `Err(Failure)` si el servidor usa otra versión del protocolo o
+/// rechaza la sesión.
+fn hello_capabilities(reply: &str) -> Result<Vec<String>, Failure> {
+    match parse_message(reply) {
+        Ok(Message::Hello {
+            version,
+            capabilities,
+        }) if version == PROTOCOL_VERSION => Ok(capabilities),
+        Ok(Message::Hello { version, .. }) => Err(Failure::Server(format!(
+            "ERROR \"El servidor usa la version {} del protocolo y el cliente la {}\"",
+            version, PROTOCOL_VERSION
+        ))),
+        Ok(Message::Err(m)) if m.starts_with("unsupported protocol version") => {
+            Err(Failure::Server(format!("ERROR \"{}\"", m)))
+        }
+        Ok(Message::Err(m)) if m == "parsing error" || m.starts_with("unexpected message") => {
+            Ok(Vec::new())
+        }
+        Ok(Message::Err(m)) => Err(Failure::Server(format!("ERROR \"{}\"", m))),
+        Ok(other) => Err(Failure::Server(format!(
+            "ERROR \"Respuesta inesperada: {}\"",
+            other
+        ))),
+        Err(e) => Err(Failure::Server(format!("ERROR \"{}\"", e))),
+    }
+}
+
+/// Envía `AUTH` al servidor y espera su confirmación.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si falla la comunicación o el servidor rechaza
+/// las credenciales.
+fn authenticate(
+    user: &str,
+    password: &str,
+    stream: &mut (impl Read + Write),
+) -> Result<(), Failure> {
+    let auth = Message::Auth {
+        user: user.to_string(),
+        password: password.to_string(),
+    };
+    stream
+        .write_all(format!("{}\n", auth).as_bytes())
+        .map_err(|e| Failure::Connection(format!("Error enviando AUTH: {}", e)))?;
+
+    match parse_message(&read_response(stream).map_err(Failure::Connection)?) {
+        Ok(Message::Ok(_)) => Ok(()),
+        Ok(Message::Err(m)) => Err(Failure::Server(format!("ERROR \"{}\"", m))),
+        Ok(other) => Err(Failure::Server(format!(
+            "ERROR \"Respuesta inesperada: {}\"",
+            other
+        ))),
+        Err(e) => Err(Failure::Server(format!("ERROR \"{}\"", e))),
+    }
 }
 
-/// Inicializa la conexión con el servidor y abre el archivo de operaciones.
+/// Archivo de entrada que representa a `stdin`.
+const STDIN: &str = "-";
+
+/// Expande los patrones de los archivos de entrada, en orden.
 ///
-/// Retorna un tuple `(TcpStream, File)` si tiene éxito, o `Err(String)` con
-/// un mensaje de error descriptivo.
+/// Un patrón admite `*` (cualquier secuencia) y `?` (un caracter) en el
+/// nombre del archivo, no en los directorios, y se expande a los archivos
+/// que coinciden, ordenados y sin los ocultos; como en `data/*.txt`, que
+/// cubre lo que el shell no expandió (por ejemplo, entre comillas). El
+/// resto de las entradas, incluido `-` (`stdin`), queda como está.
 ///
 /// # Errores
-/// - Si no se reciben los argumentos correctos.
-/// - Si no se puede conectar al servidor.
-/// - Si no se puede abrir el archivo.
-fn init_client() -> Result<(TcpStream, File), String> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        return Err("Se esperaba direccion y archivo como argumentos".to_string());
+/// Retorna `Err(String)` si un patrón no coincide con ningún archivo, su
+/// directorio no se puede leer o `-` aparece más de una vez.
+fn expand_inputs(inputs: &[String]) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if !input.contains(['*', '?']) {
+            files.push(input.clone());
+            continue;
+        }
+        let (dir, pattern) = match input.rsplit_once('/') {
+            Some(("", pattern)) => ("/", pattern),
+            Some((dir, pattern)) => (dir, pattern),
+            None => (".", input.as_str()),
+        };
+        if dir.contains(['*', '?']) {
+            return Err(format!(
+                "Solo se admiten comodines en el nombre del archivo: {}",
+                input
+            ));
+        }
+        let entries = fs::read_dir(dir).map_err(|e| format!("No se pudo leer {}: {}", dir, e))?;
+        let mut matches: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !name.starts_with('.') && wildcard_match(pattern, name))
+            .map(|name| match input.rsplit_once('/') {
+                Some((prefix, _)) => format!("{}/{}", prefix, name),
+                None => name,
+            })
+            .collect();
+        if matches.is_empty() {
+            return Err(format!("Ningun archivo coincide con {}", input));
+        }
+        matches.sort();
+        files.extend(matches);
+    }
+    if files.iter().filter(|f| *f == STDIN).count() > 1 {
+        return Err("- (stdin) solo puede indicarse una vez".to_string());
+    }
+    Ok(files)
+}
+
+/// Indica si `name` coincide con `pattern`, donde `*` es cualquier
+/// secuencia y `?` un caracter.
+///
+/// Ante un fallo vuelve al último `*`, extendiéndolo un caracter más; así
+/// el costo es a lo sumo proporcional al producto de las longitudes.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-    let stream = TcpStream::connect(&args[1]).map_err(|e| format!("No se pudo conectar: {}", e))?;
-    let file = File::open(&args[2]).map_err(|e| format!("No se pudo abrir el archivo: {}", e))?;
-    Ok((stream, file))
+/// Línea no vacía del archivo de operaciones, sin compilar.
+#[derive(Debug)]
+struct SourceLine {
+    /// Número de línea, desde 1.
+    line: usize,
+    input: String,
 }
 
-/// Lee cada línea del archivo y la envía al servidor como operación.
+/// Línea del archivo de operaciones ya compilada.
+#[derive(Debug)]
+struct CompiledLine {
+    /// Número de línea, desde 1.
+    line: usize,
+    input: String,
+    /// Operaciones de la línea, o el error de compilación.
+    ops: Result<Vec<Operation>, String>,
+}
+
+/// Lee las líneas no vacías del archivo, para compilarlas después (ver
+/// `compile`).
 ///
-/// Ignora líneas vacías.
+/// Si `path` es `STDIN` lee de `stdin`.
 ///
 /// # Errores
-/// Retorna `Err(String)` si ocurre un error al leer el archivo o al enviar
-/// la operación al servidor.
-fn read_file(file: File, stream: &mut TcpStream) -> Result<(), String> {
-    let reader = BufReader::new(file);
-    for line in reader.lines() {
+/// Retorna `Err(String)` si no se puede abrir o leer el archivo.
+fn read_file(path: &str) -> Result<Vec<SourceLine>, String> {
+    if path == STDIN {
+        return read_lines(io::stdin().lock());
+    }
+    let file =
+        File::open(path).map_err(|e| format!("No se pudo abrir el archivo {}: {}", path, e))?;
+    read_lines(BufReader::new(file))
+}
+
+/// Lee las líneas no vacías de `reader` (ver `read_file`).
+fn read_lines(reader: impl BufRead) -> Result<Vec<SourceLine>, String> {
+    let mut lines = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
         let line = line.map_err(|e| format!("Error leyendo archivo: {}", e))?;
-        let line = line.trim();
-        if line.is_empty() {
+        if line.trim().is_empty() {
             continue;
         }
-        send_operation(line, stream)?;
-        read_answer(stream)?;
+        lines.push(SourceLine {
+            line: i + 1,
+            input: line.trim().to_string(),
+        });
     }
+    Ok(lines)
+}
 
-    Ok(())
+/// Compila cada línea en operaciones.
+///
+/// Cada línea puede ser una operación (`+ 5`) o una expresión sobre `x`
+/// (`x = x * 7 + 1`), ver `expression::compile_line`; las constantes se
+/// calculan con `arithmetic`, que debe ser la del servidor que aplicará las
+/// operaciones. Las líneas que no compilan conservan el error, con su
+/// columna, para informarlo en el reporte; sus operaciones no se envían.
+fn compile(source: &[SourceLine], arithmetic: Arithmetic) -> Vec<CompiledLine> {
+    source
+        .iter()
+        .map(|l| CompiledLine {
+            line: l.line,
+            ops: compile_line(&l.input, arithmetic).map_err(|e| e.to_string()),
+            input: l.input.clone(),
+        })
+        .collect()
+}
+
+/// Agrupa las líneas en grupos de hasta `limit` operaciones, sin partir
+/// ninguna línea, para enviar cada grupo en un `LINES`: así el servidor
+/// aplica cada línea de una vez (una asignación `* 0`, `+ c` no se
+/// intercala con las operaciones de otros clientes) y entera (si una de sus
+/// operaciones falla, no aplica las demás). Una línea con más de `limit`
+/// operaciones va sola en su grupo, y el servidor la rechaza entera. Las
+/// líneas sin operaciones no se envían.
+fn pack_lines<'a>(
+    lines: impl IntoIterator<Item = &'a CompiledLine>,
+    limit: usize,
+) -> Vec<Vec<Vec<Operation>>> {
+    let mut groups: Vec<Vec<Vec<Operation>>> = Vec::new();
+    let mut size = 0;
+    for ops in lines.into_iter().filter_map(|l| l.ops.as_ref().ok()) {
+        match groups.last_mut() {
+            _ if ops.is_empty() => {}
+            Some(group) if size + ops.len() <= limit => {
+                group.push(ops.clone());
+                size += ops.len();
+            }
+            _ => {
+                groups.push(vec![ops.clone()]);
+                size = ops.len();
+            }
+        }
+    }
+    groups
+}
+
+/// Envía las operaciones de las líneas compiladas y retorna el resultado de
+/// cada línea (ver `line_results`).
+///
+/// Si el servidor soporta lotes (`batch`), las líneas se envían en `LINES`
+/// de hasta `batch` operaciones, con un solo ida y vuelta por lote (ver
+/// `pack_lines`); si no, se envía un `OP` por línea, o un `OPS` si la línea
+/// tiene varias operaciones, para que se apliquen juntas. Un servidor que
+/// no conoce `OPS` rechaza esa línea entera.
+///
+/// Un mensaje rechazado por la limitación de tasa se reenvía tras la espera
+/// que indicó el servidor (ver `rate_limit_wait`), hasta
+/// `RATE_LIMIT_RETRIES` veces.
+///
+/// # Errores
+/// Retorna `Err(String)` si falla la comunicación con el servidor.
+fn send_lines(
+    lines: &[CompiledLine],
+    stream: &mut (impl Read + Write),
+    batch: Option<usize>,
+) -> Result<Vec<LineResult>, String> {
+    let mut results = Vec::new();
+    let messages: Vec<Message> = match batch {
+        Some(size) => pack_lines(lines, size)
+            .into_iter()
+            .map(Message::Lines)
+            .collect(),
+        None => lines
+            .iter()
+            .filter_map(|l| l.ops.clone().ok())
+            .filter_map(|ops| match ops.as_slice() {
+                [] => None,
+                [op] => Some(Message::Op(*op, None)),
+                _ => Some(Message::Ops(ops)),
+            })
+            .collect(),
+    };
+    for message in messages {
+        let count = match &message {
+            Message::Lines(lines) => lines.iter().map(Vec::len).sum(),
+            Message::Ops(ops) => ops.len(),
+            _ => 1,
+        };
+        let answer = retry_rate_limited(
+            || {
+                stream
+                    .write_all(format!("{}\n", message).as_bytes())
+                    .map_err(|e| format!("Error enviando: {}", e))?;
+                read_answer(stream, count)
+            },
+            |answer| match answer.first() {
+                Some(Message::Err(motivo)) => Some(motivo),
+                _ => None,
+            },
+        )?;
+        results.extend(answer);
+    }
+    Ok(line_results(lines, results))
+}
+
+/// Reenvíos de un pedido rechazado por la limitación de tasa antes de
+/// aceptar el rechazo como respuesta.
+const RATE_LIMIT_RETRIES: u32 = 10;
+
+/// Hace el pedido `request` y, mientras la respuesta sea un
+/// `ERROR "rate limited; retry after <n> ms"` (según `rejection`, que
+/// retorna el motivo de un error), lo repite tras esperar lo indicado, hasta
+/// `RATE_LIMIT_RETRIES` veces. Retorna la última respuesta.
+///
+/// # Errores
+/// Retorna el `Err(String)` de `request` si falla la comunicación.
+fn retry_rate_limited<T>(
+    mut request: impl FnMut() -> Result<T, String>,
+    rejection: impl Fn(&T) -> Option<&String>,
+) -> Result<T, String> {
+    let mut retries = 0;
+    loop {
+        let answer = request()?;
+        match rejection(&answer).and_then(|motivo| rate_limit_wait(motivo)) {
+            Some(wait) if retries < RATE_LIMIT_RETRIES => thread::sleep(wait),
+            _ => return Ok(answer),
+        }
+        retries += 1;
+    }
+}
+
+/// Espera que indica el motivo `rate limited; retry after <n> ms`, o `None`
+/// si es otro motivo.
+fn rate_limit_wait(motivo: &str) -> Option<Duration> {
+    let ms = motivo
+        .strip_prefix("rate limited; retry after ")?
+        .strip_suffix(" ms")?;
+    ms.parse().ok().map(Duration::from_millis)
+}
+
+/// Valida las líneas compiladas sin contactar al servidor: cada línea que
+/// no compila (incluidas las que tienen un operando fuera del tipo numérico
+/// de `--numeric-type`) se informa con su error; el resto cuenta como
+/// válida.
+fn check(lines: &[CompiledLine]) -> Report {
+    Report {
+        lines: line_results(lines, Vec::new()),
+        value: None,
+    }
+}
+
+/// Simulación local del servidor, sin contactarlo, para `--dry-run`.
+///
+/// Parte de 0 con las reglas `arithmetic` (ver `Arithmetic::apply`), como
+/// un servidor recién iniciado con esa configuración, y conserva el valor
+/// entre archivos, como lo haría el servidor al recibirlos en orden.
+struct Simulation {
+    arithmetic: Arithmetic,
+    value: u64,
+    version: u64,
+}
+
+impl Simulation {
+    fn new(arithmetic: Arithmetic) -> Self {
+        Simulation {
+            arithmetic,
+            value: 0,
+            version: 0,
+        }
+    }
+
+    /// Aplica las líneas compiladas como el servidor aplica un `LINES`: cada
+    /// línea entera, o ninguna de sus operaciones si una falla. Las
+    /// versiones del reporte cuentan las operaciones aplicadas, como las
+    /// del servidor.
+    fn run(&mut self, lines: &[CompiledLine]) -> Report {
+        let mut results = Vec::new();
+        for ops in lines.iter().filter_map(|l| l.ops.as_ref().ok()) {
+            let applied = ops
+                .iter()
+                .try_fold(self.value, |value, op| self.arithmetic.apply(value, op));
+            match applied {
+                Ok(value) => {
+                    self.value = value;
+                    for _ in ops {
+                        self.version += 1;
+                        results.push(Message::Ok(Some(self.version)));
+                    }
+                }
+                Err(motivo) => results.extend(vec![Message::Err(motivo); ops.len()]),
+            }
+        }
+        Report {
+            lines: line_results(lines, results),
+            value: Some(self.value),
+        }
+    }
+}
+
+/// Reparte los resultados de las operaciones (uno por operación, en orden)
+/// entre las líneas que las generaron.
+///
+/// Una línea es exitosa si todas sus operaciones lo fueron, con la versión
+/// de la última; si no, lleva el motivo de su primer error. Sin resultados
+/// (como al enviar por UDP) las líneas que compilaron cuentan como
+/// exitosas.
+fn line_results(lines: &[CompiledLine], results: Vec<Message>) -> Vec<LineResult> {
+    let mut results = results.into_iter();
+    lines
+        .iter()
+        .map(|line| LineResult {
+            line: line.line,
+            input: line.input.clone(),
+            result: match &line.ops {
+                Err(e) => Err(e.clone()),
+                Ok(ops) => {
+                    let own: Vec<Message> = results.by_ref().take(ops.len()).collect();
+                    own.into_iter().try_fold(None, |_, result| match result {
+                        Message::Ok(version) => Ok(version),
+                        Message::Conflict(..) => Err("conflict".to_string()),
+                        Message::Err(m) => Err(m),
+                        other => Err(format!("Respuesta inesperada: {}", other)),
+                    })
+                }
+            },
+        })
+        .collect()
+}
+
+/// Intervalo entre reenvíos de un datagrama sin confirmar.
+const UDP_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Envíos de un datagrama antes de darlo por perdido (unos 5 segundos).
+const UDP_MAX_ATTEMPTS: u32 = 25;
+
+/// Abre un socket UDP conectado a `address`.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si la dirección es inválida o falla el socket.
+fn udp_connect(address: &str) -> Result<UdpSocket, Failure> {
+    let server = address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| Failure::Usage(format!("Direccion invalida: {}", address)))?;
+    let local = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    UdpSocket::bind(local)
+        .and_then(|socket| {
+            socket.connect(server)?;
+            socket.set_read_timeout(Some(UDP_RETRY_INTERVAL / 4))?;
+            Ok(socket)
+        })
+        .map_err(|e| Failure::Connection(format!("No se pudo abrir el socket UDP: {}", e)))
+}
+
+/// Indica si `e` es sólo la falta de respuesta (o un servidor que todavía
+/// no escucha), tras la que se reintenta.
+fn udp_retryable(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+    )
+}
+
+/// Operaciones por datagrama: `MAX_OPS_PER_DATAGRAM`, o el `burst=` del
+/// servidor si es menor, porque un datagrama con más operaciones que la
+/// capacidad de su token bucket se rechaza siempre.
+///
+/// Lo pregunta con un `HELLO` en la secuencia 0, que ningún datagrama de
+/// operaciones usa; el servidor lo responde sin registrar la secuencia. Se
+/// reenvía como un datagrama más, y si el servidor no conoce `HELLO` por UDP
+/// se usa `MAX_OPS_PER_DATAGRAM`.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si falla el socket, el servidor rechaza el
+/// `HELLO` (ver `hello_capabilities`) o no lo responde tras
+/// `UDP_MAX_ATTEMPTS` envíos.
+fn datagram_size(socket: &UdpSocket) -> Result<usize, Failure> {
+    let hello = datagram::encode(
+        0,
+        &Message::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+        },
+    );
+    let mut buf = [0; 1024];
+    for _ in 0..UDP_MAX_ATTEMPTS {
+        socket
+            .send(hello.as_bytes())
+            .map_err(|e| Failure::Connection(format!("Error enviando HELLO: {}", e)))?;
+        let sent = Instant::now();
+        while sent.elapsed() < UDP_RETRY_INTERVAL {
+            match socket.recv(&mut buf) {
+                Ok(len) => {
+                    let reply = String::from_utf8_lossy(&buf[..len]);
+                    if let Some((0, rest)) = datagram::split_seq(&reply) {
+                        let burst = burst(&hello_capabilities(rest)?)?;
+                        return Ok(
+                            burst.map_or(MAX_OPS_PER_DATAGRAM, |n| n.min(MAX_OPS_PER_DATAGRAM))
+                        );
+                    }
+                }
+                Err(e) if udp_retryable(&e) => {}
+                Err(e) => {
+                    return Err(Failure::Connection(format!(
+                        "Error leyendo respuesta: {}",
+                        e
+                    )));
+                }
+            }
+        }
+    }
+    Err(Failure::Connection(format!(
+        "El HELLO no se respondio tras {} intentos",
+        UDP_MAX_ATTEMPTS
+    )))
+}
+
+/// Envía cada grupo de líneas por UDP en un datagrama `LINES` (ver
+/// `pack_lines`, con grupos de hasta `datagram_size` operaciones), y
+/// espera a que el servidor confirme cada uno (ver `datagram`).
+///
+/// Los datagramas sin confirmar se reenvían cada `UDP_RETRY_INTERVAL`; el
+/// servidor descarta los repetidos, así cada operación se aplica una sola
+/// vez. La primera secuencia sale del reloj, para que un cliente que
+/// reutiliza el puerto de uno anterior no choque con sus secuencias. Los
+/// datagramas que el servidor rechaza y las operaciones que fallaron (las
+/// confirmaciones `RESULTS`) se informan por `stderr` y se retorna cuántos
+/// fueron.
+///
+/// # Errores
+/// Retorna `Err(Failure)` si falla el socket o algún datagrama no se
+/// confirma tras `UDP_MAX_ATTEMPTS` envíos.
+fn send_datagrams(socket: &UdpSocket, chunks: Vec<Vec<Vec<Operation>>>) -> Result<usize, Failure> {
+    let send = |text: &str| {
+        socket
+            .send(text.as_bytes())
+            .map_err(|e| Failure::Connection(format!("Error enviando: {}", e)))
+    };
+
+    let first_seq = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64);
+    let mut outbox = Outbox::new(first_seq, UDP_RETRY_INTERVAL, UDP_MAX_ATTEMPTS);
+    let mut chunks = chunks.into_iter();
+    let mut buf = [0; 1024];
+    let mut rejected = 0;
+    loop {
+        while outbox.has_room() {
+            let Some(chunk) = chunks.next() else {
+                break;
+            };
+            send(&outbox.push(chunk, Instant::now()))?;
+        }
+        if outbox.is_empty() {
+            return Ok(rejected);
+        }
+
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                let reply = String::from_utf8_lossy(&buf[..len]);
+                if let Some((seq, rest)) = datagram::split_seq(&reply)
+                    && outbox.ack(seq)
+                {
+                    let failures = match parse_message(rest) {
+                        Ok(Message::Err(m)) => vec![m],
+                        Ok(Message::Results(results)) => results
+                            .into_iter()
+                            .filter_map(|r| match r {
+                                Message::Err(m) => Some(m),
+                                _ => None,
+                            })
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    for m in &failures {
+                        eprintln!("ERROR \"datagrama {}: {}\"", seq, m);
+                    }
+                    rejected += failures.len();
+                }
+            }
+            Err(e) if udp_retryable(&e) => {}
+            Err(e) => {
+                return Err(Failure::Connection(format!(
+                    "Error leyendo respuesta: {}",
+                    e
+                )));
+            }
+        }
+        for text in outbox.due(Instant::now()).map_err(Failure::Connection)? {
+            send(&text)?;
+        }
+    }
 }
 
 /// Envía una operación al servidor.
@@ -77,68 +1156,302 @@ fn read_file(file: File, stream: &mut TcpStream) -> Result<(), String> {
 ///
 /// # Errores
 /// Retorna `Err(String)` si ocurre un error al enviar los datos.
-fn send_operation(s: &str, stream: &mut TcpStream) -> Result<(), String> {
+fn send_operation(s: &str, stream: &mut (impl Read + Write)) -> Result<(), String> {
     let payload = format!("OP {}\n", s);
     stream
         .write_all(payload.as_bytes())
         .map_err(|e| format!("Error enviando: {}", e))
 }
 
-/// Lee la respuesta inmediata del servidor tras una operación.
+/// Lee la respuesta del servidor a `count` operaciones enviadas con `OP` o
+/// en un lote, y retorna un resultado por operación.
 ///
-/// - Si la respuesta es `OK`, no hace nada.
-/// - Si la respuesta es `ERROR`, lo muestra por `stderr`.
-/// - Si la respuesta es inesperada o no se puede parsear, también lo muestra por `stderr`.
+/// Un `ERROR` que rechaza el lote completo (por ejemplo, por la limitación
+/// de tasa) cuenta como el resultado de cada una de sus operaciones, igual
+/// que una respuesta inesperada.
 ///
 /// # Errores
-/// Retorna `Err(String)` solo si ocurre un error de E/S al leer la respuesta.
-/// Los errores reportados por el servidor **no interrumpen la ejecución**
-/// y se imprimen por `stderr`.
-fn read_answer(stream: &mut TcpStream) -> Result<(), String> {
-    let mut resp = String::new();
-    let mut buff = BufReader::new(stream);
-    buff.read_line(&mut resp)
-        .map_err(|e| format!("Error leyendo respuesta: {}", e))?;
+/// Retorna `Err(String)` solo si falla la comunicación. Los errores
+/// reportados por el servidor **no interrumpen la ejecución**.
+fn read_answer(stream: &mut (impl Read + Write), count: usize) -> Result<Vec<Message>, String> {
+    Ok(match parse_message(&read_response(stream)?) {
+        Ok(Message::Results(results)) if results.len() == count => results,
+        Ok(ok @ Message::Ok(_)) if count == 1 => vec![ok],
+        Ok(error @ Message::Err(_)) => vec![error; count],
+        Ok(other) => vec![Message::Err(format!("Respuesta inesperada: {}", other)); count],
+        Err(e) => vec![Message::Err(e); count],
+    })
+}
+
+/// Fuente de las líneas del modo interactivo.
+trait LineInput {
+    /// Muestra `prompt` en `output` y lee la próxima línea; `None` al
+    /// terminar la entrada. `history` son los comandos anteriores.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si falla la lectura o la escritura.
+    fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &[String],
+        output: &mut impl Write,
+    ) -> Result<Option<String>, String>;
+}
+
+/// Entrada sin edición, línea por línea, como cuando `stdin` no es una
+/// terminal.
+struct PlainInput<R>(R);
+
+impl<R: BufRead> LineInput for PlainInput<R> {
+    fn read_line(
+        &mut self,
+        prompt: &str,
+        _history: &[String],
+        output: &mut impl Write,
+    ) -> Result<Option<String>, String> {
+        write!(output, "{}", prompt)
+            .and_then(|_| output.flush())
+            .map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        match self.0.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line)),
+            Err(e) => Err(format!("Error leyendo entrada: {}", e)),
+        }
+    }
+}
+
+/// Entrada desde la terminal con edición de línea (ver
+/// `line_editor::LineEditor`): flechas, Inicio, Fin, los atajos de Emacs y
+/// el historial con ↑ y ↓. Mientras existe, la terminal queda en modo
+/// crudo.
+#[cfg(unix)]
+struct TerminalInput {
+    _raw: RawMode,
+    stdin: io::Stdin,
+}
 
-    match parse_message(resp.trim_end()) {
-        Ok(Message::Ok) => { /* no hacer nada, todo bien */ }
-        Ok(Message::Err(m)) => eprintln!("ERROR \"{}\"", m),
-        Ok(other) => eprintln!("ERROR \"Respuesta inesperada: {}\"", other),
-        Err(e) => eprintln!("ERROR \"{}\"", e),
+#[cfg(unix)]
+impl LineInput for TerminalInput {
+    fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &[String],
+        output: &mut impl Write,
+    ) -> Result<Option<String>, String> {
+        let mut editor = LineEditor::new();
+        let mut stdin = self.stdin.lock();
+        let mut draw = |text: &str| {
+            write!(output, "{}", text)
+                .and_then(|_| output.flush())
+                .map_err(|e| e.to_string())
+        };
+        draw(&editor.render(prompt))?;
+        loop {
+            let key = line_editor::read_key(&mut stdin)
+                .map_err(|e| format!("Error leyendo entrada: {}", e))?;
+            let Some(key) = key else {
+                return Ok(None);
+            };
+            match editor.apply(key, history) {
+                Edit::Pending => draw(&editor.render(prompt))?,
+                Edit::Submit(line) => {
+                    draw("\n")?;
+                    return Ok(Some(line));
+                }
+                Edit::Cancel => draw(&format!("^C\n{}", editor.render(prompt)))?,
+                Edit::Eof => return Ok(None),
+            }
+        }
     }
+}
 
-    Ok(())
+/// Ejecuta el modo interactivo leyendo de `stdin`: con edición de línea si
+/// es una terminal, o línea por línea si no (ver `run_repl`).
+///
+/// # Errores
+/// Retorna `Err(String)` si falla la comunicación con el servidor o la
+/// terminal.
+fn run_stdin_repl(stream: &mut (impl Read + Write), arithmetic: Arithmetic) -> Result<(), String> {
+    #[cfg(unix)]
+    if io::stdin().is_terminal()
+        && let Ok(raw) = RawMode::enable()
+    {
+        let mut input = TerminalInput {
+            _raw: raw,
+            stdin: io::stdin(),
+        };
+        return run_repl(&mut input, &mut io::stdout(), stream, arithmetic);
+    }
+    let mut input = PlainInput(io::stdin().lock());
+    run_repl(&mut input, &mut io::stdout(), stream, arithmetic)
 }
 
-/// Solicita el valor final al servidor y lo imprime.
+/// Ejecuta el modo interactivo.
+///
+/// Lee comandos de `input` (ver `repl::HELP`), los envía al servidor y
+/// escribe los resultados en `output`. Los errores del servidor y los
+/// comandos inválidos se muestran en línea sin terminar la sesión.
+///
+/// El prompt muestra el último valor conocido del servidor, sin consultarlo
+/// después de cada operación: si el `OK` trae la versión siguiente a la
+/// del valor conocido, nadie más lo cambió y el nuevo valor se calcula con
+/// `arithmetic`, la del servidor. Si no (otro cliente lo cambió, o el
+/// servidor no informa versiones), el prompt muestra `?` hasta el próximo
+/// `get`.
 ///
 /// # Errores
-/// Retorna `Err(String)` si ocurre un error al enviar el mensaje GET o
-/// al leer la respuesta.
-fn get_final_value(stream: &mut TcpStream) -> Result<(), String> {
+/// Retorna `Err(String)` si falla la comunicación con el servidor o la
+/// escritura en `output`.
+fn run_repl<W: Write>(
+    input: &mut impl LineInput,
+    output: &mut W,
+    stream: &mut (impl Read + Write),
+    arithmetic: Arithmetic,
+) -> Result<(), String> {
+    let mut history: Vec<String> = Vec::new();
+    // Último valor conocido y su versión.
+    let mut known = query_value(stream)?.ok();
+    say(
+        output,
+        "Modo interactivo. Escriba 'help' para ver los comandos.",
+    )?;
+
+    loop {
+        let prompt = repl::prompt(known.map(|(value, _)| value));
+        let Some(line) = input.read_line(&prompt, &history, output)? else {
+            say(output, "")?;
+            break;
+        };
+        let mut entry = line.trim().to_string();
+
+        let mut command = repl::parse_command(&entry);
+        if let Ok(Command::Recall(n)) = command {
+            let recalled = match n {
+                None => history.last(),
+                Some(n) => history.get(n - 1),
+            };
+            match recalled {
+                Some(previous) => {
+                    entry = previous.clone();
+                    say(output, &entry)?;
+                    command = repl::parse_command(&entry);
+                }
+                None => command = Err("No existe esa entrada en el historial".to_string()),
+            }
+        }
+
+        match command {
+            Ok(Command::Empty) | Ok(Command::Recall(_)) => {}
+            Ok(Command::Quit) => break,
+            Ok(Command::Help) => say(output, repl::HELP)?,
+            Ok(Command::History) => {
+                for (i, h) in history.iter().enumerate() {
+                    say(output, &format!("{:>4}  {}", i + 1, h))?;
+                }
+            }
+            Ok(Command::Get) => {
+                history.push(entry);
+                match query_value(stream)? {
+                    Ok((value, version)) => {
+                        known = Some((value, version));
+                        say(output, &Message::Value(value, None).to_string())?;
+                    }
+                    Err(e) => say(output, &format!("ERROR \"{}\"", e))?,
+                }
+            }
+            Ok(Command::Op(op)) => {
+                history.push(entry.clone());
+                send_operation(&op.to_string(), stream)?;
+                match parse_message(&read_response(stream)?) {
+                    Ok(Message::Ok(version)) => {
+                        known = match (known, version) {
+                            (Some((value, Some(last))), Some(version))
+                                if last.checked_add(1) == Some(version) =>
+                            {
+                                arithmetic
+                                    .apply(value, &op)
+                                    .ok()
+                                    .map(|value| (value, Some(version)))
+                            }
+                            _ => None,
+                        };
+                    }
+                    Ok(Message::Err(m)) => say(output, &format!("ERROR \"{}\"", m))?,
+                    Ok(other) => say(
+                        output,
+                        &format!("ERROR \"Respuesta inesperada: {}\"", other),
+                    )?,
+                    Err(e) => say(output, &format!("ERROR \"{}\"", e))?,
+                }
+            }
+            Err(e) => say(output, &format!("ERROR \"{}\"", e))?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Escribe una línea en la salida del modo interactivo.
+fn say<W: Write>(output: &mut W, text: &str) -> Result<(), String> {
+    writeln!(output, "{}", text).map_err(|e| e.to_string())
+}
+
+/// Envía `GET` y retorna el valor informado por el servidor, con su
+/// versión si la informó.
+///
+/// # Retorno
+/// - `Ok(Ok((valor, versión)))` si el servidor respondió `VALUE`.
+/// - `Ok(Err(motivo))` si respondió un error o algo inesperado.
+/// - `Err(String)` si falló la comunicación.
+fn query_value(
+    stream: &mut (impl Read + Write),
+) -> Result<Result<(u64, Option<u64>), String>, String> {
     stream
         .write_all(b"GET\n")
         .map_err(|e| format!("Error enviando GET: {}", e))?;
+    Ok(match parse_message(&read_response(stream)?) {
+        Ok(Message::Value(value, version)) => Ok((value, version)),
+        Ok(Message::Err(m)) => Err(m),
+        Ok(other) => Err(format!("Respuesta inesperada: {}", other)),
+        Err(e) => Err(e),
+    })
+}
 
-    let mut reader = BufReader::new(stream);
+/// Lee una línea de respuesta del servidor, sin el salto de línea.
+///
+/// # Errores
+/// Retorna `Err(String)` si falla la lectura o el servidor cerró la conexión.
+fn read_response(stream: &mut (impl Read + Write)) -> Result<String, String> {
     let mut resp = String::new();
-    reader
+    let n = BufReader::new(stream)
         .read_line(&mut resp)
-        .map_err(|e| format!("Error leyendo VALUE: {}", e))?;
-
-    match parse_message(resp.trim_end()) {
-        Ok(Message::Value(v)) => println!("{}", v),
-        Ok(Message::Err(m)) => eprintln!("ERROR \"{}\"", m),
-        Ok(other) => eprintln!("ERROR \"Respuesta inesperada: {}\"", other),
-        Err(e) => eprintln!("ERROR \"{}\"", e),
+        .map_err(|e| format!("Error leyendo respuesta: {}", e))?;
+    if n == 0 {
+        return Err("El servidor cerro la conexion".to_string());
     }
+    Ok(resp.trim_end().to_string())
+}
 
-    Ok(())
+/// Solicita el valor final al servidor, repitiendo el `GET` si lo rechaza
+/// la limitación de tasa (ver `retry_rate_limited`).
+///
+/// # Errores
+/// Retorna `Err(Failure)` si falla la comunicación o el servidor no
+/// responde con el valor.
+fn get_final_value(stream: &mut (impl Read + Write)) -> Result<u64, Failure> {
+    let answer = retry_rate_limited(|| query_value(stream), |answer| answer.as_ref().err());
+    match answer.map_err(Failure::Connection)? {
+        Ok((value, _)) => Ok(value),
+        Err(e) => Err(Failure::Server(format!("ERROR \"{}\"", e))),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use calculadora_distribuida::calculator::{NumericType, OverflowPolicy};
+    use calculadora_distribuida::operator::Operator;
     use std::io::{BufRead, BufReader, Write};
     use std::net::{TcpListener, TcpStream};
     use std::thread;
@@ -166,20 +1479,595 @@ mod tests {
 
         let mut stream = TcpStream::connect(addr).unwrap();
         send_operation("+ 1", &mut stream).unwrap();
-        read_answer(&mut stream).unwrap();
+        assert_eq!(read_answer(&mut stream, 1).unwrap(), [Message::Ok(None)]);
+    }
+
+    #[test]
+    fn test_read_answer_rejected_batch() {
+        let addr = start_mock_server("ERROR \"rate limited; retry after 5 ms\"\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        send_operation("+ 1", &mut stream).unwrap();
+        let results = read_answer(&mut stream, 2).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(&results[1], Message::Err(m) if m.starts_with("rate limited")));
     }
 
     #[test]
     fn test_get_final_value_value() {
         let addr = start_mock_server("VALUE 42\n");
         let mut stream = TcpStream::connect(addr).unwrap();
-        get_final_value(&mut stream).unwrap();
+        assert_eq!(get_final_value(&mut stream), Ok(42));
     }
 
     #[test]
     fn test_get_final_value_error() {
         let addr = start_mock_server("ERROR \"Operacion invalida\"\n");
         let mut stream = TcpStream::connect(addr).unwrap();
-        get_final_value(&mut stream).unwrap();
+        let err = get_final_value(&mut stream).unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_SERVER);
+    }
+
+    #[test]
+    fn test_closed_connection_exit_code() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || drop(listener.accept()));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let err = handshake(&mut stream).unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_CONNECTION);
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let addr = start_mock_server("OK\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        authenticate("ana", "secreto", &mut stream).unwrap();
+
+        let addr = start_mock_server("ERROR \"authentication failed\"\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert!(authenticate("ana", "mal", &mut stream).is_err());
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        assert_eq!(
+            parse_credentials("ana:secreto").unwrap(),
+            ("ana".to_string(), "secreto".to_string())
+        );
+        assert!(parse_credentials("ana").is_err());
+        assert!(parse_credentials(":x").is_err());
+    }
+
+    /// Levanta un servidor TCP que responde cada línea recibida con la
+    /// siguiente respuesta de `responses`
+    fn start_scripted_server(responses: &'static [&'static str]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                for response in responses {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 {
+                        break;
+                    }
+                    let _ = stream.write_all(format!("{}\n", response).as_bytes());
+                }
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_repl_session() {
+        // GET inicial, + 5 (OK), / 0 (ERROR), get, !1 (OK), * 2 (OK) y
+        // ningún GET más: si se enviara, el servidor ya cerró.
+        let addr = start_scripted_server(&[
+            "VALUE 0 @0",
+            "OK @1",
+            "ERROR \"division by zero\"",
+            "VALUE 7 @2",
+            "OK @3",
+            "OK @5",
+        ]);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let input = "+ 5\n/ 0\nget\n!1\nfoo\nhistory\n* 2\nquit\n";
+        let mut output = Vec::new();
+        let mut input = PlainInput(input.as_bytes());
+        run_repl(&mut input, &mut output, &mut stream, Arithmetic::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("[0]> "));
+        // el valor sale del OK @1, sin consultarlo
+        assert!(output.contains("[5]> ERROR \"division by zero\""));
+        assert!(output.contains("VALUE 7"));
+        assert!(output.contains("[12]> ERROR"));
+        assert!(output.contains("   4  + 5"));
+        // OK @5 después de @3: otro cliente lo cambió
+        assert!(output.ends_with("[?]> "));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let to_args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let args = parse_args(&to_args(&["127.0.0.1:1", "ops.txt"])).unwrap();
+        assert_eq!(args.files, ["ops.txt"]);
+
+        let args = parse_args(&to_args(&["127.0.0.1:1", "--auth", "ana:x"])).unwrap();
+        assert!(args.files.is_empty());
+        assert_eq!(args.credentials, Some(("ana".to_string(), "x".to_string())));
+
+        assert!(parse_args(&[]).is_err());
+
+        let args = parse_args(&to_args(&["a", "f", "-", "--parallel", "g*.txt"])).unwrap();
+        assert_eq!(args.files, ["f", "-", "g*.txt"]);
+        assert!(args.parallel);
+        assert!(parse_args(&to_args(&["a", "--parallel"])).is_err());
+        assert!(parse_args(&to_args(&["a", "f", "--udp", "--parallel"])).is_err());
+
+        let args = parse_args(&to_args(&["127.0.0.1:1", "ops.txt", "--udp"])).unwrap();
+        assert_eq!(args.mode, Mode::Udp);
+        assert!(parse_args(&to_args(&["a", "f", "--udp", "--auth", "ana:x"])).is_err());
+
+        let args = parse_args(&to_args(&["a", "f", "--format", "csv"])).unwrap();
+        assert_eq!(args.format, Some(Format::Csv));
+        assert!(parse_args(&to_args(&["a", "f", "--format", "xml"])).is_err());
+        assert!(parse_args(&to_args(&["a", "--format", "json"])).is_err());
+        assert!(parse_args(&to_args(&["a", "f", "--udp", "--format", "json"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_local_modes() {
+        let to_args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let args = parse_args(&to_args(&["ops.txt", "--check", "b.txt"])).unwrap();
+        assert_eq!((args.mode, args.address), (Mode::Check, None));
+        assert_eq!(args.files, ["ops.txt", "b.txt"]);
+
+        let args = parse_args(&to_args(&["--dry-run", "f", "--numeric-type", "u32"])).unwrap();
+        assert_eq!(args.mode, Mode::DryRun);
+        assert_eq!(args.arithmetic.numeric_type, NumericType::U32);
+
+        assert!(parse_args(&to_args(&["--check"])).is_err());
+        assert!(parse_args(&to_args(&["f", "--check", "--dry-run"])).is_err());
+        assert!(parse_args(&to_args(&["a", "f", "--overflow", "error"])).is_err());
+        let args = parse_args(&to_args(&["a", "f", "--udp", "--overflow", "error"])).unwrap();
+        assert_eq!(args.arithmetic.overflow, OverflowPolicy::Error);
+        assert!(parse_args(&to_args(&["f", "--check", "--auth", "ana:x"])).is_err());
+    }
+
+    #[test]
+    fn test_check_and_dry_run() {
+        let lines = compile_text("calc_dry", "+ 200\nx = x * 2\n/ 0\nx +\n- 1\n");
+
+        let report = check(&lines);
+        assert_eq!(report.errors(), 1);
+        assert_eq!(report.lines[3].line, 4);
+        assert_eq!(report.value, None);
+
+        // u8 wrapping por defecto: 200 * 2 = 144
+        let mut simulation = Simulation::new(Arithmetic::default());
+        let report = simulation.run(&lines);
+        assert_eq!(report.value, Some(143));
+        assert_eq!(report.lines[1].result, Ok(Some(2)));
+        assert_eq!(report.lines[2].result, Err("division by zero".to_string()));
+        assert_eq!(report.lines[4].result, Ok(Some(3)));
+
+        // El valor y las versiones siguen en el archivo siguiente.
+        let report = simulation.run(&lines[4..]);
+        assert_eq!(report.value, Some(142));
+        assert_eq!(report.lines[0].result, Ok(Some(4)));
+
+        let arithmetic = Arithmetic {
+            numeric_type: NumericType::U8,
+            overflow: OverflowPolicy::Error,
+        };
+        let report = Simulation::new(arithmetic).run(&lines);
+        assert_eq!(report.lines[1].result, Err("overflow".to_string()));
+        assert_eq!(report.value, Some(199));
+    }
+
+    #[test]
+    fn test_dry_run_applies_each_line_whole() {
+        let lines = compile_text("calc_whole", "+ 100\n(x + 5) / 0\nx = x * 3 + 1\n+ 1\n");
+        let arithmetic = Arithmetic {
+            numeric_type: NumericType::U8,
+            overflow: OverflowPolicy::Error,
+        };
+        let report = Simulation::new(arithmetic).run(&lines);
+        // el + 5 y el * 3 no quedan aplicados: sus líneas fallan enteras
+        assert_eq!(report.lines[1].result, Err("division by zero".to_string()));
+        assert_eq!(report.lines[2].result, Err("overflow".to_string()));
+        assert_eq!(report.lines[3].result, Ok(Some(2)));
+        assert_eq!(report.value, Some(101));
+    }
+
+    #[test]
+    fn test_check_operand_range() {
+        let source = source_text("calc_range", "+ 300\n+ 255\n");
+
+        // Con u8 por defecto, 300 no entra: el servidor lo rechazaría.
+        let report = check(&compile(&source, Arithmetic::default()));
+        assert_eq!(report.errors(), 1);
+        assert_eq!(
+            report.lines[0].result,
+            Err("columna 3: 300 no entra en u8".to_string())
+        );
+        assert_eq!(report.lines[1].result, Ok(None));
+
+        let arithmetic = Arithmetic {
+            numeric_type: NumericType::U16,
+            overflow: OverflowPolicy::Wrapping,
+        };
+        assert_eq!(check(&compile(&source, arithmetic)).errors(), 0);
+    }
+
+    #[test]
+    fn test_send_datagrams_retransmits() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut buf = [0; 2048];
+            let mut received = Vec::new();
+            // Ignora el primer envío de cada datagrama y confirma los
+            // siguientes; el primero con dos operaciones fallidas y el
+            // último rechazado.
+            while received.len() < 4 {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                let text = String::from_utf8_lossy(&buf[..len]).to_string();
+                let (seq, rest) = datagram::split_seq(&text).unwrap();
+                if received.iter().any(|r| r == &text) {
+                    let reply = match (rest.contains("/ 0"), rest.contains("* 0")) {
+                        (true, _) => Message::Err("parsing error".to_string()),
+                        (_, true) => Message::Results(vec![
+                            Message::Ok(Some(1)),
+                            Message::Err("overflow".to_string()),
+                            Message::Err("overflow".to_string()),
+                        ]),
+                        _ => Message::Ok(None),
+                    };
+                    socket
+                        .send_to(datagram::encode(seq, &reply).as_bytes(), from)
+                        .unwrap();
+                }
+                received.push(text);
+            }
+            received
+        });
+
+        let mut ops = vec![
+            Operation {
+                op: Operator::Add,
+                operand: 1,
+            };
+            MAX_OPS_PER_DATAGRAM + 1
+        ];
+        ops[0].op = Operator::Mul;
+        ops[0].operand = 0;
+        ops[MAX_OPS_PER_DATAGRAM].op = Operator::Div;
+        ops[MAX_OPS_PER_DATAGRAM].operand = 0;
+        let chunks = ops
+            .chunks(MAX_OPS_PER_DATAGRAM)
+            .map(|chunk| chunk.iter().map(|op| vec![*op]).collect());
+        let client = udp_connect(&addr).unwrap();
+        assert_eq!(send_datagrams(&client, chunks.collect()), Ok(3));
+
+        let received = server.join().unwrap();
+        let seqs: Vec<u64> = received
+            .iter()
+            .map(|r| datagram::split_seq(r).unwrap().0)
+            .collect();
+        assert_eq!(seqs.len(), 4);
+        assert_eq!(seqs[0] + 1, seqs[1]);
+        assert!(received.iter().any(|r| r.ends_with("LINES / 0")));
+    }
+
+    #[test]
+    fn test_datagram_size() {
+        let size = |reply: &'static str| {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap().to_string();
+            let server = thread::spawn(move || {
+                let mut buf = [0; 1024];
+                // Ignora el primer HELLO y responde el reenvío.
+                socket.recv_from(&mut buf).unwrap();
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                socket.send_to(reply.as_bytes(), from).unwrap();
+                String::from_utf8_lossy(&buf[..len]).to_string()
+            });
+            let size = datagram_size(&udp_connect(&addr).unwrap());
+            assert_eq!(server.join().unwrap(), "0 HELLO 1");
+            size
+        };
+        assert_eq!(size("0 HELLO 1 burst=20 numeric=u8"), Ok(20));
+        assert_eq!(size("0 HELLO 1 numeric=u8"), Ok(MAX_OPS_PER_DATAGRAM));
+        // un servidor que no conoce HELLO por UDP
+        assert_eq!(
+            size("0 ERROR \"unexpected message\""),
+            Ok(MAX_OPS_PER_DATAGRAM)
+        );
+    }
+
+    /// Escribe `text` en un archivo temporal, lo lee y lo borra.
+    fn source_text(name: &str, text: &str) -> Vec<SourceLine> {
+        let path = std::env::temp_dir().join(format!("{}_{}.txt", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let lines = read_file(&path.to_string_lossy()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        lines
+    }
+
+    /// Como `source_text`, compilando con la aritmética por defecto.
+    fn compile_text(name: &str, text: &str) -> Vec<CompiledLine> {
+        compile(&source_text(name, text), Arithmetic::default())
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.txt", "ops.txt"));
+        assert!(wildcard_match("o?s*", "ops.txt"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("*.txt", "ops.txt.bak"));
+        assert!(!wildcard_match("?", ""));
+        assert!(!wildcard_match("a*b", "ac"));
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let dir = std::env::temp_dir().join(format!("calc_inputs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.txt", "a.txt", "c.log", ".oculto.txt"] {
+            std::fs::write(dir.join(name), "+ 1\n").unwrap();
+        }
+        let dir_str = dir.to_string_lossy();
+        let expand = |inputs: &[&str]| {
+            let inputs: Vec<String> = inputs.iter().map(|s| s.to_string()).collect();
+            expand_inputs(&inputs)
+        };
+
+        let files = expand(&["-", &format!("{}/*.txt", dir_str), "x"]).unwrap();
+        let a = format!("{}/a.txt", dir_str);
+        let b = format!("{}/b.txt", dir_str);
+        assert_eq!(files, ["-", a.as_str(), b.as_str(), "x"]);
+        assert!(expand(&[&format!("{}/*.csv", dir_str)]).is_err());
+        assert!(expand(&["-", "-"]).is_err());
+        assert!(expand(&["*/a.txt"]).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_send_parallel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        // Atiende cada conexión en su propio hilo, como el servidor.
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let reader = BufReader::new(stream.try_clone().unwrap());
+                    for (i, line) in reader.lines().enumerate() {
+                        let response = match line.unwrap().split(' ').next() {
+                            Some("HELLO") => "HELLO 1 versions".to_string(),
+                            Some("OP") => format!("OK @{}", i),
+                            _ => "VALUE 9".to_string(),
+                        };
+                        stream
+                            .write_all(format!("{}\n", response).as_bytes())
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        let inputs = [
+            (source_text("calc_par_a", "+ 1\n+ 2\n"), "a".to_string()),
+            (source_text("calc_par_b", "* 3\nx +\n"), "b".to_string()),
+        ];
+
+        let reports = send_parallel(&addr, None, &inputs).unwrap();
+        assert_eq!(reports[0].file, "a");
+        assert_eq!(reports[0].report.lines[1].result, Ok(Some(2)));
+        assert_eq!(reports[1].report.errors(), 1);
+        assert_eq!(reports[1].report.value, Some(9));
+    }
+
+    #[test]
+    fn test_send_lines_compiles_expressions() {
+        let lines = compile_text("calc_expr", "x = (x + 3) * 2\n\n10 - x\n");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let lines: Vec<String> = reader.lines().take(1).map(|l| l.unwrap()).collect();
+            stream.write_all(b"RESULTS OK @1; OK @2\n").unwrap();
+            lines
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        let results = send_lines(&lines, &mut stream, Some(MAX_BATCH_SIZE)).unwrap();
+        // una sola ida y vuelta; la línea con error no se envía
+        assert_eq!(server.join().unwrap(), ["LINES + 3, * 2"]);
+        assert_eq!(results[0].result, Ok(Some(2)));
+        assert_eq!(results[1].line, 3);
+        assert!(results[1].result.is_err());
+    }
+
+    #[test]
+    fn test_send_lines_without_batch() {
+        let lines = compile_text("calc_nobatch", "+ 1\n* 2\n/ 0\n");
+        let addr = start_scripted_server(&["OK", "OK", "ERROR \"division by zero\""]);
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        let results = send_lines(&lines, &mut stream, None).unwrap();
+        assert_eq!(results[1].result, Ok(None));
+        assert_eq!(results[2].result, Err("division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_send_lines_sends_each_line_at_once() {
+        let lines = compile_text("calc_ops", "x = 5\n+ 1\n");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            for line in reader.lines().take(2) {
+                let line = line.unwrap();
+                let reply = match line.starts_with("OPS") {
+                    true => "RESULTS OK @1; OK @2\n",
+                    false => "OK @3\n",
+                };
+                stream.write_all(reply.as_bytes()).unwrap();
+                received.push(line);
+            }
+            received
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        // sin lotes, la asignación va en un solo OPS
+        let results = send_lines(&lines, &mut stream, None).unwrap();
+        assert_eq!(server.join().unwrap(), ["OPS * 0, + 5", "OP + 1"]);
+        assert_eq!(results[0].result, Ok(Some(2)));
+        assert_eq!(results[1].result, Ok(Some(3)));
+    }
+
+    #[test]
+    fn test_send_lines_retries_when_rate_limited() {
+        let lines = compile_text("calc_retry", "+ 1\n+ 2\n");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let replies = [
+                "OK @1\n",
+                "ERROR \"rate limited; retry after 20 ms\"\n",
+                "OK @2\n",
+            ];
+            let mut received = Vec::new();
+            for (reply, line) in replies.into_iter().zip(reader.lines()) {
+                stream.write_all(reply.as_bytes()).unwrap();
+                received.push(line.unwrap());
+            }
+            received
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        let start = Instant::now();
+        let results = send_lines(&lines, &mut stream, None).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(server.join().unwrap(), ["OP + 1", "OP + 2", "OP + 2"]);
+        assert_eq!(results[1].result, Ok(Some(2)));
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let capabilities = |list: &[&str]| list.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(batch_size(&capabilities(&["cas"])), Ok(None));
+        assert_eq!(
+            batch_size(&capabilities(&["batch"])),
+            Ok(Some(MAX_BATCH_SIZE))
+        );
+        assert_eq!(
+            batch_size(&capabilities(&["batch", "burst=20"])),
+            Ok(Some(20))
+        );
+        assert!(batch_size(&capabilities(&["batch", "burst=0"])).is_err());
+
+        // los lotes entran en la capacidad del bucket
+        let lines = compile_text("calc_burst", &"+ 1\n".repeat(45));
+        let sizes: Vec<usize> = pack_lines(&lines, 20)
+            .iter()
+            .map(|g| g.iter().map(Vec::len).sum::<usize>())
+            .collect();
+        assert_eq!(sizes, [20, 20, 5]);
+    }
+
+    #[test]
+    fn test_pack_lines_keeps_lines_whole() {
+        let lines = compile_text("calc_pack", "+ 1\nx = 3\nx = x * 2 + 1\n/ 0\nx +\n");
+        let groups = pack_lines(&lines, 3);
+        assert_eq!(
+            groups
+                .iter()
+                .map(|g| g.iter().map(Vec::len).sum::<usize>())
+                .collect::<Vec<_>>(),
+            [3, 3]
+        );
+        // cada línea queda separada, aunque comparta el grupo
+        assert_eq!(groups[0].iter().map(Vec::len).collect::<Vec<_>>(), [1, 2]);
+        let sizes: Vec<usize> = pack_lines(&lines, 1)
+            .iter()
+            .map(|g| g.iter().map(Vec::len).sum::<usize>())
+            .collect();
+        assert_eq!(sizes, [1, 2, 2, 1]);
+    }
+
+    #[test]
+    fn test_server_arithmetic() {
+        let capabilities = ["batch", "numeric=u16", "overflow=error"].map(String::from);
+        let arithmetic = server_arithmetic(&capabilities).unwrap();
+        assert_eq!(arithmetic.numeric_type, NumericType::U16);
+        assert_eq!(arithmetic.overflow, OverflowPolicy::Error);
+        assert_eq!(server_arithmetic(&[]), Ok(Arithmetic::default()));
+        assert!(server_arithmetic(&["numeric=u128".to_string()]).is_err());
+
+        // las constantes se calculan como en el servidor
+        let lines = compile(&source_text("calc_arith", "x = 200 + 100\n"), arithmetic);
+        assert_eq!(lines[0].ops.as_ref().unwrap()[1].operand, 300);
+    }
+
+    #[test]
+    fn test_line_results() {
+        let lines = compile_text("calc_lines", "x = x * 2 + 1\n- 1\n");
+        let results = line_results(
+            &lines,
+            vec![
+                Message::Err("overflow".to_string()),
+                Message::Ok(Some(1)),
+                Message::Ok(Some(2)),
+            ],
+        );
+        // el error de la primera operación no corre los resultados de la
+        // línea siguiente
+        assert_eq!(results[0].result, Err("overflow".to_string()));
+        assert_eq!(results[1].result, Ok(Some(2)));
+    }
+
+    #[test]
+    fn test_handshake() {
+        let addr = start_mock_server("HELLO 1 versions batch auth\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let capabilities = handshake(&mut stream).unwrap();
+        assert!(capabilities.contains(&"batch".to_string()));
+
+        // servidor anterior al handshake
+        let addr = start_mock_server("ERROR \"parsing error\"\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert!(handshake(&mut stream).unwrap().is_empty());
+
+        // otro error no se confunde con un servidor anterior
+        let addr = start_mock_server("ERROR \"server busy\"\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let err = handshake(&mut stream).unwrap_err();
+        assert_eq!(err, Failure::Server("ERROR \"server busy\"".to_string()));
+
+        let addr = start_mock_server("HELLO 2 batch\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let err = handshake(&mut stream).unwrap_err();
+        assert!(err.to_string().contains("version 2"), "{}", err);
+        assert_eq!(err.exit_code(), EXIT_SERVER);
+
+        let addr = start_mock_server(
+            "ERROR \"unsupported protocol version 1; server speaks version 2\"\n",
+        );
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert!(handshake(&mut stream).is_err());
     }
 }
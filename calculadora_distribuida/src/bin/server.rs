@@ -1,238 +1,3334 @@
+use std::collections::BTreeMap;
 use std::env;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use calculadora_distribuida::calculator;
-use calculadora_distribuida::protocol::{Message, Operation, parse_message};
+use calculadora_distribuida::admin::{self, ClientInfo, Command, Stats};
+use calculadora_distribuida::auth::Users;
+use calculadora_distribuida::config::ServerConfig;
+use calculadora_distribuida::connection::{LineError, read_line_limited};
+use calculadora_distribuida::datagram::{self, Dedup, MAX_DATAGRAM_SIZE, PRUNE_INTERVAL, Verdict};
+use calculadora_distribuida::gateway;
+use calculadora_distribuida::history::{self, Timeline};
+use calculadora_distribuida::http::{self, RequestError, Response};
+use calculadora_distribuida::latest;
+use calculadora_distribuida::log;
+use calculadora_distribuida::persistence;
+use calculadora_distribuida::protocol::{
+    INVALID_GET_AT, MAX_BATCH_SIZE, Message, Operation, PROTOCOL_VERSION, PointInTime,
+    UNKNOWN_MESSAGE, parse_message, redact_credentials,
+};
+use calculadora_distribuida::rate_limiter::{RateLimitConfig, RateLimiter};
+use calculadora_distribuida::simulation::Service;
+use calculadora_distribuida::state::{Backend, SharedState, State};
+use calculadora_distribuida::thread_pool::ThreadPool;
+use calculadora_distribuida::transport::{
+    Connection, Listener, STDIO_ADDRESS, Stdio, Stream, StreamListener,
+};
+use calculadora_distribuida::websocket::{self, Frame, FrameError, FrameReader};
+
+/// Ayuda de la línea de comando.
+const USAGE: &str = "\
+Uso: server [<direccion>] [opciones]
+
+La direccion es host:puerto (TCP), unix:<ruta> (socket Unix) o stdio
+(un unico cliente por la entrada y salida estandar).
+
+Opciones:
+  -c, --config <archivo>         archivo de configuracion (INI)
+  -a, --address <direccion>      direccion donde escuchar
+      --http <direccion>         pasarela HTTP/JSON (POST /ops, GET /value, POST /batch)
+      --udp <direccion>          recibe operaciones por UDP con confirmacion y reintentos
+      --admin <direccion>        canal de administracion (CLIENTS, KILL, RESET, SET, SNAPSHOT,
+                                 RESTORE, STATS)
+      --threads <n>              hilos que atienden las conexiones de lineas y HTTP, en total
+                                 (por defecto uno por conexion); hasta <n> mas esperan y el
+                                 resto recibe ERROR \"server busy\"
+      --numeric-type <tipo>      u8, u16, u32 o u64 (por defecto u8); con --backend atomic,
+                                 solo u8 o u16
+      --overflow <politica>      wrapping, saturating o error (por defecto wrapping); con
+                                 --backend aggregate, solo wrapping
+      --backend <backend>        mutex, atomic o aggregate, como se sincroniza el valor (por defecto mutex)
+      --persist <archivo>        guarda el valor tras cada cambio y lo restaura al iniciar
+      --history-size <n>         versiones que se conservan para GET AT (por defecto 10000)
+      --auth-file <archivo>      usuarios 'usuario:contraseña'; exige AUTH antes de operar
+      --admin-auth-file <archivo>
+                                 administradores, en el mismo formato; exige AUTH en --admin
+                                 (obligatorio con --admin y --auth-file)
+      --read-timeout <seg>       cierre por inactividad (0 lo desactiva)
+      --write-timeout <seg>      espera maxima al escribir (0 lo desactiva)
+      --max-line-length <bytes>  longitud maxima de una linea
+      --rate-limit <cap>:<tasa>  token bucket por cliente
+      --log-level <nivel>        error, warn, info o debug
+  -h, --help                     muestra esta ayuda
+
+Las opciones de linea de comando tienen prioridad sobre el archivo.";
+
+/// Opciones de línea de comando y la clave de configuración que asignan.
+const OPTIONS: [(&str, &str, &str); 18] = [
+    ("-a", "server", "address"),
+    ("--address", "server", "address"),
+    ("--http", "server", "http_address"),
+    ("--udp", "server", "udp_address"),
+    ("--admin", "server", "admin_address"),
+    ("--threads", "server", "threads"),
+    ("--log-level", "server", "log_level"),
+    ("--numeric-type", "calculator", "numeric_type"),
+    ("--overflow", "calculator", "overflow"),
+    ("--backend", "calculator", "backend"),
+    ("--persist", "storage", "persistence_path"),
+    ("--history-size", "storage", "history_size"),
+    ("--auth-file", "auth", "users_file"),
+    ("--admin-auth-file", "auth", "admin_file"),
+    ("--read-timeout", "connection", "read_timeout"),
+    ("--write-timeout", "connection", "write_timeout"),
+    ("--max-line-length", "connection", "max_line_length"),
+    ("--rate-limit", "rate_limit", "rate"),
+];
+
+/// Cada cuánto se aplican las operaciones acumuladas por el backend
+/// aggregate si hay observadores (ver `run_flusher`).
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Intentos de `AUTH` de administración por IP: una ráfaga de 5 y después
+/// uno cada 10 segundos, para frenar la búsqueda de contraseñas.
+const ADMIN_AUTH_LIMIT: RateLimitConfig = RateLimitConfig {
+    capacity: 5,
+    refill_per_sec: 0.1,
+};
+
+/// Aviso de cambio del valor: `(valor, versión)`.
+type Update = (u64, u64);
+
+/// Conexión WebSocket que recibe los cambios del valor.
+struct Watcher {
+    id: u64,
+    /// Conserva sólo el último aviso sin leer (ver `latest::channel`).
+    sender: latest::Sender<Update>,
+    /// Última versión avisada; los avisos de versiones anteriores se
+    /// descartan (ver `notify_watchers`).
+    last_version: Option<u64>,
+}
+
+/// Conexión activa, visible desde el canal de administración.
+struct Client {
+    id: u64,
+    peer: String,
+    /// `lines`, `http` o `admin` (ver `ClientInfo`).
+    protocol: &'static str,
+    since: Instant,
+    requests: AtomicU64,
+    /// Milisegundos entre `since` y el último pedido.
+    last_request: AtomicU64,
+    /// Manejador para cerrarla con `KILL`; `None` si no se puede (stdio).
+    closer: Option<Stream>,
+}
+
+impl Client {
+    /// Cuenta un pedido recibido por esta conexión.
+    fn touch(&self, server: &Server) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let elapsed = self.since.elapsed().as_millis();
+        self.last_request.store(
+            u64::try_from(elapsed).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        server.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn info(&self) -> ClientInfo {
+        let last = Duration::from_millis(self.last_request.load(Ordering::Relaxed));
+        ClientInfo {
+            id: self.id,
+            peer: self.peer.clone(),
+            protocol: self.protocol,
+            requests: self.requests.load(Ordering::Relaxed),
+            idle: self.since.elapsed().saturating_sub(last),
+        }
+    }
+}
+
+/// Estado y configuración compartidos por todas las conexiones.
+struct Server {
+    state: SharedState,
+    config: ServerConfig,
+    rate_limiter: Option<RateLimiter>,
+    users: Option<Users>,
+    /// Administradores; son los únicos que autentican el canal de
+    /// administración.
+    admins: Option<Users>,
+    /// Limita los `AUTH` del canal de administración (ver `ADMIN_AUTH_LIMIT`).
+    admin_auth: RateLimiter,
+    /// Versiones pasadas del valor e instantáneas. Se modifica con el lock
+    /// del estado tomado, así sigue el orden de las versiones; por eso sólo
+    /// se usa con `Backend::Mutex` (ver `Server::has_history`).
+    history: Mutex<Timeline>,
+    /// Conexiones WebSocket que reciben cada cambio del valor.
+    watchers: Mutex<Vec<Watcher>>,
+    next_watcher: AtomicU64,
+    /// Conexiones activas, por identificador (ver `Server::register`).
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+    next_client: AtomicU64,
+    /// Pedidos recibidos desde el inicio, por todas las conexiones.
+    requests: AtomicU64,
+    started: Instant,
+}
+
+impl Server {
+    /// Crea el servidor cargando el archivo de usuarios y el valor persistido,
+    /// si están configurados.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si alguno de los archivos no se puede leer, si
+    /// el valor persistido no entra en el tipo numérico configurado, o si el
+    /// backend no admite la configuración (ver `SharedState::new`). Sólo el
+    /// backend mutex persiste: con los otros los cambios no se guardan en
+    /// orden, o se responden antes de aplicarse. Si se exige `AUTH` y hay
+    /// canal de administración, éste necesita su propio archivo: de otro
+    /// modo cualquier usuario lo controlaría.
+    fn new(config: ServerConfig) -> Result<Self, String> {
+        if config.backend != Backend::Mutex && config.persistence_path.is_some() {
+            return Err("La persistencia requiere el backend mutex".to_string());
+        }
+        if config.admin_address.is_some()
+            && config.auth_file.is_some()
+            && config.admin_auth_file.is_none()
+        {
+            return Err(
+                "El canal de administracion requiere --admin-auth-file si se exige AUTH"
+                    .to_string(),
+            );
+        }
+        let users = match &config.auth_file {
+            Some(path) => Some(Users::from_file(path)?),
+            None => None,
+        };
+        let admins = match &config.admin_auth_file {
+            Some(path) => Some(Users::from_file(path)?),
+            None => None,
+        };
+
+        let (value, version) = match &config.persistence_path {
+            Some(path) => persistence::load(Path::new(path))?.unwrap_or((0, 0)),
+            None => (0, 0),
+        };
+        let numeric_type = config.arithmetic.numeric_type;
+        if value > numeric_type.max() {
+            return Err(format!(
+                "El valor persistido {} no entra en {}",
+                value, numeric_type
+            ));
+        }
+
+        let initial = history::Entry {
+            value,
+            version,
+            time: unix_time(),
+        };
+        let history_size = config.history_size.unwrap_or(history::DEFAULT_HISTORY_SIZE);
+
+        Ok(Server {
+            state: SharedState::new(config.backend, State { value, version }, config.arithmetic)?,
+            history: Mutex::new(Timeline::new(initial, history_size, first_snapshot())),
+            rate_limiter: config.rate_limit.map(RateLimiter::new),
+            users,
+            admins,
+            admin_auth: RateLimiter::new(ADMIN_AUTH_LIMIT),
+            config,
+            watchers: Mutex::new(Vec::new()),
+            next_watcher: AtomicU64::new(0),
+            clients: Mutex::new(BTreeMap::new()),
+            next_client: AtomicU64::new(1),
+            requests: AtomicU64::new(0),
+            started: Instant::now(),
+        })
+    }
+
+    /// Registra un observador de los cambios del valor y retorna su
+    /// identificador y el canal por el que recibe `(valor, versión)`.
+    ///
+    /// El primer aviso es el estado actual, leído después del registro, de
+    /// modo que no se pierde ningún cambio entre ambos. Un observador que
+    /// lee más lento de lo que cambia el valor no acumula avisos: recibe
+    /// el último y se saltea los intermedios.
+    fn watch(&self) -> Result<(u64, latest::Receiver<Update>), String> {
+        let (sender, receiver) = latest::channel();
+        let id = self.next_watcher.fetch_add(1, Ordering::Relaxed);
+        self.watchers
+            .lock()
+            .map_err(|_| "Observadores inaccesibles")?
+            .push(Watcher {
+                id,
+                sender,
+                last_version: None,
+            });
+        notify_watchers(&self.state.load()?, self);
+        Ok((id, receiver))
+    }
+
+    /// Da de baja al observador `id`; su canal se cierra.
+    fn unwatch(&self, id: u64) {
+        if let Ok(mut watchers) = self.watchers.lock() {
+            watchers.retain(|watcher| watcher.id != id);
+        }
+    }
+
+    /// Si se atienden `GET AT` y, desde administración, `SNAPSHOT` y
+    /// `RESTORE` (ver `Server::history`).
+    fn has_history(&self) -> bool {
+        self.state.backend() == Backend::Mutex
+    }
+
+    /// Registra una conexión activa, para listarla y cerrarla desde el
+    /// canal de administración. Quien la atiende debe darla de baja con
+    /// `unregister` al terminar.
+    fn register(
+        &self,
+        peer: String,
+        protocol: &'static str,
+        closer: Option<Stream>,
+    ) -> Arc<Client> {
+        let client = Arc::new(Client {
+            id: self.next_client.fetch_add(1, Ordering::Relaxed),
+            peer,
+            protocol,
+            since: Instant::now(),
+            requests: AtomicU64::new(0),
+            last_request: AtomicU64::new(0),
+            closer,
+        });
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.insert(client.id, Arc::clone(&client));
+        }
+        client
+    }
+
+    /// Da de baja la conexión `id`.
+    fn unregister(&self, id: u64) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.remove(&id);
+        }
+    }
+}
+
+/// Estado propio de cada conexión.
+struct Session {
+    /// Dirección IP del cliente.
+    peer: String,
+    /// Usuario autenticado con `AUTH`, si lo hay.
+    user: Option<String>,
+    /// `BATCH` cuyas líneas `OP` todavía se están recibiendo.
+    batch: Option<PendingBatch>,
+}
+
+/// Una operación de un lote, o el motivo por el que su línea no es válida.
+type BatchEntry = Result<(Operation, Option<u64>), String>;
+
+/// Lote anunciado con `BATCH n` que aún no recibió sus `n` líneas.
+struct PendingBatch {
+    size: usize,
+    entries: Vec<BatchEntry>,
+    /// Error a responder en lugar de aplicar el lote (tasa o autenticación).
+    rejected: Option<String>,
+}
+
+impl Session {
+    /// Sesión recién conectada, sin autenticar.
+    fn new(peer: String) -> Self {
+        Session {
+            peer,
+            user: None,
+            batch: None,
+        }
+    }
+
+    /// Clave para la limitación de tasa: el usuario si se autenticó, o la IP,
+    /// para que reconectar no reinicie el límite.
+    fn rate_key(&self) -> String {
+        match &self.user {
+            Some(user) => format!("user:{}", user),
+            None => self.peer.clone(),
+        }
+    }
+}
 
 /// Punto de entrada del servidor.
 ///
-/// Obtiene la dirección desde los argumentos de línea de comando y ejecuta
-/// el servidor TCP.
+/// Obtiene la configuración desde los argumentos de línea de comando y
+/// ejecuta el servidor por TCP, por un socket Unix, o atendiendo a un único
+/// cliente por stdin/stdout.
 fn main() {
-    if let Ok(address) = get_address()
-        && let Ok(listener) = create_listener(&address)
-    {
-        run_server(listener);
+    let Ok(config) = get_args() else {
+        return;
+    };
+    log::set_level(config.log_level);
+
+    let (listeners, server) = match start(config) {
+        Ok(started) => started,
+        Err(e) => {
+            log::error(&e);
+            return;
+        }
+    };
+    let server = Arc::new(server);
+    let pool = worker_pool(&server.config);
+    if let Some(http) = listeners.http {
+        let srv = Arc::clone(&server);
+        let pool = pool.clone();
+        thread::spawn(move || run_server(http, srv, pool, handle_http, busy_http));
+    }
+    if let Some(udp) = listeners.udp {
+        let srv = Arc::clone(&server);
+        thread::spawn(move || run_udp(udp, srv));
+    }
+    if let Some(admin) = listeners.admin {
+        let srv = Arc::clone(&server);
+        thread::spawn(move || run_server(admin, srv, None, handle_admin, busy_lines));
     }
+    if server.state.backend() == Backend::Aggregate {
+        let srv = Arc::clone(&server);
+        thread::spawn(move || run_flusher(srv));
+    }
+    match listeners.lines {
+        Some(listener) => run_server(listener, server, pool, handle_connection, busy_lines),
+        None => handle_connection(Stdio, server),
+    }
+}
+
+/// Sockets donde escucha el servidor.
+struct Listeners {
+    /// Protocolo de líneas; `None` con la dirección `stdio`.
+    lines: Option<StreamListener>,
+    /// Pasarela HTTP/JSON, si se configuró.
+    http: Option<StreamListener>,
+    /// Datagramas UDP, si se configuró.
+    udp: Option<UdpSocket>,
+    /// Canal de administración, si se configuró.
+    admin: Option<StreamListener>,
+}
+
+/// Valida la configuración, prepara el estado y bindea las direcciones del
+/// protocolo de líneas y, si se configuraron, de la pasarela HTTP, de UDP y
+/// del canal de administración.
+fn start(config: ServerConfig) -> Result<(Listeners, Server), String> {
+    let address = config
+        .address
+        .clone()
+        .ok_or("Se esperaba la direccion como argumento o en la configuracion")?;
+    let http_address = config.http_address.clone();
+    let udp_address = config.udp_address.clone();
+    let admin_address = config.admin_address.clone();
+    let server = Server::new(config)?;
+    let lines = match address.as_str() {
+        STDIO_ADDRESS => None,
+        _ => {
+            let listener = create_listener(&address)?;
+            log::info(&format!("Escuchando en {}", address));
+            Some(listener)
+        }
+    };
+    let http = match http_address {
+        Some(address) => {
+            let listener = create_listener(&address)?;
+            log::info(&format!("Pasarela HTTP escuchando en {}", address));
+            Some(listener)
+        }
+        None => None,
+    };
+    let udp = match udp_address {
+        Some(address) => {
+            let socket = UdpSocket::bind(&address)
+                .map_err(|e| format!("No se pudo bindear {}: {}", address, e))?;
+            log::info(&format!("Recibiendo datagramas en {}", address));
+            Some(socket)
+        }
+        None => None,
+    };
+    let admin = match admin_address {
+        Some(address) => {
+            let listener = create_listener(&address)?;
+            log::info(&format!("Administracion escuchando en {}", address));
+            Some(listener)
+        }
+        None => None,
+    };
+    Ok((
+        Listeners {
+            lines,
+            http,
+            udp,
+            admin,
+        },
+        server,
+    ))
 }
 
-/// Obtiene la dirección del servidor desde los argumentos de línea de comando.
+/// Obtiene la configuración del servidor desde los argumentos de línea de
+/// comando (ver `USAGE`).
 ///
-/// Retorna `Ok(String)` con la dirección o `Err(())` si no se proporcionó.
-fn get_address() -> Result<String, ()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("ERROR \"Se esperaba la direccion como argumento\"");
+/// Retorna `Ok(ServerConfig)` o `Err(())` si los argumentos son inválidos
+/// o se pidió la ayuda.
+fn get_args() -> Result<ServerConfig, ()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
         return Err(());
     }
+    parse_args(&args).map_err(|e| eprintln!("ERROR \"{}\"", e))
+}
+
+/// Interpreta los argumentos (sin el nombre del programa).
+///
+/// Primero se lee el archivo de `--config`, si lo hay, y luego se aplican
+/// encima el resto de las opciones.
+fn parse_args(args: &[String]) -> Result<ServerConfig, String> {
+    let mut iter = args.iter().peekable();
+    let mut address = None;
+    if let Some(a) = iter.next_if(|a| !a.starts_with('-')) {
+        address = Some(a.as_str());
+    }
+
+    let mut options = Vec::new();
+    while let Some(flag) = iter.next() {
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("Falta el valor de {}", flag))?;
+        options.push((flag.as_str(), value.as_str()));
+    }
+
+    let mut config = match options
+        .iter()
+        .find(|(flag, _)| *flag == "-c" || *flag == "--config")
+    {
+        Some((_, path)) => ServerConfig::from_file(path)?,
+        None => ServerConfig::default(),
+    };
+
+    if let Some(a) = address {
+        config.set("server", "address", a)?;
+    }
+    for (flag, value) in options {
+        if flag == "-c" || flag == "--config" {
+            continue;
+        }
+        let (_, section, key) = OPTIONS
+            .iter()
+            .find(|(name, _, _)| *name == flag)
+            .ok_or_else(|| format!("Argumento desconocido: {}", flag))?;
+        config
+            .set(section, key, value)
+            .map_err(|e| format!("{}: {}", flag, e))?;
+    }
 
-    Ok(String::from(&args[1]))
+    Ok(config)
 }
 
-/// Crea un `TcpListener` en la dirección proporcionada.
+/// Crea un listener en la dirección proporcionada: `host:puerto` para TCP
+/// o `unix:<ruta>` para un socket Unix.
 ///
 /// # Errores
 /// Retorna `Err(String)` si no se puede bindear la dirección.
-fn create_listener(address: &str) -> Result<TcpListener, String> {
-    TcpListener::bind(address).map_err(|e| format!("ERROR \"No se pudo bindear: {}\"", e))
+fn create_listener(address: &str) -> Result<StreamListener, String> {
+    StreamListener::bind(address).map_err(|e| format!("No se pudo bindear: {}", e))
+}
+
+/// Pool de `threads` hilos que comparten el protocolo de líneas y la
+/// pasarela HTTP, o `None` si no se configuró `threads`.
+///
+/// El canal de administración no lo usa: así se puede atender (por
+/// ejemplo, para hacer `KILL`) aunque los clientes ocupen todos los hilos.
+fn worker_pool(config: &ServerConfig) -> Option<Arc<ThreadPool>> {
+    config.threads.map(|n| Arc::new(ThreadPool::new(n, n)))
 }
 
 /// Ejecuta el bucle principal del servidor.
 ///
-/// - Acepta conexiones entrantes.
-/// - Para cada conexión, crea un hilo que maneja el cliente.
+/// - Acepta conexiones entrantes y las atiende con `handler`.
+/// - Cada conexión se atiende en su propio hilo o, si se indica `pool`
+///   (ver `worker_pool`), en ese pool de hilos. Con el pool ocupado
+///   esperan a lo sumo otras `threads` conexiones, sumando las de todos
+///   los listeners que lo comparten; las demás se rechazan en el acto con
+///   `busy` en lugar de quedar sin respuesta.
 /// - Mantiene un estado compartido seguro entre hilos.
-fn run_server(listener: TcpListener) {
-    let state = Arc::new(Mutex::new(0u8));
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(s) => {
-                let st = Arc::clone(&state);
-                thread::spawn(move || handle_connection(s, st));
+fn run_server<L: Listener>(
+    listener: L,
+    server: Arc<Server>,
+    pool: Option<Arc<ThreadPool>>,
+    handler: fn(L::Connection, Arc<Server>),
+    busy: fn(L::Connection, &Server),
+) {
+    loop {
+        match listener.accept() {
+            Ok(conn) => {
+                if pool.as_deref().is_some_and(ThreadPool::is_full) {
+                    log::warn("Pool de hilos ocupado; se rechaza la conexion");
+                    busy(conn, &server);
+                    continue;
+                }
+                let srv = Arc::clone(&server);
+                let job = move || handler(conn, srv);
+                match &pool {
+                    Some(pool) => {
+                        if let Err(e) = pool.execute(job) {
+                            log::error(&e);
+                        }
+                    }
+                    None => {
+                        thread::spawn(job);
+                    }
+                }
             }
-            Err(e) => eprintln!("ERROR \"{}\"", e),
+            Err(e) => log::error(&e.to_string()),
         }
     }
 }
 
+/// Rechaza una conexión de líneas (o de administración) con
+/// `ERROR "server busy"` porque el pool de hilos está lleno.
+fn busy_lines<C: Connection>(conn: C, server: &Server) {
+    if let Ok((_, mut writer)) = conn.split(&server.config.limits) {
+        let _ = send_error("server busy", &mut writer);
+    }
+}
+
+/// Rechaza una conexión HTTP con `503 Service Unavailable` porque el pool
+/// de hilos está lleno.
+fn busy_http<C: Connection>(conn: C, server: &Server) {
+    if let Ok((_, mut writer)) = conn.split(&server.config.limits) {
+        let _ = Response::error(503, "server busy").write_to(&mut writer, false);
+    }
+}
+
 /// Maneja una conexión individual de cliente.
 ///
-/// - Lee líneas enviadas por el cliente.
-/// - Procesa cada línea usando `handle_line`.
-fn handle_connection(stream: TcpStream, state: Arc<Mutex<u8>>) {
-    let mut writer = match stream.try_clone() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("ERROR \"{}\"", e);
-            return;
-        }
+/// - Configura los timeouts de lectura y escritura de la conexión.
+/// - Lee líneas enviadas por el cliente sin superar `max_line_length`.
+/// - Procesa cada lectura usando `process_input`.
+fn handle_connection<C: Connection>(conn: C, server: Arc<Server>) {
+    let limits = server.config.limits;
+    let Some((client, reader, mut writer)) = open_connection(conn, &server, "lines") else {
+        return;
     };
+    let mut session = Session::new(client.peer.clone());
 
-    let reader = BufReader::new(stream);
-    for line in reader.lines() {
-        if let Err(e) = handle_line(&line, &state, &mut writer) {
-            eprintln!("ERROR \"{}\"", e);
+    log::info(&format!("Conexion de {}", session.peer));
+    let mut reader = BufReader::new(reader);
+    loop {
+        let input = read_line_limited(&mut reader, limits.max_line_length, limits.read_timeout);
+        if let Ok(Some(_)) = input {
+            client.touch(&server);
+        }
+        if !process_input(input, &server, &mut session, &mut writer) {
+            break;
         }
     }
+    server.unregister(client.id);
+    log::info(&format!("Desconexion de {}", session.peer));
 }
 
-/// Procesa una línea recibida del cliente.
-///
-/// # Parámetros
-/// - `line`: línea recibida del cliente.
-/// - `state`: referencia al estado compartido entre clientes.
-/// - `writer`: stream para responder al cliente.
-///
-/// # Retorno
-/// Retorna `Ok(())` si se procesó la línea (incluso si contenía errores lógicos
-/// que fueron notificados al cliente), o `Err(String)` si ocurrió un error
-/// de E/S al leer la línea o al escribir la respuesta de error.
-fn handle_line(
-    line: &Result<String, std::io::Error>,
-    state: &Arc<Mutex<u8>>,
-    writer: &mut TcpStream,
-) -> Result<(), String> {
-    let l = line.as_ref().map_err(|e| e.to_string())?;
-    match parse_message(l) {
-        Ok(Message::Op(op)) => {
-            let mut guard = lock_state(state, writer)?;
-            let _ = apply_operation(op, &mut guard, writer);
+/// Identifica al cliente, separa la conexión en lectura y escritura con
+/// los timeouts configurados y la registra como activa (ver
+/// `Server::register`). Si falla, lo registra en el log y retorna `None`.
+fn open_connection<C: Connection>(
+    conn: C,
+    server: &Server,
+    protocol: &'static str,
+) -> Option<(Arc<Client>, C::Reader, C::Writer)> {
+    let opened = conn
+        .peer()
+        .and_then(|peer| Ok((peer, conn.closer()?, conn.split(&server.config.limits)?)));
+    match opened {
+        Ok((peer, closer, (reader, writer))) => {
+            Some((server.register(peer, protocol, closer), reader, writer))
         }
-        Ok(Message::Get) => {
-            let guard = lock_state(state, writer)?;
-            send_value(&guard, writer);
+        Err(e) => {
+            log::error(&e.to_string());
+            None
         }
-        Ok(_) => send_unexpected(writer),
-        Err(_) => send_parse_error("parsing error", writer),
     }
-    Ok(())
 }
 
-/// Aplica una operación matemática sobre el estado.
+/// Atiende una conexión del canal de administración: una respuesta por
+/// cada comando (ver `admin_response`).
 ///
-/// # Parámetros
-/// - `op`: operación a aplicar.
-/// - `guard`: referencia mutable al estado.
-/// - `writer`: stream para enviar la respuesta al cliente.
+/// El canal da control total sobre el servidor, así que conviene escucharlo
+/// sólo en una dirección local. Si el servidor tiene archivo de
+/// administradores, exige además `AUTH` con uno de ellos antes de cualquier
+/// otro comando.
+fn handle_admin<C: Connection>(conn: C, server: Arc<Server>) {
+    let limits = server.config.limits;
+    let Some((client, reader, mut writer)) = open_connection(conn, &server, "admin") else {
+        return;
+    };
+    log::info(&format!("Conexion de administracion de {}", client.peer));
+    let mut reader = BufReader::new(reader);
+    let mut authenticated = server.admins.is_none();
+    loop {
+        let line = match read_line_limited(&mut reader, limits.max_line_length, limits.read_timeout)
+        {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                log::warn(&format!("{}: administracion: {:?}", client.peer, e));
+                break;
+            }
+        };
+        client.touch(&server);
+        let response = admin_response(&line, &server, &client, &mut authenticated);
+        if let Err(e) = writer.write_all(format!("{}\n", response).as_bytes()) {
+            log::error(&format!("{}: {}", client.peer, e));
+            break;
+        }
+    }
+    server.unregister(client.id);
+    log::info(&format!("Desconexion de administracion de {}", client.peer));
+}
+
+/// Ejecuta un comando del canal de administración y retorna la respuesta.
 ///
-/// # Retorno
-/// Retorna `Ok(())` siempre.  
-/// Si la operación se aplica con éxito, envía `"OK"` al cliente.  
-/// Si ocurre un error lógico (división por cero),
-/// envía un mensaje de error al cliente.
-fn apply_operation(op: Operation, guard: &mut u8, writer: &mut TcpStream) -> Result<(), ()> {
-    match calculator::apply_operation(*guard, &op) {
-        Ok(new_val) => {
-            *guard = new_val;
-            let _ = writer.write_all(b"OK\n");
+/// - `CLIENTS`: `CLIENTS <n>` seguido de una línea `CLIENT ...` por conexión
+///   activa (ver `ClientInfo`), incluida la que pregunta.
+/// - `KILL <id>`: cierra la conexión; su hilo termina al notar el cierre.
+/// - `RESET` y `SET <valor>`: reemplazan el valor como una modificación
+///   más, con versión nueva, persistencia y avisos; responden `OK @<version>`.
+/// - `STATS`: ver `Stats`.
+///
+/// Los errores se responden como `ERROR "<motivo>"`.
+fn admin_response(
+    line: &str,
+    server: &Server,
+    client: &Client,
+    authenticated: &mut bool,
+) -> String {
+    let command = match admin::parse_command(line) {
+        Ok(command) => command,
+        Err(motivo) => return Message::Err(motivo).to_string(),
+    };
+    let error = |motivo: &str| Message::Err(motivo.to_string()).to_string();
+    match command {
+        Command::Auth { .. } if let Err(wait) = server.admin_auth.check(&client.peer) => {
+            log::warn(&format!(
+                "{}: demasiados intentos de autenticacion de administracion",
+                client.peer
+            ));
+            error(&rate_limit_error(wait))
+        }
+        Command::Auth { user, password } => {
+            if server
+                .admins
+                .as_ref()
+                .is_none_or(|u| u.verify(&user, &password))
+            {
+                log::info(&format!("{}: administracion como {}", client.peer, user));
+                *authenticated = true;
+                Message::Ok(None).to_string()
+            } else {
+                log::warn(&format!(
+                    "{}: autenticacion de administracion fallida para {}",
+                    client.peer, user
+                ));
+                error("authentication failed")
+            }
         }
-        Err(motivo) => {
-            let _ = writer.write_all(format!("ERROR \"{}\"\n", motivo).as_bytes());
+        _ if !*authenticated => error("authentication required"),
+        Command::Clients => {
+            let clients: Vec<ClientInfo> = match server.clients.lock() {
+                Ok(clients) => clients.values().map(|c| c.info()).collect(),
+                Err(_) => return error("Conexiones inaccesibles"),
+            };
+            let mut response = format!("CLIENTS {}", clients.len());
+            for info in clients {
+                response.push_str(&format!("\n{}", info));
+            }
+            response
+        }
+        Command::Kill(id) => {
+            let target = match server.clients.lock() {
+                Ok(clients) => clients.get(&id).cloned(),
+                Err(_) => return error("Conexiones inaccesibles"),
+            };
+            let Some(target) = target else {
+                return error("unknown client");
+            };
+            let Some(closer) = &target.closer else {
+                return error("client cannot be closed");
+            };
+            match closer.shutdown() {
+                Ok(()) => {
+                    log::info(&format!(
+                        "{}: cerrada la conexion {} de {}",
+                        client.peer, id, target.peer
+                    ));
+                    Message::Ok(None).to_string()
+                }
+                Err(e) => error(&e.to_string()),
+            }
+        }
+        Command::Reset => admin_store(0, server, client),
+        Command::Set(value) if value > server.config.arithmetic.numeric_type.max() => {
+            error("operand out of range")
+        }
+        Command::Set(value) => admin_store(value, server, client),
+        Command::Snapshot | Command::Restore(_) if !server.has_history() => {
+            error("history not available")
+        }
+        Command::Snapshot => match server.history.lock() {
+            Ok(mut history) => {
+                let id = history.snapshot();
+                log::info(&format!("{}: instantanea {}", client.peer, id));
+                Message::Snapshot(Some(id)).to_string()
+            }
+            Err(_) => error("Historial inaccesible"),
+        },
+        Command::Restore(id) => {
+            let snapshot = match server.history.lock() {
+                Ok(history) => history.get_snapshot(id),
+                Err(_) => return error("Historial inaccesible"),
+            };
+            let Some(snapshot) = snapshot else {
+                return error("unknown snapshot");
+            };
+            log::info(&format!(
+                "{}: restaurada la instantanea {} (version {})",
+                client.peer, id, snapshot.version
+            ));
+            admin_store(snapshot.value, server, client)
+        }
+        Command::Stats => {
+            let state = match server.state.load() {
+                Ok(state) => state,
+                Err(motivo) => return error(&motivo),
+            };
+            let clients = server.clients.lock().map_or(0, |clients| clients.len());
+            Stats {
+                value: state.value,
+                version: state.version,
+                clients,
+                requests: server.requests.load(Ordering::Relaxed),
+                uptime: server.started.elapsed(),
+            }
+            .to_string()
         }
     }
-    Ok(())
 }
 
-/// Envía el valor actual del estado al cliente.
-fn send_value(guard: &u8, writer: &mut TcpStream) {
-    let _ = writer.write_all(format!("VALUE {}\n", *guard).as_bytes());
+/// Reemplaza el valor desde el canal de administración (ver
+/// `store_value`) y retorna `OK @<version>`.
+fn admin_store(value: u64, server: &Server, client: &Client) -> String {
+    log::info(&format!(
+        "{}: valor reemplazado por {} desde administracion",
+        client.peer, value
+    ));
+    let stored = server.state.update(
+        |state| store_value(value, state),
+        |state, _| commit(state, server),
+    );
+    match stored {
+        Ok(version) => Message::Ok(Some(version)).to_string(),
+        Err(motivo) => Message::Err(motivo).to_string(),
+    }
 }
 
-/// Envía un mensaje de error de parseo al cliente.
-fn send_parse_error(parse_err: &str, writer: &mut TcpStream) {
-    let _ = writer.write_all(format!("ERROR \"{}\"\n", parse_err).as_bytes());
-}
+/// Atiende una conexión de la pasarela HTTP/JSON (ver `gateway::handle`).
+///
+/// Cada pedido se traduce a mensajes del protocolo que se procesan con
+/// `handle_line` en una sesión propia, con la misma autenticación,
+/// limitación de tasa y versiones que el protocolo de líneas. La conexión
+/// se mantiene abierta entre pedidos salvo que el cliente pida cerrarla.
+fn handle_http<C: Connection>(conn: C, server: Arc<Server>) {
+    let limits = server.config.limits;
+    let Some((client, reader, mut writer)) = open_connection(conn, &server, "http") else {
+        return;
+    };
+    let peer = client.peer.clone();
+    log::info(&format!("Conexion HTTP de {}", peer));
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let request = match http::read_request(&mut reader, limits.max_line_length) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(RequestError::Status(status, motivo)) => {
+                log::warn(&format!("{}: {}", peer, motivo));
+                let _ = Response::error(status, &motivo).write_to(&mut writer, false);
+                break;
+            }
+            Err(RequestError::Line(e)) => {
+                log::warn(&format!("{}: {:?}", peer, e));
+                break;
+            }
+        };
+        log::debug(&format!("{}: {} {}", peer, request.method, request.path));
+        client.touch(&server);
 
-/// Envía un mensaje de error por mensaje inesperado.
-fn send_unexpected(writer: &mut TcpStream) {
-    let _ = writer.write_all(b"ERROR \"unexpected message\"\n");
+        let mut session = Session::new(peer.clone());
+        let keep_alive = request.keep_alive();
+        let response = if request.path == websocket::PATH {
+            Ok(websocket::handshake(&request).unwrap_or_else(|error| error))
+        } else {
+            gateway::handle(&request, |message| {
+                let mut out = Vec::new();
+                handle_line(&message.to_string(), &server, &mut session, &mut out)?;
+                parse_message(String::from_utf8_lossy(&out).trim_end())
+            })
+        };
+        let upgrade = matches!(&response, Ok(r) if r.status == 101);
+        let result = response.and_then(|response| {
+            response
+                .write_to(&mut writer, keep_alive)
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            log::error(&format!("{}: {}", peer, e));
+            break;
+        }
+        if upgrade {
+            log::info(&format!("{}: WebSocket abierto", peer));
+            handle_websocket(reader, writer, &server, &client);
+            break;
+        }
+        if !keep_alive {
+            break;
+        }
+    }
+    server.unregister(client.id);
+    log::info(&format!("Desconexion HTTP de {}", peer));
 }
 
-/// Bloquea el estado compartido para su uso seguro.
+/// Atiende una conexión WebSocket ya abierta (ver `websocket::handshake`).
 ///
-/// Retorna un `MutexGuard` sobre el estado o `Err(String)` si no se puede acceder.
-fn lock_state<'a>(
-    state: &'a Arc<Mutex<u8>>,
-    writer: &mut TcpStream,
-) -> Result<std::sync::MutexGuard<'a, u8>, String> {
-    state.lock().map_err(|_| {
-        let _ = writer.write_all(b"ERROR \"Estado inaccesible\"\n");
-        "Estado inaccesible".to_string()
-    })
-}
+/// Cada mensaje de texto trae una o más líneas del protocolo, que se
+/// procesan como en `handle_connection` dentro de una sesión propia; cada
+/// línea de respuesta se envía como un mensaje de texto. Además el servidor
+/// envía `UPDATE <valor> @<versión>` al abrir la conexión y con cada cambio
+/// del valor, y un ping cuando pasa la mitad del timeout de lectura sin
+/// cambios, para que un cliente que sólo observa no se desconecte por
+/// inactividad.
+///
+/// Cada mensaje recibido cuenta como un pedido de `client`.
+fn handle_websocket<R: Read, W: Write + Send>(
+    reader: R,
+    writer: W,
+    server: &Server,
+    client: &Client,
+) {
+    let limits = server.config.limits;
+    let (id, updates) = match server.watch() {
+        Ok(watch) => watch,
+        Err(e) => {
+            log::error(&e);
+            return;
+        }
+    };
+    let writer = Mutex::new(writer);
+    let mut frames = FrameReader::new(reader, http::MAX_BODY_SIZE);
+    let mut session = Session::new(client.peer.clone());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::{BufRead, BufReader, Write};
-    use std::net::TcpStream;
-    use std::thread;
-    use std::time::Duration;
+    thread::scope(|scope| {
+        scope.spawn(|| push_updates(updates, &writer, limits.read_timeout.map(|t| t / 2)));
 
-    /// Arranca el servidor en un hilo y devuelve la dirección
-    fn start_server() -> String {
-        let listener = TcpListener::bind("127.0.0.1:0").unwrap(); // puerto aleatorio
-        let addr = listener.local_addr().unwrap().to_string();
+        let close = loop {
+            let frame = match frames.read() {
+                Ok(Some(frame)) => {
+                    client.touch(server);
+                    frame
+                }
+                Ok(None) => break None,
+                Err(FrameError::Protocol(code, motivo)) => {
+                    log::warn(&format!("{}: {}", session.peer, motivo));
+                    break Some(code);
+                }
+                Err(FrameError::Line(e)) => {
+                    let code = match e {
+                        LineError::TooLong => Some(websocket::CLOSE_TOO_BIG),
+                        LineError::Timeout => Some(websocket::CLOSE_GOING_AWAY),
+                        LineError::Io(_) => None,
+                    };
+                    let mut out = Vec::new();
+                    process_input(Err(e), server, &mut session, &mut out);
+                    send_lines(&out, &writer);
+                    break code;
+                }
+            };
+            match frame {
+                Frame::Text(text) => {
+                    let mut out = Vec::new();
+                    let open = text.lines().all(|line| {
+                        let input = if line.len() > limits.max_line_length {
+                            Err(LineError::TooLong)
+                        } else {
+                            Ok(Some(line.to_string()))
+                        };
+                        process_input(input, server, &mut session, &mut out)
+                    });
+                    if !send_lines(&out, &writer) {
+                        break None;
+                    }
+                    if !open {
+                        break Some(websocket::CLOSE_POLICY_VIOLATION);
+                    }
+                }
+                Frame::Ping(data) => {
+                    if !send_frame(&Frame::Pong(data), &writer) {
+                        break None;
+                    }
+                }
+                Frame::Pong(_) => {}
+                Frame::Binary(_) => break Some(websocket::CLOSE_UNSUPPORTED_DATA),
+                Frame::Close(_) => break Some(websocket::CLOSE_NORMAL),
+            }
+        };
 
-        thread::spawn(move || {
-            run_server(listener);
-        });
+        // Cerrar el canal termina el hilo de avisos.
+        server.unwatch(id);
+        if let Some(code) = close {
+            send_frame(&Frame::Close(Some(code)), &writer);
+        }
+    });
+    log::info(&format!("{}: WebSocket cerrado", session.peer));
+}
 
-        // Pequeña pausa para que el servidor esté escuchando
-        thread::sleep(Duration::from_millis(50));
-        addr
+/// Envía por WebSocket cada cambio del valor recibido por `updates`, y un
+/// ping cada `keepalive` sin cambios. Si el cliente lee más lento de lo que
+/// cambia el valor, la escritura se bloquea y los avisos de mientras se
+/// reemplazan por el último. Termina cuando se cierra el canal o falla la
+/// escritura.
+fn push_updates(
+    updates: latest::Receiver<Update>,
+    writer: &Mutex<impl Write>,
+    keepalive: Option<Duration>,
+) {
+    loop {
+        let update = match keepalive {
+            Some(interval) => updates.recv_timeout(interval),
+            None => updates.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let frame = match update {
+            Ok((value, version)) => Frame::Text(Message::Update(value, Some(version)).to_string()),
+            Err(RecvTimeoutError::Timeout) => Frame::Ping(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if !send_frame(&frame, writer) {
+            return;
+        }
     }
+}
 
-    #[test]
-    fn test_server_ok_response() {
-        let addr = start_server();
-        let mut stream = TcpStream::connect(addr).unwrap();
+/// Envía un frame WebSocket; retorna `false` si la conexión ya no admite
+/// escrituras.
+fn send_frame(frame: &Frame, writer: &Mutex<impl Write>) -> bool {
+    writer
+        .lock()
+        .is_ok_and(|mut w| websocket::write_frame(&mut *w, frame).is_ok())
+}
 
-        // Enviar operación válida
-        stream.write_all(b"OP + 10\n").unwrap();
+/// Envía cada línea de `out` como un mensaje de texto.
+fn send_lines(out: &[u8], writer: &Mutex<impl Write>) -> bool {
+    String::from_utf8_lossy(out)
+        .lines()
+        .all(|line| send_frame(&Frame::Text(line.to_string()), writer))
+}
 
-        let mut reader = BufReader::new(&mut stream);
-        let mut response = String::new();
-        reader.read_line(&mut response).unwrap();
+/// Procesa una lectura de la conexión: una línea, el cierre por parte del
+/// cliente o un error.
+///
+/// La conexión se cierra si el cliente envía una línea demasiado larga
+/// (respondiendo `ERROR "line too long"`), si permanece inactivo más que
+/// el timeout de lectura, o si no consume las respuestas a tiempo.
+///
+/// Retorna `false` si la conexión debe cerrarse.
+fn process_input(
+    input: Result<Option<String>, LineError>,
+    server: &Server,
+    session: &mut Session,
+    writer: &mut impl Write,
+) -> bool {
+    let result = match input {
+        Ok(Some(line)) => {
+            log::debug(&format!("{}: {}", session.peer, redact_credentials(&line)));
+            handle_line(&line, server, session, writer)
+        }
+        Ok(None) => return false,
+        Err(LineError::TooLong) => {
+            let _ = send_error("line too long", writer);
+            log::warn(&format!("{}: line too long", session.peer));
+            return false;
+        }
+        Err(LineError::Timeout) => {
+            let _ = send_error("idle timeout", writer);
+            log::warn(&format!("{}: idle timeout", session.peer));
+            return false;
+        }
+        Err(LineError::Io(e)) => Err(e),
+    };
 
-        assert_eq!(response.trim(), "OK");
+    if let Err(e) = result {
+        log::error(&format!("{}: {}", session.peer, e));
+        return false;
     }
+    true
+}
 
-    #[test]
-    fn test_server_value_response() {
-        let addr = start_server();
-        let mut stream = TcpStream::connect(addr).unwrap();
-        let mut reader = BufReader::new(&mut stream);
+/// Aplica periódicamente las sumas y restas acumuladas por el backend
+/// aggregate, para que los observadores las reciban aunque no llegue
+/// ninguna otra modificación.
+fn run_flusher(server: Arc<Server>) {
+    loop {
+        thread::sleep(FLUSH_INTERVAL);
+        flush_pending(&server);
+    }
+}
 
-        // Enviar operación
-        reader.get_mut().write_all(b"OP + 5\n").unwrap();
-        let mut resp1 = String::new();
-        reader.read_line(&mut resp1).unwrap();
-        assert_eq!(resp1.trim(), "OK");
+/// Aplica las operaciones acumuladas y las avisa, si hay observadores.
+fn flush_pending(server: &Server) {
+    let watched = server.watchers.lock().is_ok_and(|w| !w.is_empty());
+    if watched
+        && let Err(e) = server
+            .state
+            .update(|_| (), |state, _| commit(state, server))
+    {
+        log::error(&e);
+    }
+}
 
-        // Enviar GET usando la misma referencia
-        reader.get_mut().write_all(b"GET\n").unwrap();
-        let mut resp2 = String::new();
-        reader.read_line(&mut resp2).unwrap();
-        assert_eq!(resp2.trim(), "VALUE 5");
+/// Recibe datagramas UDP (ver `datagram`) y responde cada uno con
+/// `handle_datagram`.
+///
+/// Un único hilo atiende todos los datagramas; cada uno se aplica con una
+/// sola toma del lock, como un lote. Cada `PRUNE_INTERVAL`, aunque no
+/// lleguen datagramas, olvida a los emisores inactivos.
+fn run_udp(socket: UdpSocket, server: Arc<Server>) {
+    let mut dedup = Dedup::new();
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    let mut last_prune = Instant::now();
+    if let Err(e) = socket.set_read_timeout(Some(PRUNE_INTERVAL)) {
+        log::error(&format!("UDP: {}", e));
+    }
+    loop {
+        if last_prune.elapsed() >= PRUNE_INTERVAL {
+            last_prune = Instant::now();
+            dedup.prune(last_prune);
+        }
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                log::error(&format!("UDP: {}", e));
+                continue;
+            }
+        };
+        let Ok(text) = std::str::from_utf8(&buf[..len]) else {
+            log::warn(&format!("{}: datagrama que no es UTF-8", from));
+            continue;
+        };
+        if let Some(reply) = handle_datagram(text, from, &server, &mut dedup)
+            && let Err(e) = socket.send_to(reply.as_bytes(), from)
+        {
+            log::error(&format!("{}: {}", from, e));
+        }
     }
+}
 
-    #[test]
-    fn test_server_error_response() {
-        let addr = start_server();
-        let mut stream = TcpStream::connect(addr).unwrap();
+/// Procesa un datagrama `<secuencia> OP ...`, `<secuencia> OPS ...` o
+/// `<secuencia> LINES ...` de `from` y retorna la respuesta, o `None` si no
+/// se debe responder.
+///
+/// - A `<secuencia> HELLO ...` responde como por TCP (ver `hello_reply`),
+///   con la misma secuencia y sin registrarla: así el emisor conoce
+///   `burst=` antes de armar los datagramas.
+/// - Aplica sus operaciones como un lote y confirma con `<secuencia> OK`
+///   si todas se aplicaron, o con `<secuencia> RESULTS ...` (ver
+///   `apply_batch`) si alguna falló (división por cero, overflow).
+/// - Si la secuencia ya se aplicó, vuelve a confirmarla sin aplicarla, con
+///   la misma respuesta, para que los reintentos del emisor no dupliquen
+///   operaciones.
+/// - Rechaza con `<secuencia> ERROR "<motivo>"` lo que no tiene arreglo
+///   reintentando: mensajes inválidos, `OP` con versión, lotes demasiado
+///   grandes, secuencias fuera de la ventana del emisor (ver
+///   `Dedup::check`), un servidor que exige autenticación (UDP no tiene
+///   sesión donde hacer `AUTH`), o más operaciones que la capacidad del
+///   token bucket (`ERROR "batch exceeds rate limit"`).
+/// - Si el emisor superó su tasa no responde, y el emisor reintenta más
+///   tarde.
+fn handle_datagram(
+    text: &str,
+    from: SocketAddr,
+    server: &Server,
+    dedup: &mut Dedup,
+) -> Option<String> {
+    let Some((seq, rest)) = datagram::split_seq(text) else {
+        log::warn(&format!("{}: datagrama sin secuencia", from));
+        return None;
+    };
+    let sender = from.to_string();
+    log::debug(&format!("{}: {} {}", sender, seq, redact_credentials(rest)));
+    let reject = |motivo: &str| Some(datagram::encode(seq, &Message::Err(motivo.to_string())));
+    // Espera que indica el limitador si no alcanzan los tokens para `ops`.
+    let rate_limited = |ops: usize| {
+        let limiter = server.rate_limiter.as_ref()?;
+        limiter.check_n(&from.ip().to_string(), tokens(ops)).err()
+    };
+    let dropped = |wait: Duration| {
+        log::debug(&format!(
+            "{}: datagrama {} descartado por tasa; reintentar en {} ms",
+            sender,
+            seq,
+            wait.as_millis()
+        ));
+        None
+    };
 
-        // Enviar operación inválida (división por cero)
-        stream.write_all(b"OP / 0\n").unwrap();
+    let lines = match parse_message(rest) {
+        Ok(Message::Hello {
+            version,
+            capabilities,
+        }) => {
+            if let Some(wait) = rate_limited(1) {
+                return dropped(wait);
+            }
+            return Some(datagram::encode(
+                seq,
+                &hello_reply(version, &capabilities, server),
+            ));
+        }
+        Ok(Message::Op(op, None)) => vec![vec![op]],
+        Ok(Message::Ops(ops)) => ops.into_iter().map(|op| vec![op]).collect(),
+        Ok(Message::Lines(lines)) => lines,
+        Ok(Message::Op(_, Some(_))) => return reject("versions not supported over UDP"),
+        Ok(_) => return reject("unexpected message"),
+        Err(e) if e == "batch too large" => return reject(&e),
+        Err(_) => return reject("parsing error"),
+    };
+    if server.users.is_some() {
+        return reject("authentication required");
+    }
+    match dedup.check(&sender, seq) {
+        Verdict::New => {}
+        Verdict::Repeated(reply) => {
+            log::debug(&format!("{}: datagrama {} repetido", sender, seq));
+            return Some(match reply {
+                Some(reply) => format!("{} {}", seq, reply),
+                None => datagram::encode(seq, &Message::Ok(None)),
+            });
+        }
+        Verdict::Rejected(motivo) => {
+            log::warn(&format!("{}: datagrama {}: {}", sender, seq, motivo));
+            return reject(motivo);
+        }
+    }
+    match rate_limited(lines.iter().map(Vec::len).sum()) {
+        Some(Duration::MAX) => return reject(&rate_limit_error(Duration::MAX)),
+        Some(wait) => return dropped(wait),
+        None => {}
+    }
 
-        let mut reader = BufReader::new(&mut stream);
-        let mut response = String::new();
-        reader.read_line(&mut response).unwrap();
+    let mut out = Vec::new();
+    if let Err(e) = apply_batch(line_units(lines), server, &mut out) {
+        log::error(&format!("{}: {}", sender, e));
+        return None;
+    }
+    let results = String::from_utf8_lossy(&out).trim_end().to_string();
+    let failed = match parse_message(&results) {
+        Ok(Message::Results(results)) => results.iter().any(|r| matches!(r, Message::Err(_))),
+        _ => true,
+    };
+    let reply = failed.then_some(results);
+    if let Some(results) = &reply {
+        log::warn(&format!("{}: datagrama {}: {}", sender, seq, results));
+    }
+    let ack = match &reply {
+        Some(results) => format!("{} {}", seq, results),
+        None => datagram::encode(seq, &Message::Ok(None)),
+    };
+    dedup.record(&sender, seq, reply, Instant::now());
+    Some(ack)
+}
 
-        assert!(response.trim().starts_with("ERROR"));
+/// Permite ejecutar el servidor dentro de `simulation::run`, sin sockets.
+impl Service for Server {
+    type Session = Session;
+
+    fn connect(&self, peer: &str) -> Session {
+        Session::new(peer.to_string())
+    }
+
+    fn handle(&self, session: &mut Session, line: &str, out: &mut Vec<u8>) -> bool {
+        process_input(Ok(Some(line.to_string())), self, session, out)
+    }
+}
+
+/// Procesa una línea recibida del cliente.
+///
+/// # Parámetros
+/// - `line`: línea recibida del cliente.
+/// - `server`: estado y configuración compartidos entre clientes.
+/// - `session`: estado de la conexión (dirección y usuario autenticado).
+/// - `writer`: stream para responder al cliente.
+///
+/// Si el cliente superó su tasa permitida se responde
+/// `ERROR "rate limited; retry after <n> ms"` sin tocar el estado. `OPS` y
+/// `BATCH` consumen un token por operación y se rechazan enteros si no
+/// alcanzan; si superan la capacidad del bucket, con
+/// `ERROR "batch exceeds rate limit"`. Si el
+/// servidor exige autenticación, `OP`, `GET`, `CAS`, `OPIF` y los mensajes
+/// del historial se rechazan con `ERROR "authentication required"` hasta
+/// recibir un `AUTH` válido.
+///
+/// `GET AT` responde con el valor de una versión o un instante pasado (ver
+/// `Timeline`). `SNAPSHOT` y `RESTORE` se responden con
+/// `ERROR "use the admin channel"`: sólo se atienden desde administración
+/// (ver `admin_response`).
+///
+/// `BATCH n` hace que las siguientes `n` líneas se tomen como mensajes `OP`
+/// de un mismo lote; el lote, al igual que `OPS`, se aplica de una sola vez
+/// (ver `apply_batch`) y se responde con un único `RESULTS`.
+///
+/// Las respuestas `OK` y `VALUE` incluyen la versión del estado
+/// (`OK @<version>`). `CAS`, `OPIF` y `OP ... @<version>` comparan y modifican
+/// el valor en un solo paso (sin soltar el lock, o con un compare-exchange
+/// con el backend atómico), de modo que ningún otro cliente puede
+/// intercalar una operación; si el valor o la versión no son los esperados
+/// se responde `ERROR "conflict" VALUE <actual> @<version>`.
+///
+/// Con el backend aggregate, un `OP` de suma o resta sin versión se acumula
+/// sin tomar el lock y se responde `OK` sin versión, que recién se le asigna
+/// al aplicarlo junto con el próximo `GET` u otra modificación.
+///
+/// # Retorno
+/// Retorna `Ok(())` si se procesó la línea (incluso si contenía errores lógicos
+/// que fueron notificados al cliente), o `Err(String)` si el estado es
+/// inaccesible o no se pudo escribir la respuesta.
+fn handle_line(
+    line: &str,
+    server: &Server,
+    session: &mut Session,
+    writer: &mut impl Write,
+) -> Result<(), String> {
+    if let Some(batch) = &mut session.batch {
+        batch.entries.push(parse_batch_entry(line));
+        return match session.batch.take_if(|b| b.entries.len() == b.size) {
+            Some(batch) => finish_batch(batch, server, writer),
+            None => Ok(()),
+        };
+    }
+
+    let message = parse_message(line);
+    let rate_limited = server.rate_limiter.as_ref().and_then(|limiter| {
+        let cost = match &message {
+            Ok(Message::Ops(ops)) => ops.len(),
+            Ok(Message::Lines(lines)) => lines.iter().map(Vec::len).sum(),
+            Ok(Message::Batch(size)) => *size,
+            _ => 1,
+        };
+        let wait = limiter.check_n(&session.rate_key(), tokens(cost)).err()?;
+        Some(rate_limit_error(wait))
+    });
+    if let Ok(Message::Batch(size)) = message {
+        if size > MAX_BATCH_SIZE {
+            // No se pueden descartar sus líneas sin leerlas todas: se corta la
+            // conexión para no aplicarlas como operaciones sueltas.
+            send_error("batch too large", writer)?;
+            return Err("batch too large".to_string());
+        }
+        // Las líneas del lote se consumen aunque se vaya a rechazar, para
+        // no interpretarlas como mensajes sueltos.
+        let batch = PendingBatch {
+            size,
+            entries: Vec::with_capacity(size),
+            rejected: rate_limited.or_else(|| auth_error(server, session)),
+        };
+        return match size {
+            0 => finish_batch(batch, server, writer),
+            _ => {
+                session.batch = Some(batch);
+                Ok(())
+            }
+        };
+    }
+    if let Some(motivo) = rate_limited {
+        return send_error(&motivo, writer);
+    }
+
+    match message {
+        Ok(Message::Hello {
+            version,
+            capabilities,
+        }) => hello(version, &capabilities, server, session, writer),
+        Ok(Message::Auth { user, password }) => {
+            authenticate(user, &password, server, session, writer)
+        }
+        Ok(
+            Message::Op(..)
+            | Message::Get
+            | Message::GetAt(_)
+            | Message::Cas { .. }
+            | Message::OpIf { .. }
+            | Message::Ops(_)
+            | Message::Lines(_),
+        ) if auth_error(server, session).is_some() => send_error("authentication required", writer),
+        Ok(Message::Snapshot(None) | Message::Restore(_)) => {
+            send_error("use the admin channel", writer)
+        }
+        Ok(Message::GetAt(_)) if !server.has_history() => {
+            send_error("history not available", writer)
+        }
+        Ok(Message::Op(op, version)) => {
+            if version.is_none()
+                && let Some(accumulated) = server.state.accumulate(&op)
+            {
+                return match accumulated {
+                    Ok(()) => send(&Message::Ok(None), writer),
+                    Err(motivo) => state_failure(motivo, writer),
+                };
+            }
+            update_state(server, writer, |state| {
+                if version.is_some_and(|v| v != state.version) {
+                    conflict(state)
+                } else {
+                    apply_operation(&op, state, server)
+                }
+            })
+        }
+        Ok(Message::Cas { expected, new }) => update_state(server, writer, |state| {
+            if state.value != expected {
+                conflict(state)
+            } else if new > server.config.arithmetic.numeric_type.max() {
+                Message::Err("operand out of range".to_string())
+            } else {
+                Message::Ok(Some(store_value(new, state)))
+            }
+        }),
+        Ok(Message::OpIf { expected, op }) => update_state(server, writer, |state| {
+            if state.value != expected {
+                conflict(state)
+            } else {
+                apply_operation(&op, state, server)
+            }
+        }),
+        Ok(Message::Ops(ops)) => {
+            let entries = ops.into_iter().map(|op| Ok((op, None))).collect();
+            apply_batch(singles(entries), server, writer)
+        }
+        Ok(Message::Lines(lines)) => apply_batch(line_units(lines), server, writer),
+        Ok(Message::Get) => update_state(server, writer, |state| {
+            Message::Value(state.value, Some(state.version))
+        }),
+        Ok(Message::GetAt(point)) => {
+            let found = match point {
+                PointInTime::Version(v) => lock_history(server, writer)?.at_version(v),
+                PointInTime::Time(t) => lock_history(server, writer)?.at_time(t),
+            };
+            match found {
+                Ok(entry) => send(&Message::Value(entry.value, Some(entry.version)), writer),
+                Err(motivo) => send_error(&motivo, writer),
+            }
+        }
+        Ok(_) => send_error("unexpected message", writer),
+        Err(e) if e == "batch too large" || e.starts_with(INVALID_GET_AT) => send_error(&e, writer),
+        Err(e) if e == UNKNOWN_MESSAGE => send_error(
+            &format!(
+                "unknown message; server speaks protocol version {} (send HELLO)",
+                PROTOCOL_VERSION
+            ),
+            writer,
+        ),
+        Err(_) => send_error("parsing error", writer),
+    }
+}
+
+/// Responde la negociación `HELLO` con la versión y las capacidades del
+/// servidor, o con un error si el cliente usa otra versión del protocolo.
+///
+/// De las capacidades opcionales (`NEGOTIATED`) se anuncian sólo las que
+/// también pidió el cliente; las demás (`auth`, `burst=`, `numeric=`,
+/// `overflow=`, `backend=`) describen al servidor y se anuncian siempre.
+///
+/// `HELLO` no requiere autenticación, para que el cliente pueda saber si
+/// debe enviar `AUTH`.
+fn hello(
+    version: u32,
+    capabilities: &[String],
+    server: &Server,
+    session: &Session,
+    writer: &mut impl Write,
+) -> Result<(), String> {
+    log::debug(&format!(
+        "{}: HELLO {} {}",
+        session.peer,
+        version,
+        capabilities.join(" ")
+    ));
+    send(&hello_reply(version, capabilities, server), writer)
+}
+
+/// Respuesta a `HELLO <version> <capabilities...>` (ver `hello`): el
+/// `HELLO` del servidor, o el `ERROR` si el cliente usa otra versión.
+fn hello_reply(version: u32, capabilities: &[String], server: &Server) -> Message {
+    if version != PROTOCOL_VERSION {
+        return Message::Err(format!(
+            "unsupported protocol version {}; server speaks version {}",
+            version, PROTOCOL_VERSION
+        ));
+    }
+    Message::Hello {
+        version: PROTOCOL_VERSION,
+        capabilities: server_capabilities(server)
+            .into_iter()
+            .filter(|c| !NEGOTIATED.contains(&c.as_str()) || capabilities.contains(c))
+            .collect(),
+    }
+}
+
+/// Capacidades opcionales, que se anuncian sólo si el cliente las pide en
+/// `HELLO` (ver `hello`).
+const NEGOTIATED: [&str; 4] = ["versions", "cas", "batch", "history"];
+
+/// Capacidades que el servidor anuncia en `HELLO`.
+///
+/// - `versions`: respuestas con `@<version>` y `OP ... @<version>` (no con
+///   el backend aggregate, que responde `OK` sin versión a las sumas y
+///   restas acumuladas).
+/// - `cas`: mensajes `CAS` y `OPIF`.
+/// - `batch`: mensajes `BATCH`, `OPS` y `LINES`.
+/// - `auth`: se exige `AUTH` antes de operar.
+/// - `burst=<n>`: capacidad del token bucket de cada cliente, si hay
+///   limitación de tasa; un `OPS`, `LINES` o `BATCH` de más de `n`
+///   operaciones se rechaza siempre (ver `handle_line`).
+/// - `history`: mensajes `GET AT` y, desde administración, `SNAPSHOT` y
+///   `RESTORE` (sólo con el backend mutex).
+/// - `numeric=<tipo>` y `overflow=<politica>`: reglas aritméticas.
+/// - `backend=<backend>`: cómo se sincroniza el valor, que limita las
+///   reglas (atomic sólo admite `u8` y `u16`; aggregate, `wrapping`).
+fn server_capabilities(server: &Server) -> Vec<String> {
+    let arithmetic = server.config.arithmetic;
+    let mut capabilities = Vec::new();
+    if server.state.backend() != Backend::Aggregate {
+        capabilities.push("versions".to_string());
+    }
+    capabilities.push("cas".to_string());
+    capabilities.push("batch".to_string());
+    if server.has_history() {
+        capabilities.push("history".to_string());
+    }
+    if server.users.is_some() {
+        capabilities.push("auth".to_string());
+    }
+    if let Some(limit) = server.config.rate_limit {
+        capabilities.push(format!("burst={}", limit.capacity));
+    }
+    capabilities.push(format!("numeric={}", arithmetic.numeric_type));
+    capabilities.push(format!("overflow={}", arithmetic.overflow));
+    capabilities.push(format!("backend={}", server.state.backend()));
+    capabilities
+}
+
+/// Tokens que consume un mensaje con `ops` operaciones: uno por operación,
+/// y al menos uno aunque no tenga ninguna.
+fn tokens(ops: usize) -> u32 {
+    u32::try_from(ops.max(1)).unwrap_or(u32::MAX)
+}
+
+/// Motivo del rechazo por tasa, con la espera que indicó el limitador.
+fn rate_limit_error(wait: Duration) -> String {
+    if wait == Duration::MAX {
+        return "batch exceeds rate limit".to_string();
+    }
+    format!("rate limited; retry after {} ms", wait.as_millis().max(1))
+}
+
+/// Retorna `Some("authentication required")` si el servidor exige
+/// autenticación y la sesión todavía no la hizo.
+fn auth_error(server: &Server, session: &Session) -> Option<String> {
+    (server.users.is_some() && session.user.is_none())
+        .then(|| "authentication required".to_string())
+}
+
+/// Separa las entradas en grupos de una, para `apply_batch`: cada operación
+/// de un `BATCH` o un `OPS` se aplica por su cuenta.
+fn singles(entries: Vec<BatchEntry>) -> Vec<Vec<BatchEntry>> {
+    entries.into_iter().map(|entry| vec![entry]).collect()
+}
+
+/// Agrupa las operaciones de cada línea de `LINES` para `apply_batch`.
+fn line_units(lines: Vec<Vec<Operation>>) -> Vec<Vec<BatchEntry>> {
+    lines
+        .into_iter()
+        .map(|ops| ops.into_iter().map(|op| Ok((op, None))).collect())
+        .collect()
+}
+
+/// Interpreta una línea de un `BATCH`, que debe ser un mensaje `OP`.
+fn parse_batch_entry(line: &str) -> BatchEntry {
+    match parse_message(line) {
+        Ok(Message::Op(op, version)) => Ok((op, version)),
+        Ok(_) => Err("unexpected message".to_string()),
+        Err(_) => Err("parsing error".to_string()),
+    }
+}
+
+/// Responde un lote completo: aplica sus operaciones o informa por qué se
+/// rechazó.
+fn finish_batch(
+    batch: PendingBatch,
+    server: &Server,
+    writer: &mut impl Write,
+) -> Result<(), String> {
+    match batch.rejected {
+        Some(motivo) => send_error(&motivo, writer),
+        None => apply_batch(singles(batch.entries), server, writer),
+    }
+}
+
+/// Aplica las operaciones de un lote en orden y de una sola vez (bajo una
+/// sola toma del lock, o con un solo compare-exchange con el backend
+/// atómico), y responde `RESULTS` con el resultado de cada una.
+///
+/// Cada grupo de `units` (una línea de `LINES`; en `BATCH` y `OPS`, cada
+/// operación sola) se aplica entero o no se aplica: si una de sus
+/// operaciones falla o tiene un conflicto de versión, el grupo no modifica
+/// el estado y cada una de sus operaciones se responde con ese error. Un
+/// grupo que falla no impide aplicar los siguientes. El estado se persiste
+/// una única vez al final del lote.
+fn apply_batch(
+    units: Vec<Vec<BatchEntry>>,
+    server: &Server,
+    writer: &mut impl Write,
+) -> Result<(), String> {
+    let arithmetic = server.config.arithmetic;
+    let applied = server.state.update(
+        |state| {
+            let mut versions = Vec::new();
+            let mut results = Vec::new();
+            for unit in &units {
+                let mut next = *state;
+                let mut applied = Vec::new();
+                let failure = unit.iter().find_map(|entry| match entry {
+                    Err(motivo) => Some(Message::Err(motivo.clone())),
+                    Ok((_, Some(v))) if *v != next.version => Some(conflict(&next)),
+                    Ok((op, _)) => match arithmetic.apply(next.value, op) {
+                        Ok(new_val) => {
+                            store_value(new_val, &mut next);
+                            applied.push(next);
+                            None
+                        }
+                        Err(motivo) => Some(Message::Err(motivo)),
+                    },
+                });
+                match failure {
+                    Some(failure) => results.extend(vec![failure; unit.len()]),
+                    None => {
+                        results.extend(applied.iter().map(|s| Message::Ok(Some(s.version))));
+                        versions.extend(applied);
+                        *state = next;
+                    }
+                }
+            }
+            (results, versions)
+        },
+        |state, (_, versions)| {
+            for version in versions {
+                record_history(version, server);
+            }
+            persist(state, server);
+            notify_watchers(state, server);
+        },
+    );
+    match applied {
+        Ok((results, _)) => send(&Message::Results(results), writer),
+        Err(motivo) => state_failure(motivo, writer),
+    }
+}
+
+/// Verifica las credenciales de `AUTH`.
+///
+/// Si el servidor no tiene archivo de usuarios, `AUTH` se acepta sin efecto.
+/// Si las credenciales son válidas, la sesión queda asociada al usuario.
+fn authenticate(
+    user: String,
+    password: &str,
+    server: &Server,
+    session: &mut Session,
+    writer: &mut impl Write,
+) -> Result<(), String> {
+    let Some(users) = &server.users else {
+        return send(&Message::Ok(None), writer);
+    };
+
+    if users.verify(&user, password) {
+        log::info(&format!("{}: autenticado como {}", session.peer, user));
+        session.user = Some(user);
+        send(&Message::Ok(None), writer)
+    } else {
+        log::warn(&format!(
+            "{}: autenticacion fallida para {}",
+            session.peer, user
+        ));
+        send_error("authentication failed", writer)
+    }
+}
+
+/// Aplica una operación matemática sobre una copia del estado (ver
+/// `SharedState::update`).
+///
+/// # Retorno
+/// `OK @<version>` si la operación se aplica, o el error lógico (división
+/// por cero, overflow, operando fuera de rango) con el estado sin cambios.
+fn apply_operation(op: &Operation, state: &mut State, server: &Server) -> Message {
+    match server.config.arithmetic.apply(state.value, op) {
+        Ok(new_val) => Message::Ok(Some(store_value(new_val, state))),
+        Err(motivo) => Message::Err(motivo),
+    }
+}
+
+/// Reemplaza el valor de una copia del estado, incrementa su versión y la
+/// retorna.
+fn store_value(new_val: u64, state: &mut State) -> u64 {
+    state.value = new_val;
+    state.version += 1;
+    state.version
+}
+
+/// Efectos de una modificación ya guardada: historial, persistencia y
+/// avisos a los observadores.
+///
+/// Un error al persistir se registra pero no revierte el cambio.
+fn commit(state: &State, server: &Server) {
+    record_history(state, server);
+    persist(state, server);
+    notify_watchers(state, server);
+}
+
+/// Persiste el estado si está configurado; un error sólo se registra.
+fn persist(state: &State, server: &Server) {
+    if let Some(path) = &server.config.persistence_path
+        && let Err(e) = persistence::store(Path::new(path), state.value, state.version)
+    {
+        log::error(&e);
+    }
+}
+
+/// Agrega el estado al historial, si el servidor lo lleva (ver
+/// `Server::history`).
+fn record_history(state: &State, server: &Server) {
+    if !server.has_history() {
+        return;
+    }
+    if let Ok(mut history) = server.history.lock() {
+        history.record(history::Entry {
+            value: state.value,
+            version: state.version,
+            time: unix_time(),
+        });
+    }
+}
+
+/// Primer identificador de instantánea: los microsegundos desde la época
+/// Unix al iniciar. Así no repite los de una ejecución anterior, salvo que
+/// ésta haya tomado en promedio más de una instantánea por microsegundo.
+fn first_snapshot() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|d| u64::try_from(d.as_micros()).ok())
+        .unwrap_or(1)
+}
+
+/// Segundos desde la época Unix; `0` si el reloj está antes de ella.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Avisa el nuevo estado a los observadores (ver `Server::watch`) y da de
+/// baja a los que ya cerraron su canal.
+///
+/// Con el backend mutex se llama con el lock del estado tomado, así los
+/// avisos llegan en el orden de las versiones; con el aggregate, además, las
+/// sumas y restas acumuladas se avisan recién al aplicarse, todas juntas.
+/// Con el atómico un hilo puede
+/// avisar después que otro con una versión posterior: ese aviso se descarta,
+/// así cada observador recibe versiones crecientes y siempre la última. Un
+/// lote avisa una sola vez, con su estado final.
+fn notify_watchers(state: &State, server: &Server) {
+    if let Ok(mut watchers) = server.watchers.lock() {
+        watchers.retain_mut(|watcher| {
+            if watcher.last_version.is_some_and(|v| v >= state.version) {
+                return true;
+            }
+            watcher.last_version = Some(state.version);
+            watcher.sender.send((state.value, state.version)).is_ok()
+        });
+    }
+}
+
+/// Respuesta a un valor o una versión que no eran los esperados.
+fn conflict(state: &State) -> Message {
+    Message::Conflict(state.value, Some(state.version))
+}
+
+/// Envía un mensaje de error con el motivo dado al cliente.
+fn send_error(motivo: &str, writer: &mut impl Write) -> Result<(), String> {
+    send(&Message::Err(motivo.to_string()), writer)
+}
+
+/// Escribe un mensaje terminado en salto de línea.
+///
+/// # Errores
+/// Retorna `Err(String)` si la escritura falla, por ejemplo porque venció
+/// el timeout de escritura con un cliente que no lee sus respuestas.
+fn send(msg: &Message, writer: &mut impl Write) -> Result<(), String> {
+    writer
+        .write_all(format!("{}\n", msg).as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+/// Modifica el estado con `f` (ver `SharedState::update`), con los efectos
+/// de `commit`, y envía al cliente la respuesta que retorna `f`.
+fn update_state(
+    server: &Server,
+    writer: &mut impl Write,
+    f: impl FnMut(&mut State) -> Message,
+) -> Result<(), String> {
+    match server.state.update(f, |state, _| commit(state, server)) {
+        Ok(response) => send(&response, writer),
+        Err(motivo) => state_failure(motivo, writer),
+    }
+}
+
+/// Avisa al cliente que no se pudo acceder al estado y retorna el error,
+/// que cierra la conexión.
+fn state_failure<T>(motivo: String, writer: &mut impl Write) -> Result<T, String> {
+    let _ = send_error(&motivo, writer);
+    Err(motivo)
+}
+
+/// Toma el lock del historial; si está envenenado, se avisa al cliente.
+fn lock_history<'a>(
+    server: &'a Server,
+    writer: &mut impl Write,
+) -> Result<std::sync::MutexGuard<'a, Timeline>, String> {
+    server.history.lock().map_err(|_| {
+        let _ = writer.write_all(b"ERROR \"Historial inaccesible\"\n");
+        "Historial inaccesible".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calculadora_distribuida::calculator::{Arithmetic, NumericType, OverflowPolicy};
+    use calculadora_distribuida::linearizability::Call;
+    use calculadora_distribuida::linearizability::{CalculatorModel, History, Model, check};
+    use calculadora_distribuida::operator::Operator;
+    use calculadora_distribuida::simulation::{self, NetworkFaults, XorShift};
+    use calculadora_distribuida::transport::Stream;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::TryRecvError;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Arranca el servidor en un hilo y devuelve la dirección
+    fn start_server() -> String {
+        start_server_with(ServerConfig::default())
+    }
+
+    /// Arranca el servidor con la configuración dada y devuelve la dirección
+    fn start_server_with(config: ServerConfig) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap(); // puerto aleatorio
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // El listener ya está escuchando: las conexiones quedan en cola
+        // hasta que el hilo empiece a aceptarlas.
+        let pool = worker_pool(&config);
+        let server = Server::new(config).unwrap();
+        thread::spawn(move || {
+            run_server(
+                listener,
+                Arc::new(server),
+                pool,
+                handle_connection,
+                busy_lines,
+            );
+        });
+        addr
+    }
+
+    #[test]
+    fn test_server_ok_response() {
+        let addr = start_server();
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        // Enviar operación válida
+        stream.write_all(b"OP + 10\n").unwrap();
+
+        let mut reader = BufReader::new(&mut stream);
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+
+        assert_eq!(response.trim(), "OK @1");
+    }
+
+    #[test]
+    fn test_server_value_response() {
+        let addr = start_server();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(&mut stream);
+
+        // Enviar operación
+        reader.get_mut().write_all(b"OP + 5\n").unwrap();
+        let mut resp1 = String::new();
+        reader.read_line(&mut resp1).unwrap();
+        assert_eq!(resp1.trim(), "OK @1");
+
+        // Enviar GET usando la misma referencia
+        reader.get_mut().write_all(b"GET\n").unwrap();
+        let mut resp2 = String::new();
+        reader.read_line(&mut resp2).unwrap();
+        assert_eq!(resp2.trim(), "VALUE 5 @1");
+    }
+
+    #[test]
+    fn test_server_error_response() {
+        let addr = start_server();
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        // Enviar operación inválida (división por cero)
+        stream.write_all(b"OP / 0\n").unwrap();
+
+        let mut reader = BufReader::new(&mut stream);
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+
+        assert!(response.trim().starts_with("ERROR"));
+    }
+
+    #[test]
+    fn test_server_line_too_long() {
+        let mut config = ServerConfig::default();
+        config.limits.max_line_length = 8;
+        let addr = start_server_with(config);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"OP + 1 + 1 + 1 + 1\n").unwrap();
+
+        let mut reader = BufReader::new(&mut stream);
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "ERROR \"line too long\"");
+
+        // El servidor cierra la conexión
+        let mut rest = String::new();
+        assert_eq!(reader.read_line(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_server_idle_timeout() {
+        let mut config = ServerConfig::default();
+        config.limits.read_timeout = Some(Duration::from_millis(100));
+        let addr = start_server_with(config);
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        let mut reader = BufReader::new(&mut stream);
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "ERROR \"idle timeout\"");
+
+        let mut rest = String::new();
+        assert_eq!(reader.read_line(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_server_line_timeout_stops_drip_feeding() {
+        let mut config = ServerConfig::default();
+        config.limits.read_timeout = Some(Duration::from_millis(200));
+        let addr = start_server_with(config);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        // Un byte cada 50 ms nunca deja vencer el timeout de lectura.
+        let dripping = thread::spawn(move || {
+            for _ in 0..40 {
+                if writer.write_all(b"1").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let start = Instant::now();
+        let mut reader = BufReader::new(&mut stream);
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response.trim(), "ERROR \"idle timeout\"");
+        assert!(start.elapsed() < Duration::from_secs(1));
+        dripping.join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = to_args(&[
+            "127.0.0.1:0",
+            "--read-timeout",
+            "0",
+            "--max-line-length",
+            "64",
+            "--numeric-type",
+            "u16",
+        ]);
+        let config = parse_args(&args).unwrap();
+        assert_eq!(config.address.as_deref(), Some("127.0.0.1:0"));
+        assert_eq!(config.limits.read_timeout, None);
+        assert_eq!(config.limits.max_line_length, 64);
+        assert_eq!(config.arithmetic.numeric_type, NumericType::U16);
+
+        let config = parse_args(&to_args(&["--address", "0.0.0.0:1", "--threads", "2"])).unwrap();
+        assert_eq!(config.address.as_deref(), Some("0.0.0.0:1"));
+        assert_eq!(config.threads, Some(2));
+
+        assert!(parse_args(&to_args(&["a", "--foo", "1"])).is_err());
+        assert!(parse_args(&to_args(&["a", "--read-timeout"])).is_err());
+        let err = parse_args(&to_args(&["a", "--overflow", "nope"])).unwrap_err();
+        assert!(err.starts_with("--overflow:"), "{}", err);
+        assert!(start(ServerConfig::default()).is_err());
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_server_rate_limited() {
+        let config = ServerConfig {
+            rate_limit: Some(RateLimitConfig {
+                capacity: 2,
+                refill_per_sec: 0.5,
+            }),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(&mut stream);
+
+        reader
+            .get_mut()
+            .write_all(b"OP + 1\nGET\nOP + 1\n")
+            .unwrap();
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            responses.push(line.trim().to_string());
+        }
+
+        assert_eq!(responses[0], "OK @1");
+        assert_eq!(responses[1], "VALUE 1 @1");
+        assert!(
+            responses[2].starts_with("ERROR \"rate limited; retry after"),
+            "{}",
+            responses[2]
+        );
+    }
+
+    #[test]
+    fn test_server_rate_limits_each_batch_op() {
+        let config = ServerConfig {
+            rate_limit: Some(RateLimitConfig {
+                capacity: 3,
+                refill_per_sec: 0.01,
+            }),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream
+            .write_all(b"OPS + 1, + 1, + 1, + 1\nOPS + 1, + 1\nBATCH 2\nOP + 1\nOP + 1\nOP + 1\n")
+            .unwrap();
+        let responses: Vec<String> = (0..4)
+            .map(|_| {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line.trim().to_string()
+            })
+            .collect();
+
+        assert_eq!(responses[0], "ERROR \"batch exceeds rate limit\"");
+        assert_eq!(responses[1], "RESULTS OK @1; OK @2");
+        // quedaba un token: el lote de dos se rechaza entero
+        assert!(
+            responses[2].starts_with("ERROR \"rate limited; retry after"),
+            "{}",
+            responses[2]
+        );
+        assert_eq!(responses[3], "OK @3");
+    }
+
+    #[test]
+    fn test_hello_announces_burst() {
+        let config = ServerConfig {
+            rate_limit: Some(RateLimitConfig {
+                capacity: 3,
+                refill_per_sec: 1.0,
+            }),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        assert_eq!(
+            exchange(&addr, &["HELLO 1 batch"]),
+            ["HELLO 1 batch burst=3 numeric=u8 overflow=wrapping backend=mutex"]
+        );
+    }
+
+    /// Envía cada línea y devuelve las respuestas recibidas
+    fn exchange(addr: &str, lines: &[&str]) -> Vec<String> {
+        let mut stream = Stream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        lines
+            .iter()
+            .map(|l| {
+                stream.write_all(format!("{}\n", l).as_bytes()).unwrap();
+                let mut resp = String::new();
+                reader.read_line(&mut resp).unwrap();
+                resp.trim().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_server_numeric_type_and_overflow() {
+        let mut config = ServerConfig::default();
+        config.arithmetic.numeric_type = NumericType::U16;
+        config.arithmetic.overflow = OverflowPolicy::Error;
+        let addr = start_server_with(config);
+
+        let responses = exchange(&addr, &["OP + 300", "OP * 1000", "OP + 70000", "GET"]);
+        assert_eq!(
+            responses,
+            [
+                "OK @1",
+                "ERROR \"overflow\"",
+                "ERROR \"operand out of range\"",
+                "VALUE 300 @1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_auth_required() {
+        let path = std::env::temp_dir().join(format!("calc_users_{}.txt", std::process::id()));
+        std::fs::write(&path, "ana:secreto\n").unwrap();
+        let config = ServerConfig {
+            auth_file: Some(path.to_string_lossy().into_owned()),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        let _ = std::fs::remove_file(&path);
+
+        let responses = exchange(
+            &addr,
+            &[
+                "GET",
+                "GET AT @0",
+                "AUTH ana mal",
+                "AUTH ana secreto",
+                "OP + 2",
+                "GET",
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                "ERROR \"authentication required\"",
+                "ERROR \"authentication required\"",
+                "ERROR \"authentication failed\"",
+                "OK",
+                "OK @1",
+                "VALUE 2 @1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_persistence() {
+        let path = std::env::temp_dir().join(format!("calc_state_{}.txt", std::process::id()));
+        std::fs::write(&path, "7\n3\n").unwrap();
+        let config = ServerConfig {
+            persistence_path: Some(path.to_string_lossy().into_owned()),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+
+        let responses = exchange(&addr, &["GET", "OP + 1"]);
+        assert_eq!(responses, ["VALUE 7 @3", "OK @4"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "8\n4\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_server_thread_pool() {
+        let config = ServerConfig {
+            threads: Some(2),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        assert_eq!(exchange(&addr, &["OP + 1"]), ["OK @1"]);
+        assert_eq!(exchange(&addr, &["OP + 1", "GET"]), ["OK @2", "VALUE 2 @2"]);
+    }
+
+    #[test]
+    fn test_server_thread_pool_busy() {
+        let config = ServerConfig {
+            threads: Some(1),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        let read = |stream: &Stream| {
+            let mut resp = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut resp)
+                .unwrap();
+            resp.trim().to_string()
+        };
+
+        // el único hilo atiende a `first` y `queued` espera su turno
+        let mut first = Stream::connect(&addr).unwrap();
+        first.write_all(b"OP + 1\n").unwrap();
+        assert_eq!(read(&first), "OK @1");
+        let mut queued = Stream::connect(&addr).unwrap();
+        queued.write_all(b"GET\n").unwrap();
+
+        let rejected = Stream::connect(&addr).unwrap();
+        assert_eq!(read(&rejected), "ERROR \"server busy\"");
+
+        drop(first);
+        assert_eq!(read(&queued), "VALUE 1 @1");
+    }
+
+    #[test]
+    fn test_server_cas() {
+        let addr = start_server();
+        let responses = exchange(&addr, &["CAS 0 10", "CAS 0 20", "CAS 10 300", "GET"]);
+        assert_eq!(
+            responses,
+            [
+                "OK @1",
+                "ERROR \"conflict\" VALUE 10 @1",
+                "ERROR \"operand out of range\"",
+                "VALUE 10 @1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_opif() {
+        let addr = start_server();
+        let responses = exchange(&addr, &["OPIF 0 + 5", "OPIF 0 + 5", "OPIF 5 / 0", "GET"]);
+        assert_eq!(
+            responses,
+            [
+                "OK @1",
+                "ERROR \"conflict\" VALUE 5 @1",
+                "ERROR \"division by zero\"",
+                "VALUE 5 @1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_cas_is_atomic() {
+        // Varios clientes incrementan con CAS reintentando ante conflictos;
+        // si CAS no fuera atómico se perderían incrementos.
+        let mut config = ServerConfig::default();
+        config.arithmetic.numeric_type = NumericType::U64;
+        let addr = start_server_with(config);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let addr = addr.clone();
+                thread::spawn(move || {
+                    let mut stream = TcpStream::connect(&addr).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut current = 0u64;
+                    let mut done = 0;
+                    while done < 25 {
+                        let msg = format!("CAS {} {}\n", current, current + 1);
+                        stream.write_all(msg.as_bytes()).unwrap();
+                        let mut resp = String::new();
+                        reader.read_line(&mut resp).unwrap();
+                        match parse_message(&resp).unwrap() {
+                            Message::Ok(_) => {
+                                current += 1;
+                                done += 1;
+                            }
+                            Message::Conflict(actual, _) => current = actual,
+                            other => panic!("respuesta inesperada: {:?}", other),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(exchange(&addr, &["GET"]), ["VALUE 100 @100"]);
+    }
+
+    #[test]
+    fn test_server_ops() {
+        let addr = start_server();
+        let responses = exchange(&addr, &["OPS + 5, / 0, * 2", "OPS + 1, % 2", "GET"]);
+        assert_eq!(
+            responses,
+            [
+                "RESULTS OK @1; ERROR \"division by zero\"; OK @2",
+                "ERROR \"parsing error\"",
+                "VALUE 10 @2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_lines_apply_each_line_whole() {
+        let mut config = ServerConfig::default();
+        config.arithmetic.overflow = OverflowPolicy::Error;
+        let addr = start_server_with(config);
+        // Desde 40, el * 7 desborda y la primera línea no se aplica; la
+        // tercera falla en el / 0 y tampoco deja aplicado su * 2.
+        let responses = exchange(
+            &addr,
+            &["OP + 40", "LINES * 7, + 1; + 2; * 2, / 0, + 1", "GET"],
+        );
+        assert_eq!(
+            responses,
+            [
+                "OK @1",
+                "RESULTS ERROR \"overflow\"; ERROR \"overflow\"; OK @2; \
+                 ERROR \"division by zero\"; ERROR \"division by zero\"; \
+                 ERROR \"division by zero\"",
+                "VALUE 42 @2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_get_at() {
+        let config = ServerConfig {
+            history_size: Some(3),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        let responses = exchange(
+            &addr,
+            &[
+                "OP + 5",
+                "SNAPSHOT",
+                "OPS * 2, + 1, + 1",
+                "GET AT @1",
+                "GET AT @2",
+                "GET AT @9",
+                "GET AT @0",
+                "GET AT 0",
+                "GET AT 18446744073709551615",
+                "GET AT",
+                "GET AT @x",
+                "GET AT yesterday",
+                "RESTORE 1",
+                "SNAPSHOT 1",
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                "OK @1",
+                "ERROR \"use the admin channel\"",
+                "RESULTS OK @2; OK @3; OK @4",
+                "ERROR \"version no longer in history\"",
+                "VALUE 10 @2",
+                "ERROR \"version not reached\"",
+                "ERROR \"version no longer in history\"",
+                "ERROR \"time before history\"",
+                "VALUE 12 @4",
+                "ERROR \"invalid GET AT argument: missing; expected @<version> or <seconds>\"",
+                "ERROR \"invalid GET AT argument '@x'; expected @<version> or <seconds>\"",
+                "ERROR \"invalid GET AT argument 'yesterday'; expected @<version> or <seconds>\"",
+                "ERROR \"use the admin channel\"",
+                "ERROR \"unexpected message\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_batch() {
+        let addr = start_server();
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream
+            .write_all(b"BATCH 4\nOP + 5\nOP * 2 @0\nGET\nOP - 1 @1\nBATCH 0\nGET\n")
+            .unwrap();
+
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            lines.push(line.trim().to_string());
+        }
+        assert_eq!(
+            lines,
+            [
+                "RESULTS OK @1; ERROR \"conflict\" VALUE 5 @1; \
+                 ERROR \"unexpected message\"; OK @2",
+                "RESULTS",
+                "VALUE 4 @2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_batch_too_large() {
+        let addr = start_server();
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream
+            .write_all(format!("BATCH {}\nOP + 1\n", MAX_BATCH_SIZE + 1).as_bytes())
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line.trim(), "ERROR \"batch too large\"");
+        let mut rest = String::new();
+        assert_eq!(reader.read_line(&mut rest).unwrap(), 0);
+        assert_eq!(exchange(&addr, &["GET"]), ["VALUE 0 @0"]);
+    }
+
+    #[test]
+    fn test_server_batch_requires_auth() {
+        let path =
+            std::env::temp_dir().join(format!("calc_batch_users_{}.txt", std::process::id()));
+        std::fs::write(&path, "ana:secreto\n").unwrap();
+        let config = ServerConfig {
+            auth_file: Some(path.to_string_lossy().into_owned()),
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config);
+        let _ = std::fs::remove_file(&path);
+
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream
+            .write_all(b"BATCH 2\nOP + 1\nOP + 1\nAUTH ana secreto\nGET\n")
+            .unwrap();
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            lines.push(line.trim().to_string());
+        }
+        assert_eq!(
+            lines,
+            ["ERROR \"authentication required\"", "OK", "VALUE 0 @0"]
+        );
+    }
+
+    #[test]
+    fn test_server_hello() {
+        let mut config = ServerConfig::default();
+        config.arithmetic.numeric_type = NumericType::U32;
+        let addr = start_server_with(config);
+        let responses = exchange(
+            &addr,
+            &[
+                "HELLO 1 versions cas batch history",
+                "HELLO 1 batch other",
+                "HELLO 2",
+                "FOO 1",
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                "HELLO 1 versions cas batch history numeric=u32 overflow=wrapping backend=mutex",
+                "HELLO 1 batch numeric=u32 overflow=wrapping backend=mutex",
+                "ERROR \"unsupported protocol version 2; server speaks version 1\"",
+                "ERROR \"unknown message; server speaks protocol version 1 (send HELLO)\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_history_is_linearizable() {
+        for backend in [Backend::Mutex, Backend::Atomic] {
+            let config = ServerConfig {
+                backend,
+                ..ServerConfig::default()
+            };
+            check_linearizable(&start_server_with(config), &CalculatorModel::default());
+        }
+        let config = ServerConfig {
+            backend: Backend::Aggregate,
+            ..ServerConfig::default()
+        };
+        check_linearizable(&start_server_with(config), &AggregateModel);
+    }
+
+    /// `CalculatorModel`, salvo que las sumas y restas sin versión se
+    /// responden `OK` sin versión, como con el backend aggregate.
+    struct AggregateModel;
+
+    impl Model for AggregateModel {
+        type State = (u64, u64);
+        type Input = Message;
+        type Output = Message;
+
+        fn init(&self) -> Self::State {
+            CalculatorModel::default().init()
+        }
+
+        fn step(&self, state: &Self::State, input: &Message) -> (Self::State, Message) {
+            let (next, output) = CalculatorModel::default().step(state, input);
+            match (input, output) {
+                (Message::Op(op, None), Message::Ok(_))
+                    if matches!(op.op, Operator::Add | Operator::Sub) =>
+                {
+                    (next, Message::Ok(None))
+                }
+                (_, output) => (next, output),
+            }
+        }
+    }
+
+    /// Varios clientes envían una mezcla de mensajes a `addr` y se verifica
+    /// que la historia resultante sea linealizable respecto de `model`.
+    fn check_linearizable(
+        addr: &str,
+        model: &impl Model<State = (u64, u64), Input = Message, Output = Message>,
+    ) {
+        let history = Arc::new(History::new());
+
+        let handles: Vec<_> = (0..4)
+            .map(|client| {
+                let addr = addr.to_string();
+                let history = Arc::clone(&history);
+                thread::spawn(move || {
+                    let mut stream = TcpStream::connect(&addr).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut seen = (0, 0);
+                    for i in 0..40 {
+                        let n = (client * 7 + i) as u64 % 5 + 1;
+                        let request = match (client + i) % 8 {
+                            0 => format!("OP + {}", n),
+                            1 => format!("OP * {}", n),
+                            2 => format!("OP - {} @{}", n, seen.1),
+                            3 => format!("CAS {} {}", seen.0, n * 10),
+                            4 => format!("OPIF {} / {}", seen.0, n),
+                            5 => format!("OPS + {}, - 1", n),
+                            6 => format!("LINES * {}, - 1; + {}", n, n),
+                            _ => "GET".to_string(),
+                        };
+                        let invoke = history.now();
+                        stream
+                            .write_all(format!("{}\n", request).as_bytes())
+                            .unwrap();
+                        let mut resp = String::new();
+                        reader.read_line(&mut resp).unwrap();
+                        let response = parse_message(&resp).unwrap();
+                        if let Message::Value(v, Some(ver)) | Message::Conflict(v, Some(ver)) =
+                            response
+                        {
+                            seen = (v, ver);
+                        }
+                        history.record(client, parse_message(&request).unwrap(), response, invoke);
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let calls = Arc::try_unwrap(history).unwrap().into_calls();
+        assert_eq!(calls.len(), 160);
+        if let Err(e) = check(model, &calls) {
+            panic!("{}", e);
+        }
+    }
+
+    #[test]
+    fn test_server_atomic_backend() {
+        let config = ServerConfig {
+            backend: Backend::Atomic,
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config.clone());
+        let responses = exchange(
+            &addr,
+            &[
+                "HELLO 1 versions cas batch history",
+                "OP + 250",
+                "OP + 10 @0",
+                "OP * 2 @1",
+                "CAS 244 7",
+                "OPS + 1, / 0",
+                "GET AT @1",
+                "GET",
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                "HELLO 1 versions cas batch numeric=u8 overflow=wrapping backend=atomic",
+                "OK @1",
+                "ERROR \"conflict\" VALUE 250 @1",
+                "OK @2",
+                "OK @3",
+                "RESULTS OK @4; ERROR \"division by zero\"",
+                "ERROR \"history not available\"",
+                "VALUE 8 @4"
+            ]
+        );
+
+        let mut wide = config.clone();
+        wide.arithmetic.numeric_type = NumericType::U32;
+        assert!(Server::new(wide).is_err());
+        let persistent = ServerConfig {
+            persistence_path: Some("estado.txt".to_string()),
+            ..config
+        };
+        assert!(Server::new(persistent).is_err());
+    }
+
+    #[test]
+    fn test_server_aggregate_backend() {
+        let config = ServerConfig {
+            backend: Backend::Aggregate,
+            ..ServerConfig::default()
+        };
+        let addr = start_server_with(config.clone());
+        let responses = exchange(
+            &addr,
+            &[
+                "OP + 250",
+                "OP - 10",
+                "OP + 300",
+                "OP * 2",
+                "OP + 20",
+                "OP + 1 @3",
+                "GET",
+                "GET AT @1",
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                "OK",
+                "OK",
+                "ERROR \"operand out of range\"",
+                "OK @3",
+                "OK",
+                "ERROR \"conflict\" VALUE 244 @4",
+                "VALUE 244 @4",
+                "ERROR \"history not available\""
+            ]
+        );
+
+        let saturating = ServerConfig {
+            arithmetic: Arithmetic {
+                overflow: OverflowPolicy::Saturating,
+                ..Arithmetic::default()
+            },
+            ..config
+        };
+        assert!(Server::new(saturating).is_err());
+    }
+
+    #[test]
+    fn test_server_aggregate_hello_and_flush() {
+        let config = ServerConfig {
+            backend: Backend::Aggregate,
+            ..ServerConfig::default()
+        };
+        let server = Server::new(config.clone()).unwrap();
+        let addr = start_server_with(config);
+        assert_eq!(
+            exchange(&addr, &["HELLO 1 versions cas batch"]),
+            ["HELLO 1 cas batch numeric=u8 overflow=wrapping backend=aggregate"]
+        );
+
+        // el primer aviso ya incluye lo acumulado
+        let plus = Operation {
+            op: Operator::Add,
+            operand: 5,
+        };
+        assert_eq!(server.state.accumulate(&plus), Some(Ok(())));
+        flush_pending(&server);
+        let (id, updates) = server.watch().unwrap();
+        assert_eq!(updates.try_recv(), Ok((5, 1)));
+
+        assert_eq!(server.state.accumulate(&plus), Some(Ok(())));
+        assert!(updates.try_recv().is_err());
+        flush_pending(&server);
+        assert_eq!(updates.try_recv(), Ok((10, 2)));
+        server.unwatch(id);
+    }
+
+    #[test]
+    fn test_server_op_with_version() {
+        let addr = start_server();
+        let responses = exchange(
+            &addr,
+            &["OP + 5 @0", "OP + 5 @0", "OP * 2 @1", "OP / 0 @2", "GET"],
+        );
+        assert_eq!(
+            responses,
+            [
+                "OK @1",
+                "ERROR \"conflict\" VALUE 5 @1",
+                "OK @2",
+                "ERROR \"division by zero\"",
+                "VALUE 10 @2"
+            ]
+        );
+    }
+
+    /// Guiones aleatorios pero reproducibles para `clients` clientes.
+    fn simulation_scripts(clients: usize, requests: usize, seed: u64) -> Vec<Vec<String>> {
+        let mut rng = XorShift::new(seed);
+        (0..clients)
+            .map(|_| {
+                (0..requests)
+                    .map(|_| {
+                        let n = rng.up_to(4) + 1;
+                        let small = rng.up_to(8);
+                        match rng.up_to(6) {
+                            0 => format!("OP + {}", n),
+                            1 => format!("OP * {}", n),
+                            2 => format!("OP - {} @{}", n, small),
+                            3 => format!("CAS {} {}", small, n * 10),
+                            4 => format!("OPIF {} / {}", small, n),
+                            5 => format!("OPS + {}, - 1", n),
+                            _ => "GET".to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn simulate(seed: u64, faults: &NetworkFaults) -> Vec<Call<String, String>> {
+        let server = Server::new(ServerConfig::default()).unwrap();
+        simulation::run(&server, &simulation_scripts(4, 15, seed), faults, seed)
+    }
+
+    #[test]
+    fn test_server_simulation_is_linearizable() {
+        let faults = NetworkFaults {
+            max_delay: 20,
+            drop_rate: 0.05,
+            reset_rate: 0.05,
+        };
+        for seed in 0..20 {
+            let calls: Vec<_> = simulate(seed, &faults)
+                .into_iter()
+                .map(|call| Call {
+                    client: call.client,
+                    input: parse_message(&call.input).unwrap(),
+                    output: call.output.map(|o| parse_message(&o).unwrap()),
+                    invoke: call.invoke,
+                    response: call.response,
+                })
+                .collect();
+            assert_eq!(calls.len(), 60);
+            if let Err(e) = check(&CalculatorModel::default(), &calls) {
+                panic!("semilla {}: {}", seed, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_server_simulation_is_reproducible() {
+        let faults = NetworkFaults {
+            max_delay: 20,
+            drop_rate: 0.1,
+            reset_rate: 0.1,
+        };
+        assert_eq!(simulate(9, &faults), simulate(9, &faults));
+    }
+
+    #[test]
+    fn test_start_stdio() {
+        let config = parse_args(&to_args(&["stdio"])).unwrap();
+        let (listeners, _server) = start(config).unwrap();
+        assert!(listeners.lines.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server_unix_socket() {
+        let path = std::env::temp_dir().join(format!("calc_server_{}.sock", std::process::id()));
+        let address = format!("unix:{}", path.display());
+        let config = parse_args(&to_args(&[&address])).unwrap();
+        let (listeners, server) = start(config).unwrap();
+        let listener = listeners.lines.unwrap();
+        thread::spawn(move || {
+            run_server(
+                listener,
+                Arc::new(server),
+                None,
+                handle_connection,
+                busy_lines,
+            )
+        });
+
+        assert_eq!(
+            exchange(&address, &["OP + 4", "GET"]),
+            ["OK @1", "VALUE 4 @1"]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Arranca la pasarela HTTP con la configuración dada y devuelve la
+    /// dirección.
+    fn start_http_with(config: ServerConfig) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let pool = worker_pool(&config);
+        let server = Arc::new(Server::new(config).unwrap());
+        thread::spawn(move || run_server(listener, server, pool, handle_http, busy_http));
+        addr
+    }
+
+    /// Envía un pedido HTTP y devuelve el código y el cuerpo de la respuesta.
+    fn http_request(
+        addr: &str,
+        method: &str,
+        path: &str,
+        extra: &str,
+        body: &str,
+    ) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            extra,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    #[test]
+    fn test_http_gateway() {
+        let addr = start_http_with(ServerConfig::default());
+        let ops = |body: &str| http_request(&addr, "POST", "/ops", "", body);
+
+        assert_eq!(
+            ops(r#"{"op": "+", "operand": 5}"#),
+            (200, r#"{"version":1}"#.to_string())
+        );
+        assert_eq!(
+            ops(r#"{"op": "*", "operand": 2, "version": 0}"#),
+            (
+                409,
+                r#"{"error":"conflict","value":5,"version":1}"#.to_string()
+            )
+        );
+        assert_eq!(
+            ops(r#"{"op": "/", "operand": 0}"#),
+            (422, r#"{"error":"division by zero"}"#.to_string())
+        );
+        assert_eq!(ops("{").0, 400);
+        assert_eq!(
+            http_request(
+                &addr,
+                "POST",
+                "/batch",
+                "",
+                r#"{"ops": [{"op": "*", "operand": 3}, {"op": "-", "operand": 1}]}"#
+            ),
+            (
+                200,
+                r#"{"results":[{"version":2},{"version":3}]}"#.to_string()
+            )
+        );
+        assert_eq!(
+            http_request(&addr, "GET", "/value", "", ""),
+            (200, r#"{"value":14,"version":3}"#.to_string())
+        );
+        assert_eq!(http_request(&addr, "GET", "/", "", "").0, 404);
+    }
+
+    #[test]
+    fn test_http_gateway_keep_alive() {
+        let addr = start_http_with(ServerConfig::default());
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        for expected in ["{\"value\":0,\"version\":0}", "{\"value\":0,\"version\":0}"] {
+            stream
+                .write_all(b"GET /value HTTP/1.1\r\nHost: test\r\n\r\n")
+                .unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(n) = line.strip_prefix("Content-Length: ") {
+                    length = n.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+            assert_eq!(String::from_utf8(body).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_http_gateway_auth() {
+        let path = std::env::temp_dir().join(format!("calc_http_users_{}.txt", std::process::id()));
+        std::fs::write(&path, "ana:secreto\n").unwrap();
+        let config = ServerConfig {
+            auth_file: Some(path.to_string_lossy().into_owned()),
+            ..ServerConfig::default()
+        };
+        let addr = start_http_with(config);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(http_request(&addr, "GET", "/value", "", "").0, 401);
+        // "ana:secreto" y "ana:otra"
+        let ok = "Authorization: Basic YW5hOnNlY3JldG8=\r\n";
+        let bad = "Authorization: Basic YW5hOm90cmE=\r\n";
+        assert_eq!(
+            http_request(&addr, "GET", "/value", ok, ""),
+            (200, r#"{"value":0,"version":0}"#.to_string())
+        );
+        assert_eq!(
+            http_request(&addr, "GET", "/value", bad, ""),
+            (401, r#"{"error":"authentication failed"}"#.to_string())
+        );
+    }
+
+    /// Abre una conexión WebSocket a `/ws` y retorna el stream para enviar
+    /// y el lector de los frames del servidor.
+    fn ws_connect(addr: &str) -> (TcpStream, BufReader<TcpStream>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(
+            stream,
+            "GET /ws HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 101"), "{}", line);
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        (stream, reader)
+    }
+
+    /// Envía un mensaje de texto enmascarado, como un cliente.
+    fn ws_send(stream: &mut TcpStream, text: &str) {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        stream.write_all(&frame).unwrap();
+    }
+
+    /// Lee un frame del servidor (sin máscara) y retorna su opcode y datos.
+    fn ws_recv(reader: &mut BufReader<TcpStream>) -> (u8, Vec<u8>) {
+        let mut head = [0; 2];
+        std::io::Read::read_exact(reader, &mut head).unwrap();
+        let len = match head[1] {
+            126 => {
+                let mut ext = [0; 2];
+                std::io::Read::read_exact(reader, &mut ext).unwrap();
+                u16::from_be_bytes(ext) as usize
+            }
+            n => n as usize,
+        };
+        let mut payload = vec![0; len];
+        std::io::Read::read_exact(reader, &mut payload).unwrap();
+        (head[0] & 0x0F, payload)
+    }
+
+    fn ws_recv_text(reader: &mut BufReader<TcpStream>) -> String {
+        let (opcode, payload) = ws_recv(reader);
+        assert_eq!(opcode, 1);
+        String::from_utf8(payload).unwrap()
+    }
+
+    #[test]
+    fn test_websocket() {
+        let addr = start_http_with(ServerConfig::default());
+        let (mut a, mut a_reader) = ws_connect(&addr);
+        assert_eq!(ws_recv_text(&mut a_reader), "UPDATE 0 @0");
+
+        ws_send(&mut a, "OP + 5\nGET");
+        // El aviso puede llegar antes o después de la respuesta.
+        let mut received = [
+            ws_recv_text(&mut a_reader),
+            ws_recv_text(&mut a_reader),
+            ws_recv_text(&mut a_reader),
+        ];
+        received.sort();
+        assert_eq!(received, ["OK @1", "UPDATE 5 @1", "VALUE 5 @1"]);
+
+        // Los cambios hechos por otra conexión también se avisan.
+        let (mut b, mut b_reader) = ws_connect(&addr);
+        assert_eq!(ws_recv_text(&mut b_reader), "UPDATE 5 @1");
+        ws_send(&mut b, "OPS * 2, + 1");
+        assert_eq!(ws_recv_text(&mut a_reader), "UPDATE 11 @3");
+
+        // Un mensaje binario cierra con 1003.
+        a.write_all(&[0x82, 0x80, 0, 0, 0, 0]).unwrap();
+        assert_eq!(ws_recv(&mut a_reader), (8, 1003u16.to_be_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_slow_watcher_gets_only_the_latest_update() {
+        let server = Server::new(ServerConfig::default()).unwrap();
+        let (id, updates) = server.watch().unwrap();
+        // Nadie lee mientras cambia el valor: no se acumulan avisos.
+        for version in 1..=10_000 {
+            notify_watchers(
+                &State {
+                    value: version % 256,
+                    version,
+                },
+                &server,
+            );
+        }
+        assert_eq!(updates.try_recv(), Ok((10_000 % 256, 10_000)));
+        assert!(updates.try_recv().is_err());
+
+        server.unwatch(id);
+        assert_eq!(updates.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_websocket_handshake_errors() {
+        let addr = start_http_with(ServerConfig::default());
+        assert_eq!(http_request(&addr, "GET", "/ws", "", "").0, 426);
+        assert_eq!(http_request(&addr, "POST", "/ws", "", "").0, 405);
+    }
+
+    /// Arranca el servidor con el protocolo de líneas y el canal de
+    /// administración; devuelve ambas direcciones.
+    fn start_admin_with(config: ServerConfig) -> (String, String) {
+        let lines = TcpListener::bind("127.0.0.1:0").unwrap();
+        let admin = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addrs = (
+            lines.local_addr().unwrap().to_string(),
+            admin.local_addr().unwrap().to_string(),
+        );
+        let server = Arc::new(Server::new(config).unwrap());
+        let srv = Arc::clone(&server);
+        let pool = worker_pool(&server.config);
+        thread::spawn(move || run_server(admin, srv, None, handle_admin, busy_lines));
+        thread::spawn(move || run_server(lines, server, pool, handle_connection, busy_lines));
+        addrs
+    }
+
+    /// Envía una línea y lee `count` líneas de respuesta.
+    fn send_and_read(
+        stream: &mut TcpStream,
+        reader: &mut impl BufRead,
+        line: &str,
+        count: usize,
+    ) -> Vec<String> {
+        stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+        (0..count)
+            .map(|_| {
+                let mut resp = String::new();
+                reader.read_line(&mut resp).unwrap();
+                resp.trim().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_admin_channel() {
+        let (lines_addr, admin_addr) = start_admin_with(ServerConfig::default());
+        let mut user = TcpStream::connect(&lines_addr).unwrap();
+        let mut user_reader = BufReader::new(user.try_clone().unwrap());
+        assert_eq!(
+            send_and_read(&mut user, &mut user_reader, "OP + 5", 1),
+            ["OK @1"]
+        );
+
+        let mut admin = TcpStream::connect(&admin_addr).unwrap();
+        let mut reader = BufReader::new(admin.try_clone().unwrap());
+        let mut command =
+            |line: &str, count: usize| send_and_read(&mut admin, &mut reader, line, count);
+
+        let stats = command("STATS", 1);
+        assert!(
+            stats[0].starts_with("STATS value=5 version=1 clients=2 requests=2 uptime_s="),
+            "{}",
+            stats[0]
+        );
+        let clients = command("CLIENTS", 3);
+        assert_eq!(clients[0], "CLIENTS 2");
+        assert!(clients[1].starts_with("CLIENT 1 127.0.0.1 lines requests=1 idle_ms="));
+        assert!(clients[2].starts_with("CLIENT 2 127.0.0.1 admin requests=2 idle_ms="));
+
+        assert_eq!(command("SET 300", 1), ["ERROR \"operand out of range\""]);
+        assert_eq!(command("SET 42", 1), ["OK @2"]);
+        assert_eq!(command("RESET", 1), ["OK @3"]);
+        assert_eq!(command("KILL 9", 1), ["ERROR \"unknown client\""]);
+        assert_eq!(command("GET", 1), ["ERROR \"unknown command\""]);
+        assert_eq!(command("KILL 1", 1), ["OK"]);
+
+        // La conexión cerrada recibe el fin de los datos.
+        let mut rest = String::new();
+        assert_eq!(user_reader.read_line(&mut rest).unwrap(), 0);
+        assert_eq!(exchange(&lines_addr, &["GET"]), ["VALUE 0 @3"]);
+    }
+
+    #[test]
+    fn test_pool_is_shared_by_lines_and_http_but_not_admin() {
+        let config = ServerConfig {
+            threads: Some(1),
+            ..ServerConfig::default()
+        };
+        let pool = worker_pool(&config);
+        let server = Arc::new(Server::new(config).unwrap());
+        let listeners = [(); 3].map(|_| TcpListener::bind("127.0.0.1:0").unwrap());
+        let [lines_addr, http_addr, admin_addr] = listeners
+            .each_ref()
+            .map(|l| l.local_addr().unwrap().to_string());
+        let [lines, http, admin] = listeners;
+        let (srv, shared) = (Arc::clone(&server), pool.clone());
+        thread::spawn(move || run_server(lines, srv, shared, handle_connection, busy_lines));
+        let srv = Arc::clone(&server);
+        thread::spawn(move || run_server(http, srv, pool, handle_http, busy_http));
+        thread::spawn(move || run_server(admin, server, None, handle_admin, busy_lines));
+
+        // una conexión ocupa el único hilo y otra espera su turno
+        let mut first = TcpStream::connect(&lines_addr).unwrap();
+        let mut reader = BufReader::new(first.try_clone().unwrap());
+        assert_eq!(
+            send_and_read(&mut first, &mut reader, "OP + 1", 1),
+            ["OK @1"]
+        );
+        let _queued = TcpStream::connect(&lines_addr).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        // la pasarela responde 503 sin esperar el pedido
+        let mut response = String::new();
+        let mut http = TcpStream::connect(&http_addr).unwrap();
+        std::io::Read::read_to_string(&mut http, &mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        let mut admin = TcpStream::connect(&admin_addr).unwrap();
+        let mut reader = BufReader::new(admin.try_clone().unwrap());
+        let stats = send_and_read(&mut admin, &mut reader, "STATS", 1);
+        assert!(
+            stats[0].starts_with("STATS value=1 version=1"),
+            "{}",
+            stats[0]
+        );
+    }
+
+    #[test]
+    fn test_admin_snapshots() {
+        let (lines_addr, admin_addr) = start_admin_with(ServerConfig::default());
+        let mut admin = TcpStream::connect(&admin_addr).unwrap();
+        let mut reader = BufReader::new(admin.try_clone().unwrap());
+        let mut command = |line: &str| send_and_read(&mut admin, &mut reader, line, 1).remove(0);
+
+        assert_eq!(command("SET 5"), "OK @1");
+        let snapshot = command("SNAPSHOT");
+        let id: u64 = snapshot.strip_prefix("SNAPSHOT ").unwrap().parse().unwrap();
+        // numerado desde el inicio del servidor, no desde 1
+        assert!(id > 1, "{}", snapshot);
+        assert_eq!(command("SET 9"), "OK @2");
+        assert_eq!(command(&format!("RESTORE {}", id)), "OK @3");
+        assert_eq!(
+            command(&format!("RESTORE {}", id + 1)),
+            "ERROR \"unknown snapshot\""
+        );
+        assert_eq!(exchange(&lines_addr, &["GET"]), ["VALUE 5 @3"]);
+
+        let config = ServerConfig {
+            backend: Backend::Atomic,
+            ..ServerConfig::default()
+        };
+        let (_, admin_addr) = start_admin_with(config);
+        assert_eq!(
+            exchange(&admin_addr, &["SNAPSHOT"]),
+            ["ERROR \"history not available\""]
+        );
+    }
+
+    #[test]
+    fn test_admin_requires_auth() {
+        let path = std::env::temp_dir().join(format!("calc_admins_{}.txt", std::process::id()));
+        std::fs::write(&path, "ana:secreto\n").unwrap();
+        let users = std::env::temp_dir().join(format!("calc_users_{}.txt", std::process::id()));
+        std::fs::write(&users, "beto:clave\n").unwrap();
+        let mut config = ServerConfig {
+            auth_file: Some(users.to_string_lossy().into_owned()),
+            admin_address: Some("127.0.0.1:0".to_string()),
+            ..ServerConfig::default()
+        };
+        // sin administradores propios, cualquier usuario controlaría el canal
+        assert!(Server::new(config.clone()).is_err());
+        config.admin_auth_file = Some(path.to_string_lossy().into_owned());
+        let (_, admin_addr) = start_admin_with(config);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&users);
+
+        let responses = exchange(
+            &admin_addr,
+            &[
+                "RESET",
+                "AUTH beto clave",
+                "AUTH ana mal",
+                "AUTH ana secreto",
+                "SET 7",
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                "ERROR \"authentication required\"",
+                "ERROR \"authentication failed\"",
+                "ERROR \"authentication failed\"",
+                "OK",
+                "OK @1"
+            ]
+        );
+
+        // los intentos se limitan por IP, aunque se cambie de conexión
+        let attempts = ["AUTH ana mal", "AUTH ana mal", "AUTH ana secreto", "STATS"];
+        let responses = exchange(&admin_addr, &attempts);
+        assert_eq!(responses[..2], ["ERROR \"authentication failed\""; 2]);
+        assert!(
+            responses[2].starts_with("ERROR \"rate limited; retry after "),
+            "{}",
+            responses[2]
+        );
+        assert_eq!(responses[3], "ERROR \"authentication required\"");
+    }
+
+    /// Arranca la recepción UDP y retorna un socket conectado a ella y el
+    /// servidor.
+    fn start_udp_with(config: ServerConfig) -> (UdpSocket, Arc<Server>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let server = Arc::new(Server::new(config).unwrap());
+        let srv = Arc::clone(&server);
+        thread::spawn(move || run_udp(socket, srv));
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        (client, server)
+    }
+
+    fn udp_exchange(client: &UdpSocket, text: &str) -> String {
+        client.send(text.as_bytes()).unwrap();
+        let mut buf = [0; 1024];
+        let len = client.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).to_string()
+    }
+
+    #[test]
+    fn test_udp_deduplicates() {
+        let (client, server) = start_udp_with(ServerConfig::default());
+        assert_eq!(udp_exchange(&client, "10 OPS + 5, * 2"), "10 OK");
+        // Un reintento se confirma sin volver a aplicarse.
+        assert_eq!(udp_exchange(&client, "10 OPS + 5, * 2"), "10 OK");
+        assert_eq!(udp_exchange(&client, "12 OP + 1"), "12 OK");
+        let failed = "11 RESULTS ERROR \"division by zero\"";
+        assert_eq!(udp_exchange(&client, "11 OP / 0"), failed);
+        assert_eq!(udp_exchange(&client, "11 OP / 0"), failed);
+        assert_eq!(
+            server.state.load().unwrap(),
+            State {
+                value: 11,
+                version: 3
+            }
+        );
+
+        assert_eq!(
+            udp_exchange(&client, "13 GET"),
+            "13 ERROR \"unexpected message\""
+        );
+        assert_eq!(
+            udp_exchange(&client, "14 OP + 1 @3"),
+            "14 ERROR \"versions not supported over UDP\""
+        );
+        assert_eq!(
+            udp_exchange(&client, "15 OP ?"),
+            "15 ERROR \"parsing error\""
+        );
+        assert_eq!(
+            udp_exchange(&client, "1000 OP + 1"),
+            "1000 ERROR \"sequence out of window\""
+        );
+        assert_eq!(udp_exchange(&client, "13 OP + 1"), "13 OK");
+    }
+
+    #[test]
+    fn test_udp_rate_limits_each_op() {
+        let config = ServerConfig {
+            rate_limit: Some(RateLimitConfig {
+                capacity: 2,
+                refill_per_sec: 0.01,
+            }),
+            ..ServerConfig::default()
+        };
+        let (client, server) = start_udp_with(config);
+        // tres operaciones nunca entran en dos tokens
+        assert_eq!(
+            udp_exchange(&client, "1 OPS + 1, + 1, + 1"),
+            "1 ERROR \"batch exceeds rate limit\""
+        );
+        assert_eq!(udp_exchange(&client, "2 OPS + 1"), "2 OK");
+        // dos no alcanzan con el token que queda: se descarta sin confirmar
+        client.send(b"3 OPS + 1, + 1").unwrap();
+        assert_eq!(
+            udp_exchange(&client, "0 HELLO 1 batch"),
+            "0 HELLO 1 batch burst=2 numeric=u8 overflow=wrapping backend=mutex"
+        );
+        assert_eq!(server.state.load().unwrap().value, 1);
+    }
+
+    #[test]
+    fn test_udp_rejects_without_session() {
+        let path = std::env::temp_dir().join(format!("calc_udp_users_{}.txt", std::process::id()));
+        std::fs::write(&path, "ana:secreto\n").unwrap();
+        let config = ServerConfig {
+            auth_file: Some(path.to_string_lossy().into_owned()),
+            ..ServerConfig::default()
+        };
+        let (client, _server) = start_udp_with(config);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            udp_exchange(&client, "1 OP + 1"),
+            "1 ERROR \"authentication required\""
+        );
     }
 }
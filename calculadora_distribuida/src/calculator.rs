@@ -1,25 +1,172 @@
+use std::fmt;
+use std::str::FromStr;
+
 use crate::operator::Operator;
 use crate::protocol::Operation;
 
-/// Aplica una operación aritmética sobre el valor actual (`u8`).
-/// Para +, -, * usamos aritmética wrapping (módulo 256).
-/// Para / hacemos división entera; si el operando es 0 devolvemos error.
-pub fn apply_operation(current: u8, op: &Operation) -> Result<u8, String> {
-    let operand = op.operand;
-
-    match op.op {
-        Operator::Add => Ok(current.wrapping_add(operand)),
-        Operator::Sub => Ok(current.wrapping_sub(operand)),
-        Operator::Mul => Ok(current.wrapping_mul(operand)),
-        Operator::Div => {
-            if operand == 0 {
-                Err("division by zero".to_string())
-            } else {
-                // división entera; quedamos con el comportamiento usual de u8
-                Ok(current / operand)
+/// Tipo numérico del valor central de la calculadora.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumericType {
+    #[default]
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl NumericType {
+    /// Mayor valor representable por el tipo.
+    pub fn max(self) -> u64 {
+        match self {
+            NumericType::U8 => u64::from(u8::MAX),
+            NumericType::U16 => u64::from(u16::MAX),
+            NumericType::U32 => u64::from(u32::MAX),
+            NumericType::U64 => u64::MAX,
+        }
+    }
+}
+
+impl FromStr for NumericType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u8" => Ok(NumericType::U8),
+            "u16" => Ok(NumericType::U16),
+            "u32" => Ok(NumericType::U32),
+            "u64" => Ok(NumericType::U64),
+            _ => Err(format!(
+                "Tipo numerico invalido: {} (se esperaba u8, u16, u32 o u64)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NumericType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumericType::U8 => write!(f, "u8"),
+            NumericType::U16 => write!(f, "u16"),
+            NumericType::U32 => write!(f, "u32"),
+            NumericType::U64 => write!(f, "u64"),
+        }
+    }
+}
+
+/// Qué hacer cuando el resultado de +, - o * no entra en el tipo numérico.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Aritmética modular (comportamiento original).
+    #[default]
+    Wrapping,
+    /// El resultado se satura en el mínimo o el máximo del tipo.
+    Saturating,
+    /// La operación se rechaza con `"overflow"` y el valor no cambia.
+    Error,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrapping" => Ok(OverflowPolicy::Wrapping),
+            "saturating" => Ok(OverflowPolicy::Saturating),
+            "error" => Ok(OverflowPolicy::Error),
+            _ => Err(format!(
+                "Politica de overflow invalida: {} (se esperaba wrapping, saturating o error)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowPolicy::Wrapping => write!(f, "wrapping"),
+            OverflowPolicy::Saturating => write!(f, "saturating"),
+            OverflowPolicy::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Reglas aritméticas de la calculadora: tipo numérico y política de overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Arithmetic {
+    pub numeric_type: NumericType,
+    pub overflow: OverflowPolicy,
+}
+
+impl Arithmetic {
+    /// Aplica una operación sobre `current` según el tipo y la política configurados.
+    ///
+    /// # Errores
+    /// - `"operand out of range"` si el operando no entra en el tipo numérico.
+    /// - `"division by zero"` si se divide por cero.
+    /// - `"overflow"` si el resultado no entra y la política es `Error`.
+    pub fn apply(&self, current: u64, op: &Operation) -> Result<u64, String> {
+        let max = self.numeric_type.max();
+        let operand = op.operand;
+        if operand > max {
+            return Err("operand out of range".to_string());
+        }
+        let modulus = u128::from(max) + 1;
+
+        match op.op {
+            Operator::Add => {
+                let sum = u128::from(current) + u128::from(operand);
+                self.fit(sum, max, || (sum % modulus) as u64, max)
+            }
+            Operator::Sub => match current.checked_sub(operand) {
+                Some(r) => Ok(r),
+                None => {
+                    let wrapped = (modulus - u128::from(operand - current)) as u64;
+                    self.on_overflow(wrapped, 0)
+                }
+            },
+            Operator::Mul => {
+                let product = u128::from(current) * u128::from(operand);
+                self.fit(product, max, || (product % modulus) as u64, max)
             }
+            // división entera; nunca desborda
+            Operator::Div => current
+                .checked_div(operand)
+                .ok_or_else(|| "division by zero".to_string()),
         }
     }
+
+    /// Retorna `value` si no supera `max`, o aplica la política de overflow.
+    fn fit(
+        &self,
+        value: u128,
+        max: u64,
+        wrapped: impl FnOnce() -> u64,
+        saturated: u64,
+    ) -> Result<u64, String> {
+        match u64::try_from(value) {
+            Ok(v) if v <= max => Ok(v),
+            _ => self.on_overflow(wrapped(), saturated),
+        }
+    }
+
+    /// Resuelve un overflow según la política configurada.
+    fn on_overflow(&self, wrapped: u64, saturated: u64) -> Result<u64, String> {
+        match self.overflow {
+            OverflowPolicy::Wrapping => Ok(wrapped),
+            OverflowPolicy::Saturating => Ok(saturated),
+            OverflowPolicy::Error => Err("overflow".to_string()),
+        }
+    }
+}
+
+/// Aplica una operación aritmética sobre el valor actual con las reglas
+/// por defecto (`u8`).
+/// Para +, -, * usamos aritmética wrapping (módulo 256).
+/// Para / hacemos división entera; si el operando es 0 devolvemos error.
+pub fn apply_operation(current: u64, op: &Operation) -> Result<u64, String> {
+    Arithmetic::default().apply(current, op)
 }
 
 #[cfg(test)]
@@ -71,4 +218,76 @@ mod tests {
         };
         assert_eq!(apply_operation(10, &op).unwrap_err(), "division by zero");
     }
+
+    #[test]
+    fn test_default_wraps_as_u8() {
+        let add = Operation {
+            op: Operator::Add,
+            operand: 10,
+        };
+        let sub = Operation {
+            op: Operator::Sub,
+            operand: 10,
+        };
+        assert_eq!(apply_operation(250, &add).unwrap(), 4);
+        assert_eq!(apply_operation(5, &sub).unwrap(), 251);
+    }
+
+    #[test]
+    fn test_operand_out_of_range() {
+        let op = Operation {
+            op: Operator::Add,
+            operand: 300,
+        };
+        assert_eq!(apply_operation(0, &op).unwrap_err(), "operand out of range");
+
+        let wide = Arithmetic {
+            numeric_type: NumericType::U16,
+            ..Arithmetic::default()
+        };
+        assert_eq!(wide.apply(0, &op).unwrap(), 300);
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let mul = Operation {
+            op: Operator::Mul,
+            operand: 2,
+        };
+        let sub = Operation {
+            op: Operator::Sub,
+            operand: 1,
+        };
+        let saturating = Arithmetic {
+            numeric_type: NumericType::U16,
+            overflow: OverflowPolicy::Saturating,
+        };
+        assert_eq!(saturating.apply(40_000, &mul).unwrap(), 65_535);
+        assert_eq!(saturating.apply(0, &sub).unwrap(), 0);
+
+        let error = Arithmetic {
+            numeric_type: NumericType::U64,
+            overflow: OverflowPolicy::Error,
+        };
+        assert_eq!(error.apply(u64::MAX, &mul).unwrap_err(), "overflow");
+        assert_eq!(error.apply(0, &sub).unwrap_err(), "overflow");
+
+        let wrapping = Arithmetic {
+            numeric_type: NumericType::U64,
+            overflow: OverflowPolicy::Wrapping,
+        };
+        assert_eq!(wrapping.apply(u64::MAX, &mul).unwrap(), u64::MAX - 1);
+        assert_eq!(wrapping.apply(0, &sub).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_parse_numeric_type_and_policy() {
+        assert_eq!("u32".parse::<NumericType>().unwrap(), NumericType::U32);
+        assert!("i32".parse::<NumericType>().is_err());
+        assert_eq!(
+            "saturating".parse::<OverflowPolicy>().unwrap(),
+            OverflowPolicy::Saturating
+        );
+        assert!("panic".parse::<OverflowPolicy>().is_err());
+    }
 }
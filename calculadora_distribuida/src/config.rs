@@ -0,0 +1,389 @@
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::calculator::Arithmetic;
+use crate::connection::ConnectionLimits;
+use crate::log::LogLevel;
+use crate::rate_limiter::RateLimitConfig;
+use crate::state::Backend;
+
+/// Configuración del servidor.
+///
+/// Se obtiene de un archivo estilo INI:
+///
+/// ```text
+/// # comentario
+/// [server]
+/// address = 127.0.0.1:12345
+/// http_address = 127.0.0.1:8080
+/// udp_address = 127.0.0.1:12346
+/// admin_address = 127.0.0.1:12347
+/// threads = 8
+/// log_level = info
+///
+/// [calculator]
+/// numeric_type = u32
+/// overflow = saturating
+/// backend = mutex
+///
+/// [storage]
+/// persistence_path = data/estado.txt
+/// history_size = 10000
+///
+/// [auth]
+/// users_file = data/usuarios.txt
+/// admin_file = data/administradores.txt
+///
+/// [connection]
+/// read_timeout = 300
+/// write_timeout = 10
+/// max_line_length = 1024
+///
+/// [rate_limit]
+/// capacity = 20
+/// refill_per_sec = 10
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ServerConfig {
+    /// Dirección donde escucha el servidor.
+    pub address: Option<String>,
+    /// Dirección donde escucha la pasarela HTTP/JSON; `None` no la inicia.
+    pub http_address: Option<String>,
+    /// Dirección UDP donde se reciben operaciones sueltas; `None` no la abre.
+    pub udp_address: Option<String>,
+    /// Dirección del canal de administración; `None` no lo abre.
+    pub admin_address: Option<String>,
+    /// Cantidad de hilos que atienden las conexiones de líneas y HTTP, en un
+    /// único pool; `None` crea un hilo por conexión.
+    pub threads: Option<usize>,
+    /// Nivel mínimo de los mensajes de log.
+    pub log_level: LogLevel,
+    /// Tipo numérico y política de overflow del valor central.
+    pub arithmetic: Arithmetic,
+    /// Cómo se sincroniza el valor entre conexiones.
+    pub backend: Backend,
+    /// Archivo donde se guarda el valor tras cada cambio; `None` no persiste.
+    pub persistence_path: Option<String>,
+    /// Versiones del valor que se conservan para `GET AT`; `None` usa
+    /// `history::DEFAULT_HISTORY_SIZE`.
+    pub history_size: Option<usize>,
+    /// Archivo de usuarios; si está presente se exige `AUTH` antes de operar.
+    pub auth_file: Option<String>,
+    /// Archivo de administradores; si está presente el canal de
+    /// administración exige `AUTH` con uno de ellos.
+    pub admin_auth_file: Option<String>,
+    /// Límites aplicados a cada conexión.
+    pub limits: ConnectionLimits,
+    /// Limitación de tasa por cliente; `None` la desactiva.
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// Una entrada `clave = valor` del archivo, con su sección y número de línea.
+#[derive(Debug, PartialEq, Eq)]
+struct Entry<'a> {
+    line: usize,
+    section: &'a str,
+    key: &'a str,
+    value: &'a str,
+}
+
+impl ServerConfig {
+    /// Lee y valida la configuración desde el archivo `path`.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si no se puede leer el archivo o si su contenido
+    /// es inválido, indicando la línea del problema.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("No se pudo leer la configuracion {}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Interpreta el contenido de un archivo de configuración.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = ServerConfig::default();
+        let mut rate_limit: Option<(Option<u32>, Option<f64>)> = None;
+
+        for entry in parse_entries(text)? {
+            let at = |msg: String| format!("linea {}: {}", entry.line, msg);
+            match (entry.section, entry.key) {
+                ("rate_limit", "capacity") => {
+                    let capacity = parse_positive::<u32>(entry.key, entry.value).map_err(at)?;
+                    rate_limit.get_or_insert((None, None)).0 = Some(capacity);
+                }
+                ("rate_limit", "refill_per_sec") => {
+                    let rate = parse_rate(entry.key, entry.value).map_err(at)?;
+                    rate_limit.get_or_insert((None, None)).1 = Some(rate);
+                }
+                (section, key) => config.set(section, key, entry.value).map_err(at)?,
+            }
+        }
+
+        if let Some((capacity, refill)) = rate_limit {
+            config.rate_limit = Some(RateLimitConfig {
+                capacity: capacity.ok_or("[rate_limit] requiere capacity")?,
+                refill_per_sec: refill.ok_or("[rate_limit] requiere refill_per_sec")?,
+            });
+        }
+
+        Ok(config)
+    }
+
+    /// Asigna la opción `key` de la sección `section`.
+    ///
+    /// Es la misma función que usan el archivo de configuración y las
+    /// opciones de línea de comando, para que ambas validen igual.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si la clave no existe o el valor es inválido.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        match (section, key) {
+            ("server", "address") => self.address = Some(parse_non_empty(key, value)?),
+            ("server", "http_address") => self.http_address = Some(parse_non_empty(key, value)?),
+            ("server", "udp_address") => self.udp_address = Some(parse_non_empty(key, value)?),
+            ("server", "admin_address") => self.admin_address = Some(parse_non_empty(key, value)?),
+            ("server", "threads") => self.threads = Some(parse_positive(key, value)?),
+            ("server", "log_level") => self.log_level = LogLevel::from_str(value)?,
+            ("calculator", "numeric_type") => self.arithmetic.numeric_type = value.parse()?,
+            ("calculator", "overflow") => self.arithmetic.overflow = value.parse()?,
+            ("calculator", "backend") => self.backend = value.parse()?,
+            ("storage", "persistence_path") => {
+                self.persistence_path = Some(parse_non_empty(key, value)?)
+            }
+            ("storage", "history_size") => self.history_size = Some(parse_positive(key, value)?),
+            ("auth", "users_file") => self.auth_file = Some(parse_non_empty(key, value)?),
+            ("auth", "admin_file") => self.admin_auth_file = Some(parse_non_empty(key, value)?),
+            ("connection", "read_timeout") => self.limits.read_timeout = parse_timeout(key, value)?,
+            ("connection", "write_timeout") => {
+                self.limits.write_timeout = parse_timeout(key, value)?
+            }
+            ("connection", "max_line_length") => {
+                self.limits.max_line_length = parse_positive(key, value)?
+            }
+            ("rate_limit", "rate") => self.rate_limit = Some(parse_rate_limit(key, value)?),
+            _ => return Err(format!("clave desconocida: [{}] {}", section, key)),
+        }
+        Ok(())
+    }
+}
+
+/// Separa el texto en entradas `clave = valor`, ignorando líneas vacías y
+/// comentarios (`#` o `;`).
+fn parse_entries(text: &str) -> Result<Vec<Entry<'_>>, String> {
+    let mut entries = Vec::new();
+    let mut section = "";
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("linea {}: se esperaba 'clave = valor'", i + 1))?;
+        entries.push(Entry {
+            line: i + 1,
+            section,
+            key: key.trim(),
+            value: value.trim().trim_matches('"'),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Interpreta un entero mayor a cero.
+fn parse_positive<T>(key: &str, value: &str) -> Result<T, String>
+where
+    T: FromStr + PartialOrd + Default,
+{
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|n| *n > T::default())
+        .ok_or_else(|| {
+            format!(
+                "{} (se esperaba un entero positivo)",
+                invalid_value(key, value)
+            )
+        })
+}
+
+/// Interpreta un timeout en segundos; `0` significa sin timeout.
+fn parse_timeout(key: &str, value: &str) -> Result<Option<Duration>, String> {
+    let secs = value.parse::<u64>().map_err(|_| {
+        format!(
+            "{} (se esperaban segundos, 0 para desactivar)",
+            invalid_value(key, value)
+        )
+    })?;
+    Ok((secs > 0).then(|| Duration::from_secs(secs)))
+}
+
+/// Menor tasa de tokens por segundo admitida: un token cada ~17 minutos.
+/// Con tasas menores la espera hasta el próximo token deja de tener sentido
+/// (y para tasas ínfimas ni siquiera entra en un `Duration`).
+const MIN_RATE: f64 = 0.001;
+
+/// Interpreta una tasa de tokens por segundo de al menos `MIN_RATE`.
+fn parse_rate(key: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|r| r.is_finite() && *r >= MIN_RATE)
+        .ok_or_else(|| {
+            format!(
+                "{} (se esperaba un numero de al menos {})",
+                invalid_value(key, value),
+                MIN_RATE
+            )
+        })
+}
+
+/// Interpreta `<capacidad>:<tokens por segundo>`.
+fn parse_rate_limit(key: &str, value: &str) -> Result<RateLimitConfig, String> {
+    let (capacity, rate) = value.split_once(':').ok_or_else(|| {
+        format!(
+            "{} (se esperaba <capacidad>:<por segundo>)",
+            invalid_value(key, value)
+        )
+    })?;
+    Ok(RateLimitConfig {
+        capacity: parse_positive("capacity", capacity)?,
+        refill_per_sec: parse_rate("refill_per_sec", rate)?,
+    })
+}
+
+/// Interpreta un texto no vacío.
+fn parse_non_empty(key: &str, value: &str) -> Result<String, String> {
+    if value.is_empty() {
+        return Err(invalid_value(key, value));
+    }
+    Ok(value.to_string())
+}
+
+fn invalid_value(key: &str, value: &str) -> String {
+    format!("valor invalido para {}: '{}'", key, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::{NumericType, OverflowPolicy};
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(ServerConfig::parse("").unwrap(), ServerConfig::default());
+    }
+
+    #[test]
+    fn test_parse_rate_limit() {
+        let text = "# limites\n[rate_limit]\ncapacity = 5\nrefill_per_sec = 2.5\n";
+        let config = ServerConfig::parse(text).unwrap();
+        assert_eq!(
+            config.rate_limit,
+            Some(RateLimitConfig {
+                capacity: 5,
+                refill_per_sec: 2.5
+            })
+        );
+
+        let text = "[rate_limit]\ncapacity = 5\nrefill_per_sec = 1e-20\n";
+        assert!(ServerConfig::parse(text).is_err());
+    }
+
+    #[test]
+    fn test_parse_full() {
+        let text = "\
+[server]
+address = \"0.0.0.0:9000\"
+http_address = 0.0.0.0:8080
+udp_address = 0.0.0.0:9001
+admin_address = 127.0.0.1:9002
+threads = 4
+log_level = debug
+
+[calculator]
+numeric_type = u32
+overflow = error
+backend = atomic
+
+[storage]
+persistence_path = estado.txt
+history_size = 50
+
+[auth]
+users_file = usuarios.txt
+admin_file = administradores.txt
+
+[connection]
+read_timeout = 0
+write_timeout = 5
+max_line_length = 64
+";
+        let config = ServerConfig::parse(text).unwrap();
+        assert_eq!(config.address.as_deref(), Some("0.0.0.0:9000"));
+        assert_eq!(config.http_address.as_deref(), Some("0.0.0.0:8080"));
+        assert_eq!(config.udp_address.as_deref(), Some("0.0.0.0:9001"));
+        assert_eq!(config.admin_address.as_deref(), Some("127.0.0.1:9002"));
+        assert_eq!(config.threads, Some(4));
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.arithmetic.numeric_type, NumericType::U32);
+        assert_eq!(config.arithmetic.overflow, OverflowPolicy::Error);
+        assert_eq!(config.backend, Backend::Atomic);
+        assert_eq!(config.persistence_path.as_deref(), Some("estado.txt"));
+        assert_eq!(config.history_size, Some(50));
+        assert_eq!(config.auth_file.as_deref(), Some("usuarios.txt"));
+        assert_eq!(
+            config.admin_auth_file.as_deref(),
+            Some("administradores.txt")
+        );
+        assert_eq!(config.limits.read_timeout, None);
+        assert_eq!(config.limits.write_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.limits.max_line_length, 64);
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        let err = ServerConfig::parse("[rate_limit]\ncapacity = -1\n").unwrap_err();
+        assert!(err.starts_with("linea 2:"), "{}", err);
+
+        let err = ServerConfig::parse("\n\nfoo = 1\n").unwrap_err();
+        assert!(err.starts_with("linea 3:"), "{}", err);
+
+        let err = ServerConfig::parse("[rate_limit]\nsin igual\n").unwrap_err();
+        assert!(err.starts_with("linea 2:"), "{}", err);
+
+        let err = ServerConfig::parse("[calculator]\nnumeric_type = i8\n").unwrap_err();
+        assert!(err.starts_with("linea 2:") && err.contains("i8"), "{}", err);
+
+        let err = ServerConfig::parse("[server]\nthreads = 0\n").unwrap_err();
+        assert!(err.contains("entero positivo"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_incomplete_rate_limit() {
+        assert!(ServerConfig::parse("[rate_limit]\ncapacity = 5\n").is_err());
+    }
+
+    #[test]
+    fn test_set_rate_limit_pair() {
+        let mut config = ServerConfig::default();
+        config.set("rate_limit", "rate", "10:2").unwrap();
+        assert_eq!(
+            config.rate_limit,
+            Some(RateLimitConfig {
+                capacity: 10,
+                refill_per_sec: 2.0
+            })
+        );
+        assert!(config.set("rate_limit", "rate", "10").is_err());
+    }
+}
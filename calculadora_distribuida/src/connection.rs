@@ -0,0 +1,204 @@
+use std::io::{BufRead, ErrorKind};
+use std::time::{Duration, Instant};
+
+/// Límites aplicados a cada conexión para protegerse de clientes lentos
+/// o malintencionados.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Tiempo máximo de espera sin recibir datos antes de cerrar la conexión,
+    /// y para completar una línea desde su primer byte. `None` desactiva el
+    /// timeout.
+    pub read_timeout: Option<Duration>,
+    /// Tiempo máximo que puede bloquearse una escritura hacia el cliente.
+    /// `None` desactiva el timeout.
+    pub write_timeout: Option<Duration>,
+    /// Longitud máxima (en bytes) de una línea, sin contar el salto de línea.
+    pub max_line_length: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        ConnectionLimits {
+            read_timeout: Some(Duration::from_secs(300)),
+            write_timeout: Some(Duration::from_secs(10)),
+            max_line_length: 1024,
+        }
+    }
+}
+
+/// Errores posibles al leer una línea con límites.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LineError {
+    /// La línea supera `max_line_length`.
+    TooLong,
+    /// Venció el timeout de lectura sin recibir una línea completa.
+    Timeout,
+    /// Cualquier otro error de E/S.
+    Io(String),
+}
+
+/// Lee una línea de `reader` sin superar `max_len` bytes.
+///
+/// El salto de línea final (`\n` o `\r\n`) no se incluye en el resultado.
+/// Los bytes que no sean UTF-8 válido se reemplazan por `U+FFFD`.
+///
+/// Con `line_timeout`, la línea debe completarse en ese tiempo desde su
+/// primer byte: el timeout de lectura sólo vence si no llega nada, y un
+/// cliente que envía un byte justo antes de cada vencimiento retendría la
+/// conexión indefinidamente. Como se verifica entre lecturas, la línea
+/// puede demorar además lo que tarde en volver la lectura en curso.
+///
+/// # Retorno
+/// - `Ok(Some(linea))` si se leyó una línea.
+/// - `Ok(None)` si se alcanzó el fin del stream sin datos pendientes.
+/// - `Err(LineError)` si la línea es demasiado larga, venció el timeout
+///   o falló la lectura.
+pub fn read_line_limited<R: BufRead>(
+    reader: &mut R,
+    max_len: usize,
+    line_timeout: Option<Duration>,
+) -> Result<Option<String>, LineError> {
+    let mut line: Vec<u8> = Vec::new();
+    let mut deadline = None;
+
+    loop {
+        let buf = match reader.fill_buf() {
+            Ok(b) => b,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(LineError::Timeout);
+            }
+            Err(e) => return Err(LineError::Io(e.to_string())),
+        };
+
+        if buf.is_empty() {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            break;
+        }
+
+        // Se tolera un byte extra por un posible `\r` final; el largo exacto
+        // se verifica al terminar la línea.
+        match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                if line.len() + i > max_len + 1 {
+                    reader.consume(i + 1);
+                    return Err(LineError::TooLong);
+                }
+                line.extend_from_slice(&buf[..i]);
+                reader.consume(i + 1);
+                break;
+            }
+            None => {
+                let n = buf.len();
+                if line.len() + n > max_len + 1 {
+                    reader.consume(n);
+                    return Err(LineError::TooLong);
+                }
+                line.extend_from_slice(buf);
+                reader.consume(n);
+                let deadline = *deadline.get_or_insert_with(|| {
+                    line_timeout.and_then(|t| Instant::now().checked_add(t))
+                });
+                if deadline.is_some_and(|d| Instant::now() >= d) {
+                    return Err(LineError::Timeout);
+                }
+            }
+        }
+    }
+
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    if line.len() > max_len {
+        return Err(LineError::TooLong);
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_read_single_line() {
+        let mut r = Cursor::new("OP + 1\n");
+        assert_eq!(
+            read_line_limited(&mut r, 16, None).unwrap(),
+            Some("OP + 1".to_string())
+        );
+        assert_eq!(read_line_limited(&mut r, 16, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_crlf_and_last_line_without_newline() {
+        let mut r = Cursor::new("GET\r\nOK");
+        assert_eq!(
+            read_line_limited(&mut r, 3, None).unwrap(),
+            Some("GET".into())
+        );
+        assert_eq!(
+            read_line_limited(&mut r, 3, None).unwrap(),
+            Some("OK".into())
+        );
+        assert_eq!(read_line_limited(&mut r, 3, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_line_too_long() {
+        let mut r = Cursor::new("OP + 100\nGET\n");
+        assert_eq!(read_line_limited(&mut r, 4, None), Err(LineError::TooLong));
+        // La línea larga se descarta completa.
+        assert_eq!(
+            read_line_limited(&mut r, 4, None).unwrap(),
+            Some("GET".into())
+        );
+    }
+
+    /// Entrega un byte por lectura, esperando `delay` antes de cada uno.
+    struct Drip {
+        data: Cursor<Vec<u8>>,
+        delay: Duration,
+    }
+
+    impl Read for Drip {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(self.delay);
+            let len = buf.len().min(1);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_line_timeout_stops_drip_feeding() {
+        let drip = |text: &str| {
+            std::io::BufReader::new(Drip {
+                data: Cursor::new(text.as_bytes().to_vec()),
+                delay: Duration::from_millis(10),
+            })
+        };
+        let timeout = Some(Duration::from_millis(50));
+        let mut r = drip("OP + 1234567890\n");
+        assert_eq!(
+            read_line_limited(&mut r, 64, timeout),
+            Err(LineError::Timeout)
+        );
+        let mut r = drip("GET\n");
+        assert_eq!(
+            read_line_limited(&mut r, 64, timeout).unwrap(),
+            Some("GET".into())
+        );
+    }
+
+    #[test]
+    fn test_endless_line_without_newline() {
+        let data = vec![b'a'; 10_000];
+        let mut r = std::io::BufReader::with_capacity(64, Cursor::new(data));
+        assert_eq!(
+            read_line_limited(&mut r, 100, None),
+            Err(LineError::TooLong)
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::protocol::{Message, Operation};
+
+/// Cantidad máxima de operaciones por datagrama, para que el texto entre en
+/// un paquete de 1400 bytes aun con operandos de 20 dígitos.
+pub const MAX_OPS_PER_DATAGRAM: usize = 50;
+
+/// Tamaño del buffer de recepción: el mayor datagrama UDP posible.
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// Ventana de secuencias: el emisor no envía la secuencia `n` hasta que se
+/// confirmaron todas las anteriores a `n - WINDOW + 1`. Así el receptor
+/// puede tomar como repetida cualquier secuencia que quedó fuera de su
+/// ventana, porque ya la había recibido.
+pub const WINDOW: u64 = 64;
+
+/// Tiempo sin datagramas tras el cual el receptor olvida a un emisor. Debe
+/// superar con holgura el tiempo que un emisor sigue reintentando.
+pub const SENDER_TTL: Duration = Duration::from_secs(600);
+
+/// Cada cuánto el receptor olvida a los emisores inactivos (ver
+/// `Dedup::prune`).
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Cantidad máxima de emisores recordados a la vez; los datagramas de
+/// emisores nuevos se rechazan hasta que se olvide alguno.
+pub const MAX_SENDERS: usize = 4096;
+
+/// Texto de un datagrama: `<secuencia> <mensaje>`.
+///
+/// El emisor envía `7 OPS + 5, * 2` y el receptor responde con la misma
+/// secuencia: `7 OK` si lo aplicó (o ya lo había aplicado) sin errores,
+/// `7 RESULTS OK @3; ERROR "<motivo>"` si alguna operación falló, o
+/// `7 ERROR "<motivo>"` si lo rechaza sin remedio.
+pub fn encode(seq: u64, message: &Message) -> String {
+    format!("{} {}", seq, message)
+}
+
+/// Separa la secuencia del resto del datagrama, o `None` si no empieza con
+/// un número.
+pub fn split_seq(text: &str) -> Option<(u64, &str)> {
+    let (seq, rest) = text.split_once(' ')?;
+    Some((seq.parse().ok()?, rest))
+}
+
+/// Secuencias ya recibidas de cada emisor, para aplicar cada datagrama una
+/// sola vez aunque llegue repetido.
+#[derive(Debug, Default)]
+pub struct Dedup {
+    senders: HashMap<String, Window>,
+}
+
+/// Secuencias recibidas de un emisor: la mayor y, en `seen`, un bit por
+/// cada una de las `WINDOW` anteriores (el bit `i` es `highest - i`).
+#[derive(Debug)]
+struct Window {
+    highest: u64,
+    seen: u64,
+    /// Respuestas distintas de `OK` de las secuencias de la ventana, para
+    /// repetirlas a los reintentos.
+    replies: BTreeMap<u64, String>,
+    last: Instant,
+}
+
+/// Qué hacer con un datagrama según su secuencia (ver `Dedup::check`).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict<'a> {
+    /// No se recibió: se aplica y se registra con `Dedup::record`.
+    New,
+    /// Ya se aplicó: se vuelve a confirmar con la respuesta registrada, o
+    /// con `OK` si fue `None`.
+    Repeated(Option<&'a str>),
+    /// Se rechaza sin aplicarlo, por el motivo dado.
+    Rejected(&'static str),
+}
+
+impl Dedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clasifica la secuencia `seq` de `sender`.
+    ///
+    /// Las secuencias anteriores a la ventana cuentan como recibidas. Como
+    /// el emisor no se adelanta más de `WINDOW` a lo ya confirmado, una
+    /// secuencia más adelantada se rechaza con `"sequence out of window"`:
+    /// aceptarla correría la ventana y haría pasar por repetidas las
+    /// legítimas. Un emisor nuevo se rechaza con `"too many senders"` si ya
+    /// se recuerdan `MAX_SENDERS`.
+    pub fn check(&self, sender: &str, seq: u64) -> Verdict<'_> {
+        let Some(window) = self.senders.get(sender) else {
+            if self.senders.len() >= MAX_SENDERS {
+                return Verdict::Rejected("too many senders");
+            }
+            return Verdict::New;
+        };
+        match window.highest.checked_sub(seq) {
+            None if seq - window.highest > WINDOW => Verdict::Rejected("sequence out of window"),
+            None => Verdict::New,
+            Some(offset) if offset >= WINDOW => Verdict::Repeated(None),
+            Some(offset) if window.seen & (1 << offset) != 0 => {
+                Verdict::Repeated(window.replies.get(&seq).map(String::as_str))
+            }
+            Some(_) => Verdict::New,
+        }
+    }
+
+    /// Registra la secuencia `seq` de `sender` como recibida en `now`, con
+    /// la respuesta que se le dio si no fue `OK`. Se llama sólo después de
+    /// que `check` respondió `Verdict::New`.
+    pub fn record(&mut self, sender: &str, seq: u64, reply: Option<String>, now: Instant) {
+        let window = self.senders.entry(sender.to_string()).or_insert(Window {
+            highest: seq,
+            seen: 0,
+            replies: BTreeMap::new(),
+            last: now,
+        });
+        window.last = now;
+        match seq.checked_sub(window.highest) {
+            Some(shift) => {
+                window.seen = match shift {
+                    0..WINDOW => (window.seen << shift) | 1,
+                    _ => 1,
+                };
+                window.highest = seq;
+                let oldest = seq.saturating_sub(WINDOW - 1);
+                window.replies = window.replies.split_off(&oldest);
+            }
+            None => {
+                let offset = window.highest - seq;
+                if offset >= WINDOW {
+                    return;
+                }
+                window.seen |= 1 << offset;
+            }
+        }
+        if let Some(reply) = reply {
+            window.replies.insert(seq, reply);
+        }
+    }
+
+    /// Olvida a los emisores que llevan más de `SENDER_TTL` sin enviar nada
+    /// en `now`. El receptor lo llama cada `PRUNE_INTERVAL`.
+    pub fn prune(&mut self, now: Instant) {
+        self.senders
+            .retain(|_, w| now.saturating_duration_since(w.last) < SENDER_TTL);
+    }
+}
+
+/// Datagramas enviados que todavía no se confirmaron, con sus reintentos.
+#[derive(Debug)]
+pub struct Outbox {
+    next_seq: u64,
+    pending: BTreeMap<u64, Pending>,
+    retry_interval: Duration,
+    max_attempts: u32,
+}
+
+#[derive(Debug)]
+struct Pending {
+    text: String,
+    sent: Instant,
+    attempts: u32,
+}
+
+impl Outbox {
+    /// Bandeja vacía cuyo primer datagrama usa la secuencia `first_seq`.
+    ///
+    /// Cada datagrama se reenvía cada `retry_interval` hasta que se confirma
+    /// o se envió `max_attempts` veces.
+    pub fn new(first_seq: u64, retry_interval: Duration, max_attempts: u32) -> Self {
+        Outbox {
+            next_seq: first_seq,
+            pending: BTreeMap::new(),
+            retry_interval,
+            max_attempts,
+        }
+    }
+
+    /// Indica si se puede enviar otro datagrama sin salir de la ventana.
+    pub fn has_room(&self) -> bool {
+        self.pending
+            .keys()
+            .next()
+            .is_none_or(|oldest| self.next_seq - oldest < WINDOW)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Asigna la próxima secuencia a las operaciones de `lines`, enviadas en
+    /// `now` en un `LINES`, y retorna el texto del datagrama.
+    pub fn push(&mut self, lines: Vec<Vec<Operation>>, now: Instant) -> String {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let text = encode(seq, &Message::Lines(lines));
+        self.pending.insert(
+            seq,
+            Pending {
+                text: text.clone(),
+                sent: now,
+                attempts: 1,
+            },
+        );
+        text
+    }
+
+    /// Da por confirmado el datagrama `seq`. Retorna `false` si no estaba
+    /// pendiente (una confirmación repetida).
+    pub fn ack(&mut self, seq: u64) -> bool {
+        self.pending.remove(&seq).is_some()
+    }
+
+    /// Retorna los datagramas que hay que reenviar en `now` y los da por
+    /// reenviados.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si algún datagrama agotó sus reintentos.
+    pub fn due(&mut self, now: Instant) -> Result<Vec<String>, String> {
+        let mut resend = Vec::new();
+        for (seq, pending) in &mut self.pending {
+            if now.duration_since(pending.sent) < self.retry_interval {
+                continue;
+            }
+            if pending.attempts >= self.max_attempts {
+                return Err(format!(
+                    "El datagrama {} no se confirmo tras {} intentos",
+                    seq, pending.attempts
+                ));
+            }
+            pending.sent = now;
+            pending.attempts += 1;
+            resend.push(pending.text.clone());
+        }
+        Ok(resend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::Operator;
+    use crate::protocol::parse_message;
+
+    #[test]
+    fn test_encode() {
+        let op = Operation {
+            op: Operator::Add,
+            operand: 5,
+        };
+        let text = encode(7, &Message::Ops(vec![op, op]));
+        assert_eq!(text, "7 OPS + 5, + 5");
+        let (seq, rest) = split_seq(&text).unwrap();
+        assert_eq!(seq, 7);
+        assert!(matches!(parse_message(rest), Ok(Message::Ops(ops)) if ops.len() == 2));
+        assert_eq!(split_seq("OPS + 5"), None);
+        assert_eq!(split_seq("7"), None);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let now = Instant::now();
+        let mut dedup = Dedup::new();
+        assert_eq!(dedup.check("a", 10), Verdict::New);
+        dedup.record("a", 10, None, now);
+        assert_eq!(dedup.check("a", 10), Verdict::Repeated(None));
+        assert_eq!(dedup.check("b", 10), Verdict::New);
+
+        // Fuera de orden.
+        dedup.record("a", 12, Some("RESULTS ERROR \"overflow\"".to_string()), now);
+        assert_eq!(dedup.check("a", 11), Verdict::New);
+        dedup.record("a", 11, None, now);
+        assert_eq!(dedup.check("a", 11), Verdict::Repeated(None));
+        assert_eq!(
+            dedup.check("a", 12),
+            Verdict::Repeated(Some("RESULTS ERROR \"overflow\""))
+        );
+        assert_eq!(dedup.check("a", 13), Verdict::New);
+
+        // Lo que quedó fuera de la ventana cuenta como recibido.
+        dedup.record("a", 12 + WINDOW, None, now);
+        assert_eq!(dedup.check("a", 12), Verdict::Repeated(None));
+        assert_eq!(dedup.check("a", 13), Verdict::New);
+    }
+
+    #[test]
+    fn test_dedup_bounds_the_window_advance() {
+        let now = Instant::now();
+        let mut dedup = Dedup::new();
+        dedup.record("a", 10, None, now);
+        // una secuencia falsa muy adelantada no corre la ventana
+        assert_eq!(
+            dedup.check("a", u64::MAX),
+            Verdict::Rejected("sequence out of window")
+        );
+        assert_eq!(
+            dedup.check("a", 11 + WINDOW),
+            Verdict::Rejected("sequence out of window")
+        );
+        assert_eq!(dedup.check("a", 10 + WINDOW), Verdict::New);
+        assert_eq!(dedup.check("a", 11), Verdict::New);
+    }
+
+    #[test]
+    fn test_dedup_forgets_idle_senders() {
+        let now = Instant::now();
+        let mut dedup = Dedup::new();
+        dedup.record("a", 1, None, now);
+        dedup.record("b", 1, None, now + SENDER_TTL / 2);
+        dedup.prune(now + SENDER_TTL);
+        assert_eq!(dedup.check("a", 1), Verdict::New);
+        assert_eq!(dedup.check("b", 1), Verdict::Repeated(None));
+
+        for i in 1..MAX_SENDERS {
+            dedup.record(&i.to_string(), 1, None, now + SENDER_TTL);
+        }
+        assert_eq!(dedup.check("a", 1), Verdict::Rejected("too many senders"));
+        assert_eq!(dedup.check("b", 2), Verdict::New);
+    }
+
+    #[test]
+    fn test_outbox() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(100);
+        let mut outbox = Outbox::new(5, interval, 2);
+        let op = Operation {
+            op: Operator::Mul,
+            operand: 2,
+        };
+        assert_eq!(outbox.push(vec![vec![op]], now), "5 LINES * 2");
+        assert_eq!(outbox.push(vec![vec![op, op]], now), "6 LINES * 2, * 2");
+        assert!(outbox.ack(5));
+        assert!(!outbox.ack(5));
+
+        assert_eq!(outbox.due(now), Ok(Vec::new()));
+        assert_eq!(
+            outbox.due(now + interval),
+            Ok(vec!["6 LINES * 2, * 2".to_string()])
+        );
+        assert!(outbox.due(now + interval * 2).is_err());
+        assert!(outbox.ack(6));
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn test_outbox_window() {
+        let now = Instant::now();
+        let mut outbox = Outbox::new(0, Duration::from_secs(1), 3);
+        for _ in 0..WINDOW {
+            assert!(outbox.has_room());
+            outbox.push(Vec::new(), now);
+        }
+        assert!(!outbox.has_room());
+        // Confirmar uno posterior no libera la ventana; el más antiguo sí.
+        outbox.ack(1);
+        assert!(!outbox.has_room());
+        outbox.ack(0);
+        assert!(outbox.has_room());
+    }
+}
@@ -0,0 +1,463 @@
+use std::fmt;
+
+use crate::calculator::Arithmetic;
+use crate::operator::Operator;
+use crate::protocol::Operation;
+
+/// Nombre de la variable que representa el valor actual del servidor.
+const VARIABLE: &str = "x";
+
+/// Error al compilar una expresión, con la columna (desde 1) donde ocurrió.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExprError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl ExprError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        ExprError {
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "columna {}: {}", self.column, self.message)
+    }
+}
+
+/// Compila una línea del archivo de operaciones en la secuencia de
+/// operaciones que el servidor debe aplicar.
+///
+/// Se aceptan tres formas:
+/// - Operación simple, como en el protocolo: `+ 5`.
+/// - Expresión infija sobre `x` (el valor actual): `((x + 3) * 2) / 4`.
+/// - Asignación: `x = x * 7 + 1`. Si el lado derecho no usa `x`, el valor
+///   se reemplaza (`x = 8` compila a `* 0`, `+ 8`).
+///
+/// Como el servidor sólo aplica operaciones sobre el valor actual, `x` debe
+/// aparecer una única vez y a la izquierda de `-` y `/`; las subexpresiones
+/// constantes se calculan en el cliente con las reglas `arithmetic` del
+/// servidor (las que anuncia en `HELLO` con `numeric=` y `overflow=`), así
+/// `x = 200 + 100` da el mismo valor que aplicar ambas operaciones.
+///
+/// # Errores
+/// Retorna `Err(ExprError)` con la columna del problema si la línea no es
+/// válida, no puede expresarse como una secuencia de operaciones o una
+/// constante no entra en el tipo numérico.
+pub fn compile_line(line: &str, arithmetic: Arithmetic) -> Result<Vec<Operation>, ExprError> {
+    let tokens = tokenize(line)?;
+    if let [Token::Op(op, _), Token::Num(n, column)] = tokens.as_slice() {
+        return Ok(vec![Operation {
+            op: *op,
+            operand: constant(*n, *column, arithmetic)?,
+        }]);
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        end: line.chars().count() + 1,
+    };
+    let (assignment, expr) = parser.parse_line()?;
+
+    match (compile(&expr, arithmetic)?, assignment) {
+        (Compiled::Linear(ops), _) => Ok(ops),
+        (Compiled::Const(c), true) => Ok(vec![
+            Operation {
+                op: Operator::Mul,
+                operand: 0,
+            },
+            Operation {
+                op: Operator::Add,
+                operand: c,
+            },
+        ]),
+        (Compiled::Const(_), false) => Err(ExprError::new(
+            1,
+            format!(
+                "la expresion no usa {}; use '{} = ...' para asignar",
+                VARIABLE, VARIABLE
+            ),
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Num(u64, usize),
+    Var(usize),
+    Op(Operator, usize),
+    Assign(usize),
+    Open(usize),
+    Close(usize),
+}
+
+impl Token {
+    fn column(&self) -> usize {
+        match *self {
+            Token::Num(_, c)
+            | Token::Var(c)
+            | Token::Op(_, c)
+            | Token::Assign(c)
+            | Token::Open(c)
+            | Token::Close(c) => c,
+        }
+    }
+}
+
+/// Separa la línea en tokens, registrando la columna de cada uno.
+fn tokenize(line: &str) -> Result<Vec<Token>, ExprError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        match c {
+            _ if c.is_whitespace() => {}
+            '+' => tokens.push(Token::Op(Operator::Add, column)),
+            '-' => tokens.push(Token::Op(Operator::Sub, column)),
+            '*' => tokens.push(Token::Op(Operator::Mul, column)),
+            '/' => tokens.push(Token::Op(Operator::Div, column)),
+            '=' => tokens.push(Token::Assign(column)),
+            '(' => tokens.push(Token::Open(column)),
+            ')' => tokens.push(Token::Close(column)),
+            _ if c.is_ascii_digit() => {
+                let start = i;
+                while i + 1 < chars.len() && chars[i + 1].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..=i].iter().collect();
+                let n = digits.parse::<u64>().map_err(|_| {
+                    ExprError::new(column, format!("numero fuera de rango: {}", digits))
+                })?;
+                tokens.push(Token::Num(n, column));
+            }
+            _ if c.is_alphabetic() => {
+                let start = i;
+                while i + 1 < chars.len() && chars[i + 1].is_alphanumeric() {
+                    i += 1;
+                }
+                let name: String = chars[start..=i].iter().collect();
+                if name != VARIABLE {
+                    return Err(ExprError::new(
+                        column,
+                        format!("variable desconocida '{}' (solo existe {})", name, VARIABLE),
+                    ));
+                }
+                tokens.push(Token::Var(column));
+            }
+            _ => {
+                return Err(ExprError::new(
+                    column,
+                    format!("caracter inesperado '{}'", c),
+                ));
+            }
+        }
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Árbol de una expresión.
+#[derive(Debug)]
+enum Expr {
+    Num(u64, usize),
+    Var(usize),
+    Bin(Operator, usize, Box<Expr>, Box<Expr>),
+}
+
+/// Parser descendente recursivo:
+///
+/// ```text
+/// linea  := [ 'x' '=' ] expr
+/// expr   := term (('+' | '-') term)*
+/// term   := factor (('*' | '/') factor)*
+/// factor := NUMERO | 'x' | '(' expr ')'
+/// ```
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// Columna usada para errores al final de la línea.
+    end: usize,
+}
+
+impl Parser<'_> {
+    /// Parsea la línea completa; retorna si era una asignación y la expresión.
+    fn parse_line(&mut self) -> Result<(bool, Expr), ExprError> {
+        let assignment = matches!(self.tokens, [Token::Var(_), Token::Assign(_), ..]);
+        if assignment {
+            self.pos = 2;
+        }
+
+        let expr = self.parse_expr()?;
+        match self.peek() {
+            None => Ok((assignment, expr)),
+            Some(Token::Close(c)) => Err(ExprError::new(c, "')' sin '(' correspondiente")),
+            Some(t) => Err(ExprError::new(t.column(), "se esperaba un operador")),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_term()?;
+        while let Some(Token::Op(op @ (Operator::Add | Operator::Sub), column)) = self.peek() {
+            self.pos += 1;
+            let rhs = self.parse_term()?;
+            lhs = Expr::Bin(op, column, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_factor()?;
+        while let Some(Token::Op(op @ (Operator::Mul | Operator::Div), column)) = self.peek() {
+            self.pos += 1;
+            let rhs = self.parse_factor()?;
+            lhs = Expr::Bin(op, column, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_factor(&mut self) -> Result<Expr, ExprError> {
+        let token = self.peek();
+        self.pos += 1;
+        match token {
+            Some(Token::Num(n, c)) => Ok(Expr::Num(n, c)),
+            Some(Token::Var(c)) => Ok(Expr::Var(c)),
+            Some(Token::Open(c)) => {
+                let expr = self.parse_expr()?;
+                match self.peek() {
+                    Some(Token::Close(_)) => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    Some(t) => Err(ExprError::new(t.column(), "se esperaba ')'")),
+                    None => Err(ExprError::new(c, "'(' sin cerrar")),
+                }
+            }
+            Some(Token::Assign(c)) => Err(ExprError::new(
+                c,
+                format!("'=' solo se permite al comienzo: {} = ...", VARIABLE),
+            )),
+            Some(t) => Err(ExprError::new(
+                t.column(),
+                format!("se esperaba un numero, {} o '('", VARIABLE),
+            )),
+            None => Err(ExprError::new(self.end, "expresion incompleta")),
+        }
+    }
+
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).copied()
+    }
+}
+
+/// Resultado de compilar una subexpresión.
+enum Compiled {
+    /// No depende de `x`: se calcula en el cliente.
+    Const(u64),
+    /// Depende de `x`: operaciones a aplicar en orden sobre el valor actual.
+    Linear(Vec<Operation>),
+}
+
+fn compile(expr: &Expr, arithmetic: Arithmetic) -> Result<Compiled, ExprError> {
+    match expr {
+        Expr::Num(n, column) => constant(*n, *column, arithmetic).map(Compiled::Const),
+        Expr::Var(_) => Ok(Compiled::Linear(Vec::new())),
+        Expr::Bin(op, column, lhs, rhs) => {
+            let (op, column) = (*op, *column);
+            match (compile(lhs, arithmetic)?, compile(rhs, arithmetic)?) {
+                (Compiled::Const(a), Compiled::Const(b)) => {
+                    fold(op, a, b, column, arithmetic).map(Compiled::Const)
+                }
+                (Compiled::Linear(mut ops), Compiled::Const(operand)) => {
+                    ops.push(Operation { op, operand });
+                    Ok(Compiled::Linear(ops))
+                }
+                // + y * son conmutativas: `3 + x` equivale a `x + 3`.
+                (Compiled::Const(operand), Compiled::Linear(mut ops))
+                    if matches!(op, Operator::Add | Operator::Mul) =>
+                {
+                    ops.push(Operation { op, operand });
+                    Ok(Compiled::Linear(ops))
+                }
+                (Compiled::Const(_), Compiled::Linear(_)) => Err(ExprError::new(
+                    column,
+                    format!("{} debe quedar a la izquierda de '{}'", VARIABLE, op),
+                )),
+                (Compiled::Linear(_), Compiled::Linear(_)) => Err(ExprError::new(
+                    first_var(rhs).unwrap_or(column),
+                    format!("{} solo puede aparecer una vez", VARIABLE),
+                )),
+            }
+        }
+    }
+}
+
+/// Verifica que la constante `n`, en la columna `column`, entre en el tipo
+/// numérico.
+fn constant(n: u64, column: usize, arithmetic: Arithmetic) -> Result<u64, ExprError> {
+    if n <= arithmetic.numeric_type.max() {
+        Ok(n)
+    } else {
+        Err(ExprError::new(
+            column,
+            format!("{} no entra en {}", n, arithmetic.numeric_type),
+        ))
+    }
+}
+
+/// Calcula una operación entre constantes como lo haría el servidor (ver
+/// `Arithmetic::apply`); ambas ya entran en el tipo numérico.
+fn fold(
+    op: Operator,
+    a: u64,
+    b: u64,
+    column: usize,
+    arithmetic: Arithmetic,
+) -> Result<u64, ExprError> {
+    arithmetic
+        .apply(a, &Operation { op, operand: b })
+        .map_err(|motivo| match motivo.as_str() {
+            "division by zero" => ExprError::new(column, "division por cero"),
+            _ => ExprError::new(column, "la constante desborda"),
+        })
+}
+
+/// Columna de la primera aparición de `x` en la expresión.
+fn first_var(expr: &Expr) -> Option<usize> {
+    match expr {
+        Expr::Num(..) => None,
+        Expr::Var(c) => Some(*c),
+        Expr::Bin(_, _, lhs, rhs) => first_var(lhs).or_else(|| first_var(rhs)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(list: &[(Operator, u64)]) -> Vec<Operation> {
+        list.iter()
+            .map(|&(op, operand)| Operation { op, operand })
+            .collect()
+    }
+
+    #[test]
+    fn test_simple_operation() {
+        assert_eq!(
+            compile_line("+ 5", Arithmetic::default()).unwrap(),
+            ops(&[(Operator::Add, 5)])
+        );
+        assert_eq!(
+            compile_line("/ 0", Arithmetic::default()).unwrap(),
+            ops(&[(Operator::Div, 0)])
+        );
+
+        let err = compile_line("+ 300", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.to_string(), "columna 3: 300 no entra en u8");
+    }
+
+    #[test]
+    fn test_nested_expression() {
+        assert_eq!(
+            compile_line("((x + 3) * 2) / 4", Arithmetic::default()).unwrap(),
+            ops(&[(Operator::Add, 3), (Operator::Mul, 2), (Operator::Div, 4)])
+        );
+    }
+
+    #[test]
+    fn test_assignment_with_precedence() {
+        assert_eq!(
+            compile_line("x = x * 7 + 1", Arithmetic::default()).unwrap(),
+            ops(&[(Operator::Mul, 7), (Operator::Add, 1)])
+        );
+    }
+
+    #[test]
+    fn test_constant_folding_and_commutativity() {
+        assert_eq!(
+            compile_line("2 * (x + 1) - 3 * 4", Arithmetic::default()).unwrap(),
+            ops(&[(Operator::Add, 1), (Operator::Mul, 2), (Operator::Sub, 12)])
+        );
+    }
+
+    #[test]
+    fn test_constant_assignment() {
+        assert_eq!(
+            compile_line("x = 4 * 2", Arithmetic::default()).unwrap(),
+            ops(&[(Operator::Mul, 0), (Operator::Add, 8)])
+        );
+        assert_eq!(compile_line("x", Arithmetic::default()).unwrap(), ops(&[]));
+    }
+
+    #[test]
+    fn test_errors_report_column() {
+        let err = compile_line("10 - x", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 4);
+
+        let err = compile_line("x + x", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 5);
+
+        let err = compile_line("(x + 3", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 1);
+
+        let err = compile_line("x + 3)", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 6);
+
+        let err = compile_line("x + y", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 5);
+
+        let err = compile_line("x * (1 / 0)", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 8);
+
+        let err = compile_line("x +", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 4);
+
+        let err = compile_line("% 5", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.to_string(), "columna 1: caracter inesperado '%'");
+
+        assert!(compile_line("3 + 4", Arithmetic::default()).is_err());
+    }
+
+    #[test]
+    fn test_folding_uses_server_arithmetic() {
+        let arithmetic = |numeric_type: &str, overflow: &str| Arithmetic {
+            numeric_type: numeric_type.parse().unwrap(),
+            overflow: overflow.parse().unwrap(),
+        };
+
+        // 200 + 100 en u8 con wrapping es 44, como al aplicar ambas.
+        assert_eq!(
+            compile_line("x = 200 + 100", arithmetic("u8", "wrapping")).unwrap(),
+            ops(&[(Operator::Mul, 0), (Operator::Add, 44)])
+        );
+        assert_eq!(
+            compile_line("x = 200 + 100", arithmetic("u8", "saturating")).unwrap(),
+            ops(&[(Operator::Mul, 0), (Operator::Add, 255)])
+        );
+        assert_eq!(
+            compile_line("x = 200 + 100", arithmetic("u16", "error")).unwrap(),
+            ops(&[(Operator::Mul, 0), (Operator::Add, 300)])
+        );
+        assert_eq!(
+            compile_line("x - (1 - 2)", arithmetic("u8", "wrapping")).unwrap(),
+            ops(&[(Operator::Sub, 255)])
+        );
+
+        let err = compile_line("x = 200 + 100", arithmetic("u8", "error")).unwrap_err();
+        assert_eq!(err.to_string(), "columna 9: la constante desborda");
+
+        // Una constante que no entra se rechaza antes de enviar nada.
+        let err = compile_line("x = 300", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.to_string(), "columna 5: 300 no entra en u8");
+        let err = compile_line("x * 2 + 256", Arithmetic::default()).unwrap_err();
+        assert_eq!(err.column, 9);
+    }
+}
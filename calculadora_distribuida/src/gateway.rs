@@ -0,0 +1,357 @@
+use crate::base64;
+use crate::http::{Request, Response};
+use crate::json::{self, Json};
+use crate::protocol::{Message, Operation};
+
+/// Realm anunciado en `WWW-Authenticate` cuando falta autenticarse.
+const REALM: &str = "Basic realm=\"calculadora\"";
+
+/// Atiende un pedido HTTP traduciéndolo a mensajes del protocolo.
+///
+/// - `GET /value` → `GET`; responde `{"value": v, "version": n}`.
+/// - `POST /ops` con `{"op": "+", "operand": 5, "version": n}` (la versión
+///   es opcional) → `OP + 5 [@n]`; responde `{"version": n}`.
+/// - `POST /batch` con `{"ops": [{"op": "+", "operand": 5}, ...]}` → `OPS`;
+///   responde `{"results": [...]}` con un resultado por operación.
+///
+/// Si el pedido trae `Authorization: Basic`, antes se envía `AUTH` con esas
+/// credenciales. `exec` envía un mensaje al servidor y retorna su respuesta.
+/// Los `ERROR` se responden como `{"error": motivo}` con el código de
+/// `status_for`, y un conflicto de versión como 409 con el valor y la
+/// versión actuales.
+///
+/// # Errores
+/// Retorna `Err(String)` si `exec` falla; en ese caso no hay respuesta que
+/// enviar.
+pub fn handle<F>(request: &Request, mut exec: F) -> Result<Response, String>
+where
+    F: FnMut(Message) -> Result<Message, String>,
+{
+    let message = match route(request) {
+        Ok(message) => message,
+        Err(response) => return Ok(response),
+    };
+
+    if let Some(authorization) = request.header("authorization") {
+        let Some((user, password)) = parse_basic(authorization) else {
+            return Ok(error_response("authentication failed"));
+        };
+        if let Message::Err(motivo) = exec(Message::Auth { user, password })? {
+            return Ok(error_response(&motivo));
+        }
+    }
+
+    Ok(match exec(message)? {
+        Message::Value(v, version) => Response::json(
+            200,
+            &Json::Object(
+                [("value".to_string(), Json::number(v))]
+                    .into_iter()
+                    .chain(version_field(version))
+                    .collect(),
+            ),
+        ),
+        Message::Results(results) => Response::json(
+            200,
+            &Json::object([(
+                "results",
+                Json::Array(results.iter().map(result_json).collect()),
+            )]),
+        ),
+        conflict @ Message::Conflict(..) => Response::json(409, &result_json(&conflict)),
+        Message::Err(motivo) => error_response(&motivo),
+        ok @ Message::Ok(_) => Response::json(200, &result_json(&ok)),
+        other => Response::error(500, &format!("unexpected response: {}", other)),
+    })
+}
+
+/// Código de estado HTTP para el motivo de un `ERROR` del servidor.
+pub fn status_for(motivo: &str) -> u16 {
+    match motivo {
+        "authentication required" | "authentication failed" => 401,
+        "batch too large" => 413,
+        "division by zero" | "overflow" | "operand out of range" => 422,
+        "parsing error" | "unexpected message" => 400,
+        m if m.starts_with("rate limited") => 429,
+        _ => 500,
+    }
+}
+
+/// Respuesta para un `ERROR` del servidor, con `Retry-After` o
+/// `WWW-Authenticate` cuando corresponde.
+fn error_response(motivo: &str) -> Response {
+    let status = status_for(motivo);
+    let response = Response::error(status, motivo);
+    match status {
+        401 => response.with_header("WWW-Authenticate", REALM),
+        429 => {
+            // "rate limited; retry after <ms> ms", redondeado a segundos.
+            let ms: u64 = motivo
+                .split_whitespace()
+                .rev()
+                .nth(1)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(1000);
+            let seconds = ms.div_ceil(1000).max(1);
+            response.with_header("Retry-After", &seconds.to_string())
+        }
+        _ => response,
+    }
+}
+
+/// Traduce la ruta y el cuerpo al mensaje del protocolo, o a la respuesta
+/// de error si el pedido es inválido.
+fn route(request: &Request) -> Result<Message, Response> {
+    let method = |allowed: &str| {
+        if request.method == allowed {
+            Ok(())
+        } else {
+            Err(Response::error(405, "method not allowed").with_header("Allow", allowed))
+        }
+    };
+    match request.path.as_str() {
+        "/value" => {
+            method("GET")?;
+            Ok(Message::Get)
+        }
+        "/ops" => {
+            method("POST")?;
+            let body = parse_body(request)?;
+            let version = match body.get("version") {
+                None | Some(Json::Null) => None,
+                Some(v) => Some(v.as_u64().ok_or_else(|| {
+                    Response::error(400, "version must be a non-negative integer")
+                })?),
+            };
+            Ok(Message::Op(parse_operation(&body)?, version))
+        }
+        "/batch" => {
+            method("POST")?;
+            let body = parse_body(request)?;
+            let ops = body
+                .get("ops")
+                .and_then(Json::as_array)
+                .ok_or_else(|| Response::error(400, "ops must be an array"))?
+                .iter()
+                .map(parse_operation)
+                .collect::<Result<Vec<_>, _>>()?;
+            if ops.is_empty() {
+                return Err(Response::error(400, "ops must not be empty"));
+            }
+            Ok(Message::Ops(ops))
+        }
+        _ => Err(Response::error(404, "not found")),
+    }
+}
+
+fn parse_body(request: &Request) -> Result<Json, Response> {
+    let text = std::str::from_utf8(&request.body)
+        .map_err(|_| Response::error(400, "body must be UTF-8"))?;
+    json::parse(text).map_err(|e| Response::error(400, &e))
+}
+
+/// Interpreta `{"op": "+", "operand": 5}`.
+fn parse_operation(json: &Json) -> Result<Operation, Response> {
+    let op = json
+        .get("op")
+        .and_then(Json::as_str)
+        .ok_or_else(|| Response::error(400, "op must be one of + - * /"))?
+        .parse()
+        .map_err(|_| Response::error(400, "op must be one of + - * /"))?;
+    let operand = json
+        .get("operand")
+        .and_then(Json::as_u64)
+        .ok_or_else(|| Response::error(400, "operand must be a non-negative integer"))?;
+    Ok(Operation { op, operand })
+}
+
+/// Un resultado de operación como objeto: `{"version": n}`,
+/// `{"error": motivo}` o el conflicto con el valor y la versión actuales.
+fn result_json(result: &Message) -> Json {
+    let pairs = match result {
+        Message::Ok(version) => version_field(*version).into_iter().collect(),
+        Message::Err(motivo) => vec![("error".to_string(), Json::String(motivo.clone()))],
+        Message::Conflict(v, version) => [
+            ("error".to_string(), Json::String("conflict".to_string())),
+            ("value".to_string(), Json::number(*v)),
+        ]
+        .into_iter()
+        .chain(version_field(*version))
+        .collect(),
+        other => vec![(
+            "error".to_string(),
+            Json::String(format!("unexpected response: {}", other)),
+        )],
+    };
+    Json::Object(pairs)
+}
+
+fn version_field(version: Option<u64>) -> Option<(String, Json)> {
+    version.map(|n| ("version".to_string(), Json::number(n)))
+}
+
+/// Interpreta `Basic <base64(usuario:contraseña)>`.
+fn parse_basic(header: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64::decode(encoded.trim())?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::Operator;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    /// Atiende `request` con un servidor falso que responde `reply` y
+    /// retorna la respuesta y los mensajes recibidos.
+    fn run(request: &Request, reply: Message) -> (Response, Vec<Message>) {
+        let mut sent = Vec::new();
+        let response = handle(request, |m| {
+            sent.push(m);
+            Ok(reply.clone())
+        })
+        .unwrap();
+        (response, sent)
+    }
+
+    #[test]
+    fn test_get_value() {
+        let (response, sent) = run(&request("GET", "/value", ""), Message::Value(5, Some(2)));
+        assert_eq!(sent, [Message::Get]);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, r#"{"value":5,"version":2}"#);
+    }
+
+    #[test]
+    fn test_post_ops() {
+        let body = r#"{"op": "*", "operand": 3, "version": 1}"#;
+        let (response, sent) = run(&request("POST", "/ops", body), Message::Ok(Some(2)));
+        let op = Operation {
+            op: Operator::Mul,
+            operand: 3,
+        };
+        assert_eq!(sent, [Message::Op(op, Some(1))]);
+        assert_eq!(
+            (response.status, response.body.as_str()),
+            (200, r#"{"version":2}"#)
+        );
+
+        let (response, _) = run(
+            &request("POST", "/ops", body),
+            Message::Conflict(7, Some(4)),
+        );
+        assert_eq!(response.status, 409);
+        assert_eq!(
+            response.body,
+            r#"{"error":"conflict","value":7,"version":4}"#
+        );
+    }
+
+    #[test]
+    fn test_post_batch() {
+        let body = r#"{"ops": [{"op": "+", "operand": 1}, {"op": "/", "operand": 0}]}"#;
+        let reply = Message::Results(vec![
+            Message::Ok(Some(1)),
+            Message::Err("division by zero".to_string()),
+        ]);
+        let (response, sent) = run(&request("POST", "/batch", body), reply);
+        assert!(matches!(&sent[..], [Message::Ops(ops)] if ops.len() == 2));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.body,
+            r#"{"results":[{"version":1},{"error":"division by zero"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_error_status_codes() {
+        let cases = [
+            ("division by zero", 422),
+            ("overflow", 422),
+            ("operand out of range", 422),
+            ("authentication required", 401),
+            ("batch too large", 413),
+            ("parsing error", 400),
+            ("rate limited; retry after 1500 ms", 429),
+            ("Estado inaccesible", 500),
+        ];
+        for (motivo, status) in cases {
+            let (response, _) = run(
+                &request("POST", "/ops", r#"{"op":"+","operand":1}"#),
+                Message::Err(motivo.to_string()),
+            );
+            assert_eq!(response.status, status, "{}", motivo);
+        }
+        let response = error_response("rate limited; retry after 1500 ms");
+        assert!(
+            response
+                .headers
+                .contains(&("Retry-After".into(), "2".into()))
+        );
+        let response = error_response("authentication required");
+        assert!(
+            response
+                .headers
+                .contains(&("WWW-Authenticate".into(), REALM.into()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let status = |method: &str, path: &str, body: &str| {
+            let (response, sent) = run(&request(method, path, body), Message::Ok(None));
+            assert!(sent.is_empty());
+            response.status
+        };
+        assert_eq!(status("GET", "/nada", ""), 404);
+        assert_eq!(status("POST", "/value", ""), 405);
+        assert_eq!(status("GET", "/ops", ""), 405);
+        assert_eq!(status("POST", "/ops", "no es json"), 400);
+        assert_eq!(status("POST", "/ops", r#"{"op":"%","operand":1}"#), 400);
+        assert_eq!(status("POST", "/ops", r#"{"op":"+","operand":-1}"#), 400);
+        assert_eq!(
+            status("POST", "/ops", r#"{"op":"+","operand":1,"version":"a"}"#),
+            400
+        );
+        assert_eq!(status("POST", "/batch", r#"{"ops":[]}"#), 400);
+        assert_eq!(status("POST", "/batch", r#"{"ops":{}}"#), 400);
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let mut req = request("GET", "/value", "");
+        // "ana:secreta"
+        req.headers
+            .push(("Authorization".into(), "Basic YW5hOnNlY3JldGE=".into()));
+        let (response, sent) = run(&req, Message::Value(0, Some(0)));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            sent[0],
+            Message::Auth {
+                user: "ana".into(),
+                password: "secreta".into()
+            }
+        );
+
+        let (response, sent) = run(&req, Message::Err("authentication failed".into()));
+        assert_eq!(response.status, 401);
+        assert_eq!(sent.len(), 1);
+
+        req.headers[0].1 = "Bearer abc".into();
+        assert_eq!(run(&req, Message::Ok(None)).0.status, 401);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// Cantidad de versiones que se conservan si no se configura otra.
+pub const DEFAULT_HISTORY_SIZE: usize = 10_000;
+
+/// Cantidad máxima de instantáneas; al tomar una más se descarta la más
+/// antigua.
+pub const MAX_SNAPSHOTS: usize = 100;
+
+/// El valor en una versión, y cuándo se llegó a ella.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub value: u64,
+    pub version: u64,
+    /// Segundos desde la época Unix.
+    pub time: u64,
+}
+
+/// Historial de las últimas versiones del valor, para leer valores pasados,
+/// e instantáneas con identificador, para volver a ellos.
+///
+/// Se guarda sólo en memoria: al reiniciar el servidor el historial empieza
+/// en el valor restaurado y las instantáneas se pierden. Para que el
+/// identificador de una instantánea perdida no pase a nombrar otra, la
+/// numeración empieza donde indique quien crea el historial.
+#[derive(Debug)]
+pub struct Timeline {
+    /// Versiones en orden creciente; la última es la actual.
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    snapshots: BTreeMap<u64, Entry>,
+    next_snapshot: u64,
+}
+
+impl Timeline {
+    /// Historial que empieza en `initial` y conserva hasta `capacity`
+    /// versiones (al menos una, la actual); la primera instantánea será
+    /// `first_snapshot`.
+    pub fn new(initial: Entry, capacity: usize, first_snapshot: u64) -> Self {
+        Timeline {
+            entries: VecDeque::from([initial]),
+            capacity: capacity.max(1),
+            snapshots: BTreeMap::new(),
+            next_snapshot: first_snapshot,
+        }
+    }
+
+    /// Registra una nueva versión; si se supera la capacidad se olvida la
+    /// más antigua.
+    pub fn record(&mut self, entry: Entry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// La versión actual.
+    pub fn current(&self) -> Entry {
+        // `new` y `record` garantizan al menos una entrada.
+        self.entries[self.entries.len() - 1]
+    }
+
+    /// El valor en la versión `version`.
+    ///
+    /// # Errores
+    /// - `"version not reached"` si la versión todavía no existe.
+    /// - `"version no longer in history"` si ya se olvidó.
+    pub fn at_version(&self, version: u64) -> Result<Entry, String> {
+        if version > self.current().version {
+            return Err("version not reached".to_string());
+        }
+        let i = self.entries.partition_point(|e| e.version < version);
+        match self.entries.get(i) {
+            Some(entry) if entry.version == version => Ok(*entry),
+            _ => Err("version no longer in history".to_string()),
+        }
+    }
+
+    /// El valor vigente en el instante `time`: la última versión a la que
+    /// se llegó hasta ese segundo inclusive.
+    ///
+    /// # Errores
+    /// Retorna `"time before history"` si `time` es anterior a la versión
+    /// más antigua que se conserva.
+    pub fn at_time(&self, time: u64) -> Result<Entry, String> {
+        match self.entries.partition_point(|e| e.time <= time) {
+            0 => Err("time before history".to_string()),
+            i => Ok(self.entries[i - 1]),
+        }
+    }
+
+    /// Guarda la versión actual como instantánea y retorna su identificador.
+    pub fn snapshot(&mut self) -> u64 {
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_first();
+        }
+        let id = self.next_snapshot;
+        self.next_snapshot = self.next_snapshot.wrapping_add(1);
+        self.snapshots.insert(id, self.current());
+        id
+    }
+
+    /// La instantánea `id`, si existe.
+    pub fn get_snapshot(&self, id: u64) -> Option<Entry> {
+        self.snapshots.get(&id).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(value: u64, version: u64, time: u64) -> Entry {
+        Entry {
+            value,
+            version,
+            time,
+        }
+    }
+
+    #[test]
+    fn test_at_version() {
+        let mut timeline = Timeline::new(entry(7, 3, 100), 3, 1);
+        timeline.record(entry(8, 4, 100));
+        timeline.record(entry(9, 5, 105));
+        assert_eq!(timeline.at_version(3), Ok(entry(7, 3, 100)));
+        assert_eq!(timeline.at_version(5).map(|e| e.value), Ok(9));
+        assert!(timeline.at_version(6).is_err());
+
+        timeline.record(entry(10, 6, 110));
+        assert_eq!(
+            timeline.at_version(3),
+            Err("version no longer in history".to_string())
+        );
+        assert_eq!(timeline.current(), entry(10, 6, 110));
+    }
+
+    #[test]
+    fn test_at_time() {
+        let mut timeline = Timeline::new(entry(0, 0, 100), 10, 1);
+        timeline.record(entry(1, 1, 100));
+        timeline.record(entry(2, 2, 105));
+        assert_eq!(timeline.at_time(99), Err("time before history".to_string()));
+        assert_eq!(timeline.at_time(100).map(|e| e.version), Ok(1));
+        assert_eq!(timeline.at_time(104).map(|e| e.version), Ok(1));
+        assert_eq!(timeline.at_time(u64::MAX).map(|e| e.version), Ok(2));
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let mut timeline = Timeline::new(entry(5, 1, 0), 1, 1_000);
+        let first = timeline.snapshot();
+        assert_eq!(first, 1_000);
+        timeline.record(entry(6, 2, 0));
+        // la instantánea sobrevive aunque su versión salga del historial
+        assert_eq!(timeline.get_snapshot(first), Some(entry(5, 1, 0)));
+        assert_eq!(timeline.get_snapshot(first + 1), None);
+
+        for _ in 0..MAX_SNAPSHOTS {
+            timeline.snapshot();
+        }
+        assert_eq!(timeline.get_snapshot(first), None);
+        assert_eq!(timeline.get_snapshot(first + 1).map(|e| e.value), Some(6));
+    }
+}
@@ -0,0 +1,295 @@
+use std::io::{self, BufRead, Write};
+
+use crate::connection::{LineError, read_line_limited};
+use crate::json::Json;
+
+/// Tamaño máximo del cuerpo de un pedido, suficiente para un lote de
+/// `MAX_BATCH_SIZE` operaciones.
+pub const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Cantidad máxima de encabezados de un pedido.
+pub const MAX_HEADERS: usize = 64;
+
+/// Pedido HTTP/1.x.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Ruta sin la query string.
+    pub path: String,
+    /// `HTTP/1.0` o `HTTP/1.1`.
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Valor del encabezado `name`, sin distinguir mayúsculas.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Indica si la conexión sigue abierta después de responder: por
+    /// defecto en HTTP/1.1, y en HTTP/1.0 sólo con `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        match self.header("connection") {
+            Some(c) if c.eq_ignore_ascii_case("close") => false,
+            Some(c) if c.eq_ignore_ascii_case("keep-alive") => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+}
+
+/// Motivos por los que no se pudo leer un pedido.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestError {
+    /// Falló la lectura de la conexión.
+    Line(LineError),
+    /// El pedido es inválido; se responde con el código y el motivo y se
+    /// cierra la conexión.
+    Status(u16, String),
+}
+
+/// Lee un pedido de `reader`. Ninguna línea del encabezado puede superar
+/// `max_line_length` bytes.
+///
+/// # Retorno
+/// - `Ok(Some(pedido))` si se leyó un pedido completo.
+/// - `Ok(None)` si el cliente cerró la conexión entre pedidos.
+/// - `Err(RequestError)` si falló la lectura o el pedido es inválido.
+pub fn read_request<R: BufRead>(
+    reader: &mut R,
+    max_line_length: usize,
+) -> Result<Option<Request>, RequestError> {
+    let read_line = |reader: &mut R| match read_line_limited(reader, max_line_length, None) {
+        Err(LineError::TooLong) => Err(RequestError::Status(
+            431,
+            "header line too long".to_string(),
+        )),
+        other => other.map_err(RequestError::Line),
+    };
+
+    // Se toleran líneas vacías antes del pedido (RFC 9112, 2.2).
+    let request_line = loop {
+        match read_line(reader)? {
+            None => return Ok(None),
+            Some(line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+    let bad_request = || RequestError::Status(400, "malformed request".to_string());
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(bad_request());
+    };
+    if version != "HTTP/1.1" && version != "HTTP/1.0" {
+        return Err(RequestError::Status(
+            505,
+            "HTTP version not supported".to_string(),
+        ));
+    }
+    let path = target.split('?').next().unwrap_or(target);
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or_else(bad_request)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(RequestError::Status(431, "too many headers".to_string()));
+        }
+        let (name, value) = line.split_once(':').ok_or_else(bad_request)?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        version: version.to_string(),
+        headers,
+        body: Vec::new(),
+    };
+    if request.header("transfer-encoding").is_some() {
+        return Err(RequestError::Status(
+            501,
+            "transfer-encoding not supported".to_string(),
+        ));
+    }
+    let length = match request.header("content-length") {
+        Some(n) => n.parse::<usize>().map_err(|_| bad_request())?,
+        None => 0,
+    };
+    if length > MAX_BODY_SIZE {
+        return Err(RequestError::Status(413, "body too large".to_string()));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).map_err(|e| {
+        RequestError::Line(match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => LineError::Timeout,
+            _ => LineError::Io(e.to_string()),
+        })
+    })?;
+    Ok(Some(request))
+}
+
+/// Respuesta HTTP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    /// Respuesta con `body` serializado como JSON.
+    pub fn json(status: u16, body: &Json) -> Self {
+        Response {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    /// Respuesta de error con cuerpo `{"error": motivo}`.
+    pub fn error(status: u16, motivo: &str) -> Self {
+        Self::json(
+            status,
+            &Json::object([("error", Json::String(motivo.into()))]),
+        )
+    }
+
+    /// Agrega un encabezado.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Escribe la respuesta completa, indicando con `Connection` si la
+    /// conexión sigue abierta, salvo que la respuesta ya lo indique (como
+    /// `Connection: Upgrade`). Las respuestas 1xx no llevan cuerpo.
+    pub fn write_to<W: Write>(&self, writer: &mut W, keep_alive: bool) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if self.status >= 200 {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        if !self
+            .headers
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case("connection"))
+        {
+            let connection = if keep_alive { "keep-alive" } else { "close" };
+            head.push_str(&format!("Connection: {}\r\n", connection));
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(self.body.as_bytes())?;
+        writer.flush()
+    }
+}
+
+/// Frase estándar del código de estado.
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read(text: &str) -> Result<Option<Request>, RequestError> {
+        read_request(&mut Cursor::new(text.as_bytes().to_vec()), 1024)
+    }
+
+    #[test]
+    fn test_read_request() {
+        let text = "\r\nPOST /ops?x=1 HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello";
+        let request = read(text).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/ops");
+        assert_eq!(request.header("content-LENGTH"), Some("5"));
+        assert_eq!(request.body, b"hello");
+        assert!(request.keep_alive());
+        assert_eq!(read("").unwrap(), None);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let request = read("GET / HTTP/1.0\r\n\r\n").unwrap().unwrap();
+        assert!(!request.keep_alive());
+        let request = read("GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert!(!request.keep_alive());
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let status = |text: &str| match read(text) {
+            Err(RequestError::Status(status, _)) => status,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(status("GET /\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/2\r\n\r\n"), 505);
+        assert_eq!(status("GET / HTTP/1.1\r\nsin dos puntos\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n"), 400);
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: 999999\r\n\r\n"),
+            413
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            501
+        );
+        assert_eq!(
+            status(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(2000))),
+            431
+        );
+        assert_eq!(status("GET / HTTP/1.1\r\n"), 400);
+        assert!(matches!(
+            read("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc"),
+            Err(RequestError::Line(LineError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut out = Vec::new();
+        Response::error(429, "slow down")
+            .with_header("Retry-After", "1")
+            .write_to(&mut out, false)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 429 Too Many Requests\r\nContent-Type: application/json\r\n\
+             Retry-After: 1\r\nContent-Length: 21\r\nConnection: close\r\n\r\n\
+             {\"error\":\"slow down\"}"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::fmt;
+
+/// Profundidad máxima de anidamiento que acepta `parse`, para que una
+/// entrada maliciosa no agote la pila.
+pub const MAX_DEPTH: usize = 32;
+
+/// Valor JSON.
+///
+/// Los números se guardan con su texto original para no perder precisión
+/// en enteros mayores a 2^53; `as_u64` los interpreta.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /// Pares en el orden en que aparecen.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Número entero no negativo.
+    pub fn number(n: u64) -> Json {
+        Json::Number(n.to_string())
+    }
+
+    /// Objeto con las claves y valores dados.
+    pub fn object<const N: usize>(pairs: [(&str, Json); N]) -> Json {
+        Json::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    /// Valor de la clave `key`, si es un objeto que la contiene.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    /// Serializa el valor sin espacios.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Interpreta un documento JSON completo.
+///
+/// # Errores
+/// Retorna `Err(String)` indicando la posición (en bytes) del problema si
+/// el texto no es JSON válido, tiene contenido después del valor o supera
+/// `MAX_DEPTH` niveles de anidamiento.
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error("contenido despues del valor"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!("JSON invalido en la posicion {}: {}", self.pos, msg)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.bump() {
+            Some(found) if found == c => Ok(()),
+            _ => Err(self.error(&format!("se esperaba '{}'", c))),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("demasiados niveles de anidamiento"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => self.string().map(Json::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => self.literal(),
+            None => Err(self.error("se esperaba un valor")),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Json, String> {
+        for (word, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if self.text[self.pos..].starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        Err(self.error("valor desconocido"))
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        let digits = |p: &mut Self| {
+            let from = p.pos;
+            while let Some('0'..='9') = p.peek() {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+        if !digits(self) {
+            return Err(self.error("numero invalido"));
+        }
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("numero invalido"));
+            }
+        }
+        if let Some('e' | 'E') = self.peek() {
+            self.pos += 1;
+            if let Some('+' | '-') = self.peek() {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("numero invalido"));
+            }
+        }
+        Ok(Json::Number(self.text[start..self.pos].to_string()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("escape invalido")),
+                    };
+                    s.push(c);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("caracter de control en una cadena"));
+                }
+                Some(c) => s.push(c),
+                None => return Err(self.error("cadena sin cerrar")),
+            }
+        }
+    }
+
+    /// Interpreta `XXXX` después de `\u`, incluidos los pares sustitutos.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.text[self.pos..].starts_with("\\u") {
+                return Err(self.error("par sustituto incompleto"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("par sustituto invalido"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("codigo unicode invalido"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let hex = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("escape unicode incompleto"))?;
+        let code =
+            u32::from_str_radix(hex, 16).map_err(|_| self.error("escape unicode invalido"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err(self.error("se esperaba ',' o ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut pairs = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(pairs));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            pairs.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(Json::Object(pairs)),
+                _ => return Err(self.error("se esperaba ',' o '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        let json = parse(
+            r#" {"op": "+", "operand": 18446744073709551615, "ok": true, "x": [null, -1.5e3]} "#,
+        )
+        .unwrap();
+        assert_eq!(json.get("op").and_then(Json::as_str), Some("+"));
+        assert_eq!(json.get("operand").and_then(Json::as_u64), Some(u64::MAX));
+        assert_eq!(json.get("ok"), Some(&Json::Bool(true)));
+        assert_eq!(
+            json.get("x").and_then(Json::as_array),
+            Some(&[Json::Null, Json::Number("-1.5e3".to_string())][..])
+        );
+        assert_eq!(json.get("missing"), None);
+    }
+
+    #[test]
+    fn test_parse_strings() {
+        assert_eq!(
+            parse(r#""a\"b\\c\n\u00e9\ud83d\ude00""#).unwrap(),
+            Json::String("a\"b\\c\né😀".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in [
+            "",
+            "{",
+            "{\"a\" 1}",
+            "[1,]",
+            "01x",
+            "\"abc",
+            "tru",
+            "{} {}",
+            "\"\\x\"",
+            "-",
+            "\"\\ud800\"",
+        ] {
+            assert!(parse(text).is_err(), "{:?}", text);
+        }
+        let deep = "[".repeat(MAX_DEPTH + 2) + &"]".repeat(MAX_DEPTH + 2);
+        assert!(parse(&deep).unwrap_err().contains("anidamiento"));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let json = Json::object([
+            ("error", Json::String("say \"hi\"\n".to_string())),
+            ("value", Json::number(5)),
+            ("results", Json::Array(vec![Json::Null, Json::Bool(false)])),
+        ]);
+        let text = json.to_string();
+        assert_eq!(
+            text,
+            r#"{"error":"say \"hi\"\n","value":5,"results":[null,false]}"#
+        );
+        assert_eq!(parse(&text).unwrap(), json);
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Canal que conserva sólo el último valor enviado.
+///
+/// Cada envío reemplaza al valor que el receptor todavía no leyó, así un
+/// receptor lento no acumula valores viejos sino que recibe siempre el
+/// último. Los errores son los de `std::sync::mpsc`.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        slot: Mutex::new(Slot {
+            value: None,
+            closed: false,
+        }),
+        ready: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    slot: Mutex<Slot<T>>,
+    ready: Condvar,
+}
+
+struct Slot<T> {
+    /// Último valor sin leer.
+    value: Option<T>,
+    /// Alguno de los extremos se descartó.
+    closed: bool,
+}
+
+impl<T> Shared<T> {
+    /// Marca el canal como cerrado y despierta al receptor.
+    fn close(&self) {
+        if let Ok(mut slot) = self.slot.lock() {
+            slot.closed = true;
+        }
+        self.ready.notify_all();
+    }
+}
+
+/// Extremo que envía (ver `channel`).
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Deja `value` como el último valor, reemplazando al que no se leyó.
+    ///
+    /// # Errores
+    /// Retorna `Err(value)` si el receptor ya se descartó.
+    pub fn send(&self, value: T) -> Result<(), T> {
+        let Ok(mut slot) = self.shared.slot.lock() else {
+            return Err(value);
+        };
+        if slot.closed {
+            return Err(value);
+        }
+        slot.value = Some(value);
+        drop(slot);
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+/// Extremo que recibe (ver `channel`).
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Retorna el último valor sin esperar.
+    ///
+    /// # Errores
+    /// `TryRecvError::Empty` si no hay valor nuevo, o
+    /// `TryRecvError::Disconnected` si además el emisor se descartó.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut slot = self
+            .shared
+            .slot
+            .lock()
+            .map_err(|_| TryRecvError::Disconnected)?;
+        match slot.value.take() {
+            Some(value) => Ok(value),
+            None if slot.closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Espera el próximo valor.
+    ///
+    /// # Errores
+    /// Retorna `Err(RecvError)` si el emisor se descartó sin dejar un valor
+    /// por leer.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.wait(None).map_err(|_| RecvError)
+    }
+
+    /// Espera el próximo valor a lo sumo `timeout`.
+    ///
+    /// # Errores
+    /// `RecvTimeoutError::Timeout` si no llegó ninguno a tiempo, o
+    /// `RecvTimeoutError::Disconnected` si el emisor se descartó sin dejar
+    /// un valor por leer.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    fn wait(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut slot = self
+            .shared
+            .slot
+            .lock()
+            .map_err(|_| RecvTimeoutError::Disconnected)?;
+        loop {
+            if let Some(value) = slot.value.take() {
+                return Ok(value);
+            }
+            if slot.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let waited = match deadline {
+                None => self.shared.ready.wait(slot).ok(),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    self.shared
+                        .ready
+                        .wait_timeout(slot, left)
+                        .ok()
+                        .map(|(s, _)| s)
+                }
+            };
+            slot = waited.ok_or(RecvTimeoutError::Disconnected)?;
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_keeps_only_the_latest_value() {
+        let (sender, receiver) = channel();
+        for i in 0..1000 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.try_recv(), Ok(999));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (sender, receiver) = channel();
+        sender.send(1).unwrap();
+        drop(sender);
+        // el último valor se lee aunque el emisor ya no exista
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.recv(), Err(RecvError));
+
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(sender.send(2), Err(2));
+    }
+
+    #[test]
+    fn test_recv_waits_for_a_value() {
+        let (sender, receiver) = channel();
+        let handle = thread::spawn(move || receiver.recv_timeout(Duration::from_secs(5)));
+        thread::sleep(Duration::from_millis(20));
+        sender.send(7).unwrap();
+        assert_eq!(handle.join().unwrap(), Ok(7));
+    }
+}
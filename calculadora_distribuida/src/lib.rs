@@ -1,3 +1,27 @@
+pub mod admin;
+pub mod auth;
+pub mod base64;
 pub mod calculator;
+pub mod config;
+pub mod connection;
+pub mod datagram;
+pub mod expression;
+pub mod gateway;
+pub mod history;
+pub mod http;
+pub mod json;
+pub mod latest;
+pub mod line_editor;
+pub mod linearizability;
+pub mod log;
 pub mod operator;
+pub mod persistence;
 pub mod protocol;
+pub mod rate_limiter;
+pub mod repl;
+pub mod report;
+pub mod simulation;
+pub mod state;
+pub mod thread_pool;
+pub mod transport;
+pub mod websocket;
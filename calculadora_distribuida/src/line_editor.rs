@@ -0,0 +1,362 @@
+use std::io::{self, Read};
+
+/// Tecla leída de la terminal (ver `read_key`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    /// Suprimir, o Ctrl-D con texto en la línea.
+    Delete,
+    Left,
+    Right,
+    /// Entrada anterior del historial.
+    Up,
+    /// Entrada siguiente del historial.
+    Down,
+    Home,
+    End,
+    /// Ctrl-U: borra hasta el comienzo de la línea.
+    KillStart,
+    /// Ctrl-K: borra hasta el final de la línea.
+    KillEnd,
+    /// Ctrl-D: fin de la entrada si la línea está vacía.
+    Eof,
+    /// Ctrl-C: descarta la línea.
+    Interrupt,
+    /// Cualquier otra tecla o secuencia, que se ignora.
+    Other,
+}
+
+/// Lee una tecla de una terminal en modo crudo (ver `RawMode`).
+///
+/// Reconoce las secuencias de escape de las flechas, Inicio, Fin y
+/// Suprimir, los atajos de Emacs más comunes (Ctrl-A, Ctrl-E, Ctrl-B,
+/// Ctrl-F, Ctrl-P, Ctrl-N, Ctrl-U, Ctrl-K) y caracteres UTF-8.
+///
+/// Retorna `Ok(None)` al terminar la entrada.
+///
+/// # Errores
+/// Retorna `Err(io::Error)` si falla la lectura.
+pub fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x02 => Key::Left,
+        0x06 => Key::Right,
+        0x10 => Key::Up,
+        0x0e => Key::Down,
+        0x15 => Key::KillStart,
+        0x0b => Key::KillEnd,
+        0x04 => Key::Eof,
+        0x03 => Key::Interrupt,
+        0x1b => read_escape(input)?,
+        _ if byte < 0x20 => Key::Other,
+        _ => read_char(byte, input)?,
+    };
+    Ok(Some(key))
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut buf = [0];
+    match input.read(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf[0])),
+    }
+}
+
+/// Interpreta lo que sigue a un `ESC`: `ESC [ <letra>`, `ESC O <letra>` o
+/// `ESC [ <numero> ~`.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    if !matches!(read_byte(input)?, Some(b'[' | b'O')) {
+        return Ok(Key::Other);
+    }
+    let mut number = 0u32;
+    loop {
+        let key = match read_byte(input)? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            Some(d @ b'0'..=b'9') => {
+                number = number
+                    .saturating_mul(10)
+                    .saturating_add(u32::from(d - b'0'));
+                continue;
+            }
+            Some(b'~') => match number {
+                1 | 7 => Key::Home,
+                4 | 8 => Key::End,
+                3 => Key::Delete,
+                _ => Key::Other,
+            },
+            _ => Key::Other,
+        };
+        return Ok(key);
+    }
+}
+
+/// Completa el caracter UTF-8 que empieza con `first`.
+fn read_char(first: u8, input: &mut impl Read) -> io::Result<Key> {
+    let len = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Ok(Key::Other),
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_byte(input)? {
+            Some(b) => bytes.push(b),
+            None => return Ok(Key::Other),
+        }
+    }
+    Ok(std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map_or(Key::Other, Key::Char))
+}
+
+/// Resultado de aplicar una tecla (ver `LineEditor::apply`).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Edit {
+    /// La línea sigue en edición.
+    Pending,
+    /// Se presionó Enter: la línea terminada.
+    Submit(String),
+    /// Se descartó la línea con Ctrl-C.
+    Cancel,
+    /// Ctrl-D con la línea vacía: fin de la entrada.
+    Eof,
+}
+
+/// Línea en edición, con el cursor y la navegación por el historial.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    buffer: Vec<char>,
+    /// Posición del cursor, en caracteres.
+    cursor: usize,
+    /// Entrada del historial que se está mostrando; `None` es la línea
+    /// nueva.
+    browsing: Option<usize>,
+    /// Línea nueva, guardada mientras se recorre el historial.
+    draft: Vec<char>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        LineEditor::default()
+    }
+
+    /// Aplica `key` a la línea. `history` son las entradas anteriores, de
+    /// la más vieja a la más nueva, que se recorren con ↑ y ↓.
+    pub fn apply(&mut self, key: Key, history: &[String]) -> Edit {
+        match key {
+            Key::Char(c) => {
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Enter => {
+                let line = self.buffer.iter().collect();
+                *self = LineEditor::new();
+                return Edit::Submit(line);
+            }
+            Key::Interrupt => {
+                *self = LineEditor::new();
+                return Edit::Cancel;
+            }
+            Key::Eof if self.buffer.is_empty() => return Edit::Eof,
+            Key::Eof | Key::Delete => {
+                if self.cursor < self.buffer.len() {
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.buffer.len(),
+            Key::KillStart => {
+                self.buffer.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::KillEnd => self.buffer.truncate(self.cursor),
+            Key::Up => {
+                let previous = match self.browsing {
+                    None => history.len().checked_sub(1),
+                    Some(i) => i.checked_sub(1),
+                };
+                if let Some(i) = previous {
+                    if self.browsing.is_none() {
+                        self.draft = std::mem::take(&mut self.buffer);
+                    }
+                    self.show(Some(i), history);
+                }
+            }
+            Key::Down => match self.browsing {
+                Some(i) if i + 1 < history.len() => self.show(Some(i + 1), history),
+                Some(_) => self.show(None, history),
+                None => {}
+            },
+            Key::Other => {}
+        }
+        Edit::Pending
+    }
+
+    /// Muestra la entrada `entry` del historial, o la línea nueva.
+    fn show(&mut self, entry: Option<usize>, history: &[String]) {
+        self.buffer = match entry {
+            Some(i) => history[i].chars().collect(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.browsing = entry;
+        self.cursor = self.buffer.len();
+    }
+
+    /// Secuencia que redibuja la línea tras `prompt`, en la misma fila de
+    /// la terminal, y deja el cursor en su lugar.
+    pub fn render(&self, prompt: &str) -> String {
+        let line: String = self.buffer.iter().collect();
+        let mut out = format!("\r{}{}\x1b[K", prompt, line);
+        let back = self.buffer.len() - self.cursor;
+        if back > 0 {
+            out.push_str(&format!("\x1b[{}D", back));
+        }
+        out
+    }
+}
+
+/// Terminal en modo crudo mientras exista: sin eco, sin esperar el Enter y
+/// sin señales por Ctrl-C, para que `read_key` reciba cada tecla. Al
+/// descartarse restaura la configuración anterior.
+///
+/// Se configura con `stty` sobre `/dev/tty`, para no depender de bibliotecas
+/// externas.
+#[cfg(unix)]
+pub struct RawMode {
+    saved: String,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    /// Pasa la terminal a modo crudo.
+    ///
+    /// # Errores
+    /// Retorna `Err(io::Error)` si no hay terminal o falla `stty`.
+    pub fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Ok(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Ejecuta `stty` sobre la terminal y retorna su salida.
+#[cfg(unix)]
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(std::fs::File::open("/dev/tty")?)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "stty fallo: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        let mut input = bytes;
+        std::iter::from_fn(|| read_key(&mut input).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_read_key() {
+        assert_eq!(
+            keys(b"+ \x1b[A\x1b[D\x1bOH\x1b[3~\x7f\x01\x15\x03\r"),
+            [
+                Key::Char('+'),
+                Key::Char(' '),
+                Key::Up,
+                Key::Left,
+                Key::Home,
+                Key::Delete,
+                Key::Backspace,
+                Key::Home,
+                Key::KillStart,
+                Key::Interrupt,
+                Key::Enter,
+            ]
+        );
+        assert_eq!(keys("ñ".as_bytes()), [Key::Char('ñ')]);
+        assert_eq!(keys(b"\x1b[99~\x1bx"), [Key::Other, Key::Other]);
+    }
+
+    /// Aplica las teclas de `bytes` y retorna el último resultado.
+    fn edit(editor: &mut LineEditor, bytes: &[u8], history: &[String]) -> Edit {
+        keys(bytes)
+            .into_iter()
+            .map(|key| editor.apply(key, history))
+            .last()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut editor = LineEditor::new();
+        // "5", Inicio, "+ ", Fin, "0", borrar: "+ 5"
+        assert_eq!(edit(&mut editor, b"5\x01+ \x050\x7f", &[]), Edit::Pending);
+        assert_eq!(editor.render("> "), "\r> + 5\x1b[K");
+        assert_eq!(edit(&mut editor, b"\x1b[D\x1b[D", &[]), Edit::Pending);
+        assert_eq!(editor.render("> "), "\r> + 5\x1b[K\x1b[2D");
+        assert_eq!(
+            edit(&mut editor, b"\x0b* 2\r", &[]),
+            Edit::Submit("+* 2".to_string())
+        );
+
+        assert_eq!(edit(&mut editor, b"get\x03", &[]), Edit::Cancel);
+        assert_eq!(edit(&mut editor, b"\x04", &[]), Edit::Eof);
+    }
+
+    #[test]
+    fn test_history_navigation() {
+        let history = ["+ 5".to_string(), "get".to_string()];
+        let mut editor = LineEditor::new();
+        edit(&mut editor, b"* 3\x1b[A\x1b[A\x1b[A", &history);
+        assert_eq!(editor.render(""), "\r+ 5\x1b[K");
+        edit(&mut editor, b"\x1b[B", &history);
+        assert_eq!(editor.render(""), "\rget\x1b[K");
+        // al bajar del todo vuelve la línea que se estaba escribiendo
+        assert_eq!(
+            edit(&mut editor, b"\x1b[B\r", &history),
+            Edit::Submit("* 3".to_string())
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::calculator::Arithmetic;
+use crate::protocol::{Message, Operation};
+
+/// Especificación secuencial contra la que se verifica una historia.
+pub trait Model {
+    type State: Clone + Eq + Hash;
+    type Input;
+    type Output: PartialEq;
+
+    /// Estado antes de la primera operación.
+    fn init(&self) -> Self::State;
+
+    /// Aplica `input` sobre `state` y retorna el nuevo estado y la respuesta
+    /// que debería haberse observado.
+    fn step(&self, state: &Self::State, input: &Self::Input) -> (Self::State, Self::Output);
+}
+
+/// Una operación completa de la historia: qué pidió un cliente, qué
+/// recibió, y cuándo envió el pedido y recibió la respuesta.
+///
+/// Si la respuesta se perdió (la conexión se cortó antes de recibirla),
+/// `output` es `None` y `response` es `Duration::MAX`: la operación pudo
+/// aplicarse en cualquier momento posterior a su invocación, o nunca.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call<I, O> {
+    pub client: usize,
+    pub input: I,
+    pub output: Option<O>,
+    /// Momento del envío, relativo al inicio de la historia.
+    pub invoke: Duration,
+    /// Momento de la respuesta, relativo al inicio de la historia.
+    pub response: Duration,
+}
+
+/// Registro de operaciones de varios clientes concurrentes.
+///
+/// Cada cliente toma `History::now()` antes de enviar un pedido y registra
+/// la operación con `record` después de leer la respuesta.
+#[derive(Debug)]
+pub struct History<I, O> {
+    start: Instant,
+    calls: Mutex<Vec<Call<I, O>>>,
+}
+
+impl<I, O> Default for History<I, O> {
+    fn default() -> Self {
+        History {
+            start: Instant::now(),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<I, O> History<I, O> {
+    /// Crea una historia vacía que empieza ahora.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tiempo transcurrido desde el inicio de la historia.
+    pub fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Registra una operación que empezó en `invoke` y termina ahora.
+    pub fn record(&self, client: usize, input: I, output: O, invoke: Duration) {
+        let response = self.now();
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(Call {
+                client,
+                input,
+                output: Some(output),
+                invoke,
+                response,
+            });
+        }
+    }
+
+    /// Registra una operación que empezó en `invoke` y cuya respuesta nunca
+    /// llegó.
+    pub fn record_unknown(&self, client: usize, input: I, invoke: Duration) {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(Call {
+                client,
+                input,
+                output: None,
+                invoke,
+                response: Duration::MAX,
+            });
+        }
+    }
+
+    /// Operaciones registradas hasta el momento.
+    pub fn into_calls(self) -> Vec<Call<I, O>> {
+        self.calls.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Verifica que la historia sea linealizable respecto de `model`
+/// (algoritmo de Wing y Gong con memoización del estado).
+///
+/// Una historia es linealizable si existe un orden secuencial de sus
+/// operaciones que respeta el tiempo real (si `a` respondió antes de que
+/// se invocara `b`, `a` va antes que `b`) y en el que cada respuesta es la
+/// que da `model`.
+///
+/// # Retorno
+/// - `Ok(orden)` con los índices de `calls` en el orden encontrado.
+/// - `Err(String)` si ningún orden explica la historia, indicando la
+///   operación en la que se detuvo la linealización más larga.
+pub fn check<M: Model>(
+    model: &M,
+    calls: &[Call<M::Input, M::Output>],
+) -> Result<Vec<usize>, String> {
+    let mut pending = Bitset::full(calls.len());
+    let mut order = Vec::with_capacity(calls.len());
+    let mut state = model.init();
+    let mut stack: Vec<(usize, M::State)> = Vec::new();
+    let mut visited: HashSet<(Bitset, M::State)> = HashSet::new();
+    let mut longest = 0;
+    let mut next = 0;
+
+    loop {
+        if order.len() == calls.len() {
+            return Ok(order);
+        }
+
+        // Una operación puede ir primera si ninguna otra pendiente respondió
+        // antes de que se invocara.
+        let deadline = pending
+            .iter()
+            .map(|i| calls[i].response)
+            .min()
+            .unwrap_or_default();
+        let candidate = pending
+            .iter()
+            .filter(|i| *i >= next && calls[*i].invoke <= deadline)
+            .find(|i| {
+                let (new_state, output) = model.step(&state, &calls[*i].input);
+                if calls[*i].output.as_ref().is_some_and(|o| *o != output) {
+                    return false;
+                }
+                let mut linearized = pending.clone();
+                linearized.remove(*i);
+                visited.insert((linearized, new_state))
+            });
+
+        match candidate {
+            Some(i) => {
+                let (new_state, _) = model.step(&state, &calls[i].input);
+                stack.push((i, std::mem::replace(&mut state, new_state)));
+                pending.remove(i);
+                order.push(i);
+                longest = longest.max(order.len());
+                next = 0;
+            }
+            None => {
+                // Se deshace la última operación y se prueba la siguiente.
+                let Some((i, previous)) = stack.pop() else {
+                    return Err(format!(
+                        "la historia no es linealizable: a lo sumo {} de {} operaciones \
+                         admiten un orden secuencial",
+                        longest,
+                        calls.len()
+                    ));
+                };
+                state = previous;
+                pending.insert(i);
+                order.pop();
+                next = i + 1;
+            }
+        }
+    }
+}
+
+/// Conjunto de índices de operaciones pendientes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Bitset(Vec<u64>);
+
+impl Bitset {
+    fn full(len: usize) -> Self {
+        let mut words = vec![u64::MAX; len.div_ceil(64)];
+        if !len.is_multiple_of(64)
+            && let Some(last) = words.last_mut()
+        {
+            *last = (1 << (len % 64)) - 1;
+        }
+        Bitset(words)
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.0[i / 64] &= !(1 << (i % 64));
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(w, word)| {
+            (0..64)
+                .filter(move |b| word & (1 << b) != 0)
+                .map(move |b| w * 64 + b)
+        })
+    }
+}
+
+/// Especificación del servidor de la calculadora: un registro con versión
+/// sobre el que se aplican operaciones aritméticas.
+///
+/// Las entradas y salidas son los mensajes del protocolo; el estado es el
+/// par `(valor, versión)`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CalculatorModel {
+    pub arithmetic: Arithmetic,
+    pub initial_value: u64,
+    pub initial_version: u64,
+}
+
+impl CalculatorModel {
+    /// Aplica una operación como el servidor: con éxito incrementa la versión.
+    fn apply(&self, (value, version): (u64, u64), op: &Operation) -> ((u64, u64), Message) {
+        match self.arithmetic.apply(value, op) {
+            Ok(new) => ((new, version + 1), Message::Ok(Some(version + 1))),
+            Err(motivo) => ((value, version), Message::Err(motivo)),
+        }
+    }
+}
+
+impl Model for CalculatorModel {
+    type State = (u64, u64);
+    type Input = Message;
+    type Output = Message;
+
+    fn init(&self) -> Self::State {
+        (self.initial_value, self.initial_version)
+    }
+
+    fn step(&self, state: &Self::State, input: &Message) -> (Self::State, Message) {
+        let (value, version) = *state;
+        let conflict = Message::Conflict(value, Some(version));
+        match input {
+            Message::Get => (*state, Message::Value(value, Some(version))),
+            Message::Op(_, Some(expected)) if *expected != version => (*state, conflict),
+            Message::Op(op, _) => self.apply(*state, op),
+            Message::OpIf { expected, .. } | Message::Cas { expected, .. }
+                if *expected != value =>
+            {
+                (*state, conflict)
+            }
+            Message::OpIf { op, .. } => self.apply(*state, op),
+            Message::Cas { new, .. } if *new > self.arithmetic.numeric_type.max() => {
+                (*state, Message::Err("operand out of range".to_string()))
+            }
+            Message::Cas { new, .. } => ((*new, version + 1), Message::Ok(Some(version + 1))),
+            Message::Ops(ops) => {
+                let mut state = *state;
+                let results = ops
+                    .iter()
+                    .map(|op| {
+                        let (next, result) = self.apply(state, op);
+                        state = next;
+                        result
+                    })
+                    .collect();
+                (state, Message::Results(results))
+            }
+            // Cada línea se aplica entera o, si una operación falla, no se
+            // aplica y todas sus operaciones llevan ese error.
+            Message::Lines(lines) => {
+                let mut state = *state;
+                let mut results = Vec::new();
+                for ops in lines {
+                    let mut next = state;
+                    let mut oks = Vec::new();
+                    let failure = ops.iter().find_map(|op| match self.apply(next, op) {
+                        (applied, ok @ Message::Ok(_)) => {
+                            next = applied;
+                            oks.push(ok);
+                            None
+                        }
+                        (_, failure) => Some(failure),
+                    });
+                    match failure {
+                        Some(failure) => results.extend(vec![failure; ops.len()]),
+                        None => {
+                            results.extend(oks);
+                            state = next;
+                        }
+                    }
+                }
+                (state, Message::Results(results))
+            }
+            _ => (*state, Message::Err("unexpected message".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::Operator;
+
+    fn call(
+        client: usize,
+        input: Message,
+        output: Message,
+        invoke: u64,
+        response: u64,
+    ) -> Call<Message, Message> {
+        Call {
+            client,
+            input,
+            output: Some(output),
+            invoke: Duration::from_millis(invoke),
+            response: Duration::from_millis(response),
+        }
+    }
+
+    fn add(n: u64) -> Message {
+        Message::Op(
+            Operation {
+                op: Operator::Add,
+                operand: n,
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn test_sequential_history() {
+        let calls = [
+            call(0, add(5), Message::Ok(Some(1)), 0, 1),
+            call(1, Message::Get, Message::Value(5, Some(1)), 2, 3),
+            call(
+                0,
+                Message::Cas {
+                    expected: 5,
+                    new: 9,
+                },
+                Message::Ok(Some(2)),
+                4,
+                5,
+            ),
+        ];
+        assert_eq!(
+            check(&CalculatorModel::default(), &calls).unwrap(),
+            [0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_operations_may_reorder() {
+        // El GET se solapa con ambas sumas y vio sólo la segunda.
+        let calls = [
+            call(0, add(1), Message::Ok(Some(2)), 0, 10),
+            call(1, add(2), Message::Ok(Some(1)), 1, 4),
+            call(2, Message::Get, Message::Value(2, Some(1)), 2, 12),
+        ];
+        assert_eq!(
+            check(&CalculatorModel::default(), &calls).unwrap(),
+            [1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn test_stale_read_is_not_linearizable() {
+        // El GET empieza después de que la suma respondió, pero no la ve.
+        let calls = [
+            call(0, add(5), Message::Ok(Some(1)), 0, 1),
+            call(1, Message::Get, Message::Value(0, Some(0)), 2, 3),
+        ];
+        let err = check(&CalculatorModel::default(), &calls).unwrap_err();
+        assert!(err.contains("1 de 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_lost_update_is_not_linearizable() {
+        // Dos CAS concurrentes desde 0 no pueden tener éxito ambos.
+        let calls = [
+            call(
+                0,
+                Message::Cas {
+                    expected: 0,
+                    new: 1,
+                },
+                Message::Ok(Some(1)),
+                0,
+                5,
+            ),
+            call(
+                1,
+                Message::Cas {
+                    expected: 0,
+                    new: 2,
+                },
+                Message::Ok(Some(1)),
+                1,
+                6,
+            ),
+        ];
+        assert!(check(&CalculatorModel::default(), &calls).is_err());
+    }
+
+    #[test]
+    fn test_unknown_output_may_or_may_not_apply() {
+        let lost = Call {
+            output: None,
+            response: Duration::MAX,
+            ..call(0, add(5), Message::Ok(None), 0, 0)
+        };
+        // El GET posterior puede ver la suma perdida...
+        let applied = [
+            lost.clone(),
+            call(1, Message::Get, Message::Value(5, Some(1)), 2, 3),
+        ];
+        assert_eq!(
+            check(&CalculatorModel::default(), &applied).unwrap(),
+            [0, 1]
+        );
+        // ...o no verla, si se aplica después o nunca.
+        let not_applied = [
+            lost,
+            call(1, Message::Get, Message::Value(0, Some(0)), 2, 3),
+        ];
+        assert_eq!(
+            check(&CalculatorModel::default(), &not_applied).unwrap(),
+            [1, 0]
+        );
+    }
+
+    #[test]
+    fn test_history_records_calls() {
+        let history = History::new();
+        let invoke = history.now();
+        history.record(3, Message::Get, Message::Value(0, Some(0)), invoke);
+        let calls = history.into_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].client, 3);
+        assert!(calls[0].invoke <= calls[0].response);
+
+        let history: History<Message, Message> = History::new();
+        history.record_unknown(1, Message::Get, Duration::ZERO);
+        let calls = history.into_calls();
+        assert_eq!(calls[0].output, None);
+        assert_eq!(calls[0].response, Duration::MAX);
+    }
+
+    #[test]
+    fn test_bitset() {
+        let mut set = Bitset::full(70);
+        assert_eq!(set.iter().count(), 70);
+        set.remove(0);
+        set.remove(69);
+        assert_eq!(set.iter().next(), Some(1));
+        assert_eq!(set.iter().last(), Some(68));
+        set.insert(69);
+        assert_eq!(set.iter().last(), Some(69));
+        assert_eq!(Bitset::full(64).iter().count(), 64);
+    }
+}
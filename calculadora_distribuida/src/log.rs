@@ -0,0 +1,100 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Nivel mínimo de los mensajes que se imprimen por `stderr`.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Error as u8);
+
+/// Niveles de log, de menor a mayor detalle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    #[default]
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!(
+                "Nivel de log invalido: {} (se esperaba error, warn, info o debug)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Error => write!(f, "ERROR"),
+            LogLevel::Warn => write!(f, "WARN"),
+            LogLevel::Info => write!(f, "INFO"),
+            LogLevel::Debug => write!(f, "DEBUG"),
+        }
+    }
+}
+
+/// Cambia el nivel mínimo de log del proceso.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Indica si los mensajes de `level` se imprimen.
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Imprime `msg` por `stderr` con el formato `NIVEL "<msg>"`.
+pub fn log(level: LogLevel, msg: &str) {
+    if enabled(level) {
+        eprintln!("{} \"{}\"", level, msg);
+    }
+}
+
+/// Registra un error.
+pub fn error(msg: &str) {
+    log(LogLevel::Error, msg);
+}
+
+/// Registra una advertencia.
+pub fn warn(msg: &str) {
+    log(LogLevel::Warn, msg);
+}
+
+/// Registra un mensaje informativo.
+pub fn info(msg: &str) {
+    log(LogLevel::Info, msg);
+}
+
+/// Registra un mensaje de depuración.
+pub fn debug(msg: &str) {
+    log(LogLevel::Debug, msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!("warn".parse::<LogLevel>().unwrap(), LogLevel::Warn);
+        assert!("trace".parse::<LogLevel>().is_err());
+    }
+
+    #[test]
+    fn test_levels_are_ordered() {
+        assert!(LogLevel::Error < LogLevel::Warn);
+        assert!(LogLevel::Info < LogLevel::Debug);
+        // Por defecto sólo se imprimen errores.
+        assert!(enabled(LogLevel::Error));
+    }
+}
@@ -1,7 +1,8 @@
+use std::fmt;
 use std::str::FromStr;
 
 /// Representa los operadores aritméticos soportados por la calculadora.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operator {
     Add,
     Sub,
@@ -31,6 +32,18 @@ impl FromStr for Operator {
     }
 }
 
+impl fmt::Display for Operator {
+    /// Escribe el símbolo del operador (`+`, `-`, `*` o `/`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Sub => write!(f, "-"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Div => write!(f, "/"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,6 +68,13 @@ mod tests {
         assert_eq!("/".parse::<Operator>().unwrap(), Operator::Div);
     }
 
+    #[test]
+    fn test_display_round_trip() {
+        for op in [Operator::Add, Operator::Sub, Operator::Mul, Operator::Div] {
+            assert_eq!(op.to_string().parse::<Operator>().unwrap(), op);
+        }
+    }
+
     #[test]
     fn test_parse_invalid() {
         assert!("x".parse::<Operator>().is_err());
@@ -0,0 +1,66 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Lee el valor y la versión persistidos en `path`.
+///
+/// El archivo contiene el valor en la primera línea y la versión en la
+/// segunda; si la versión falta se asume `0`.
+///
+/// # Retorno
+/// - `Ok(Some((valor, version)))` si el archivo existe y es válido.
+/// - `Ok(None)` si el archivo no existe.
+/// - `Err(String)` si no se pudo leer o su contenido es inválido.
+pub fn load(path: &Path) -> Result<Option<(u64, u64)>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("No se pudo leer {}: {}", path.display(), e)),
+    };
+
+    let invalid = || format!("Contenido invalido en {}: {}", path.display(), text.trim());
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    let value = lines
+        .next()
+        .and_then(|l| l.parse::<u64>().ok())
+        .ok_or_else(invalid)?;
+    let version = match lines.next() {
+        Some(l) => l.parse::<u64>().map_err(|_| invalid())?,
+        None => 0,
+    };
+    Ok(Some((value, version)))
+}
+
+/// Guarda `value` y `version` en `path`.
+///
+/// Se escribe primero un archivo temporal y luego se renombra, para que un
+/// corte a mitad de escritura no deje el archivo truncado.
+pub fn store(path: &Path, value: u64, version: u64) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{}\n{}\n", value, version))
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("No se pudo guardar {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_store_and_load() {
+        let path = env::temp_dir().join(format!("calc_persist_{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(load(&path).unwrap(), None);
+        store(&path, 42, 3).unwrap();
+        assert_eq!(load(&path).unwrap(), Some((42, 3)));
+
+        fs::write(&path, "7\n").unwrap();
+        assert_eq!(load(&path).unwrap(), Some((7, 0)));
+
+        fs::write(&path, "abc").unwrap();
+        assert!(load(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
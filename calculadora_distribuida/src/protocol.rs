@@ -1,36 +1,237 @@
 use crate::operator::Operator;
+use std::borrow::Cow;
 use std::fmt;
 
+/// Máxima cantidad de operaciones de un `BATCH`.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Versión del protocolo que implementa esta revisión del crate.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Error de `parse_message` para mensajes que no reconoce, por ejemplo los
+/// de una versión más nueva del protocolo.
+pub const UNKNOWN_MESSAGE: &str = "Mensaje desconocido";
+
+/// Comienzo del error de `parse_message` para un `GET AT` sin argumento o
+/// con un argumento inválido; el resto nombra el argumento recibido.
+pub const INVALID_GET_AT: &str = "invalid GET AT argument";
+
 /// Representa una operación aritmética que se enviará al servidor.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Operation {
     pub op: Operator,
-    pub operand: u8,
+    pub operand: u64,
+}
+
+impl fmt::Display for Operation {
+    /// Escribe la operación como `<operador> <numero>`, por ejemplo `+ 5`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.op, self.operand)
+    }
+}
+
+/// Momento del historial del valor que consulta `GET AT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    /// Una versión, escrita `@<version>`.
+    Version(u64),
+    /// Un instante en segundos desde la época Unix.
+    Time(u64),
+}
+
+impl fmt::Display for PointInTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointInTime::Version(v) => write!(f, "@{}", v),
+            PointInTime::Time(t) => write!(f, "{}", t),
+        }
+    }
 }
 
 /// Representa los distintos tipos de mensajes que pueden enviarse o recibirse.
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Los campos `Option<u64>` son la versión del estado del servidor, que se
+/// escribe como sufijo `@<version>`: en `OP` es una precondición y en las
+/// respuestas indica la versión tras la operación. Ver en `Message::Ok`
+/// cuándo una respuesta no la lleva.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Message {
-    Op(Operation),
+    /// Aplica la operación; con versión, sólo si el estado está en esa versión.
+    Op(Operation, Option<u64>),
     Get,
-    Ok,
+    /// Consulta el valor en una versión o instante pasado; se responde con
+    /// `VALUE` y la versión que tenía.
+    GetAt(PointInTime),
+    /// Sin identificador, pide guardar el estado actual como instantánea;
+    /// con él, es la respuesta del servidor.
+    Snapshot(Option<u64>),
+    /// Vuelve el valor al de la instantánea, como una modificación nueva.
+    Restore(u64),
+    Auth {
+        user: String,
+        password: String,
+    },
+    /// Reemplaza el valor por `new` sólo si es igual a `expected`.
+    Cas {
+        expected: u64,
+        new: u64,
+    },
+    /// Aplica `op` sólo si el valor es igual a `expected`.
+    OpIf {
+        expected: u64,
+        op: Operation,
+    },
+    /// Anuncia que las próximas `n` líneas son mensajes `OP` a aplicar juntos.
+    Batch(usize),
+    /// Varias operaciones en una sola línea, aplicadas juntas.
+    Ops(Vec<Operation>),
+    /// Las operaciones de varias líneas de un archivo, aplicadas juntas; las
+    /// de cada línea se aplican todas o ninguna.
+    Lines(Vec<Vec<Operation>>),
+    /// Respuesta a `BATCH`, `OPS` y `LINES`: el resultado de cada operación,
+    /// en orden.
+    Results(Vec<Message>),
+    /// Negociación al inicio de la conexión: versión del protocolo y
+    /// capacidades de quien lo envía (por ejemplo `batch` o `numeric=u16`).
+    /// El servidor responde con las capacidades opcionales que pidió el
+    /// cliente y soporta, más las que lo describen.
+    Hello {
+        version: u32,
+        capabilities: Vec<String>,
+    },
+    /// Éxito, con la versión tras la operación. Va sin versión en la
+    /// respuesta a `AUTH` (también en administración), que no modifica el
+    /// valor, en la de las sumas y restas que el backend aggregate acumula
+    /// sin aplicar todavía, y en las confirmaciones de `KILL` y de los
+    /// datagramas UDP.
+    Ok(Option<u64>),
     Err(String),
-    Value(u8),
+    Value(u64, Option<u64>),
+    /// Respuesta a `CAS`, `OPIF` u `OP @<version>` cuando el valor o la
+    /// versión no eran los esperados.
+    Conflict(u64, Option<u64>),
+    /// Aviso del servidor, sin pedido previo, de que el valor cambió. Sólo
+    /// se envía por WebSocket.
+    Update(u64, Option<u64>),
 }
 
 impl fmt::Display for Message {
     /// Convierte un `Message` en su representación textual.
     ///
-    /// - `Message::Ok` → "OK"
-    /// - `Message::Err(m)` → "ERROR \"m\""
-    /// - `Message::Value(v)` → "VALUE v"
-    /// - Otros mensajes no imprimen nada.
+    /// - `Message::Op(op, version)` → "OP <operador> <numero> [@version]"
+    /// - `Message::Get` → "GET"
+    /// - `Message::GetAt(point)` → "GET AT @version" o "GET AT segundos"
+    /// - `Message::Snapshot(id)` → "SNAPSHOT [id]"
+    /// - `Message::Restore(id)` → "RESTORE id"
+    /// - `Message::Ok(version)` → "OK [@version]"
+    /// - `Message::Err(m)` → "ERROR \"m\"", con `m` escapado (ver `Quoted`)
+    /// - `Message::Value(v, version)` → "VALUE v [@version]"
+    /// - `Message::Update(v, version)` → "UPDATE v [@version]"
+    /// - `Message::Auth { user, password }` → "AUTH user password"
+    /// - `Message::Cas { expected, new }` → "CAS expected new"
+    /// - `Message::OpIf { expected, op }` → "OPIF expected <operador> <numero>"
+    /// - `Message::Conflict(v, version)` → "ERROR \"conflict\" VALUE v [@version]"
+    /// - `Message::Batch(n)` → "BATCH n"
+    /// - `Message::Ops(ops)` → "OPS + 5, * 2"
+    /// - `Message::Lines(lines)` → "LINES * 7, + 1; + 5"
+    /// - `Message::Results(r)` → "RESULTS OK @1; ERROR \"motivo\""
+    /// - `Message::Hello { version, capabilities }` → "HELLO version cap1 cap2"
+    ///
+    /// Para todo mensaje válido, `parse_message(&msg.to_string())` devuelve
+    /// el mismo mensaje.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Message::Ok => write!(f, "OK"),
-            Message::Err(m) => write!(f, "ERROR \"{}\"", m),
-            Message::Value(v) => write!(f, "VALUE {}", v),
-            _ => Ok(()),
+            Message::Op(op, version) => write!(f, "OP {}{}", op, VersionSuffix(*version)),
+            Message::Get => write!(f, "GET"),
+            Message::GetAt(point) => write!(f, "GET AT {}", point),
+            Message::Snapshot(None) => write!(f, "SNAPSHOT"),
+            Message::Snapshot(Some(id)) => write!(f, "SNAPSHOT {}", id),
+            Message::Restore(id) => write!(f, "RESTORE {}", id),
+            Message::Ok(version) => write!(f, "OK{}", VersionSuffix(*version)),
+            Message::Auth { user, password } => write!(f, "AUTH {} {}", user, password),
+            Message::Cas { expected, new } => write!(f, "CAS {} {}", expected, new),
+            Message::OpIf { expected, op } => write!(f, "OPIF {} {}", expected, op),
+            Message::Conflict(v, version) => {
+                write!(
+                    f,
+                    "ERROR \"conflict\" VALUE {}{}",
+                    v,
+                    VersionSuffix(*version)
+                )
+            }
+            Message::Err(m) => write!(f, "ERROR {}", Quoted(m)),
+            Message::Value(v, version) => write!(f, "VALUE {}{}", v, VersionSuffix(*version)),
+            Message::Update(v, version) => write!(f, "UPDATE {}{}", v, VersionSuffix(*version)),
+            Message::Batch(n) => write!(f, "BATCH {}", n),
+            Message::Ops(ops) => {
+                write!(f, "OPS")?;
+                for (i, op) in ops.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, op)?;
+                }
+                Ok(())
+            }
+            Message::Lines(lines) => {
+                write!(f, "LINES")?;
+                for (i, ops) in lines.iter().enumerate() {
+                    write!(f, "{}", if i == 0 { " " } else { "; " })?;
+                    for (j, op) in ops.iter().enumerate() {
+                        write!(f, "{}{}", if j == 0 { "" } else { ", " }, op)?;
+                    }
+                }
+                Ok(())
+            }
+            Message::Results(results) => {
+                write!(f, "RESULTS")?;
+                for (i, r) in results.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { "; " }, r)?;
+                }
+                Ok(())
+            }
+            Message::Hello {
+                version,
+                capabilities,
+            } => {
+                write!(f, "HELLO {}", version)?;
+                for c in capabilities {
+                    write!(f, " {}", c)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Escribe un texto entre comillas con escapes de barra invertida.
+///
+/// `\` se escribe `\\`, `"` se escribe `\"` y los saltos de línea `\n` y
+/// `\r`, para que el texto no pueda cerrar las comillas ni partir la línea
+/// del mensaje. `parse_quoted` hace la operación inversa.
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '\\' => write!(f, "\\\\")?,
+                '"' => write!(f, "\\\"")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// Escribe ` @<version>` si hay versión, o nada.
+struct VersionSuffix(Option<u64>);
+
+impl fmt::Display for VersionSuffix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(v) => write!(f, " @{}", v),
+            None => Ok(()),
         }
     }
 }
@@ -52,8 +253,23 @@ pub fn parse_message(line: &str) -> Result<Message, String> {
     if s == "GET" {
         return Ok(Message::Get);
     }
+    if s == "GET AT" || s.starts_with("GET AT ") {
+        return parse_get_at(s["GET AT".len()..].trim());
+    }
+    if s == "SNAPSHOT" {
+        return Ok(Message::Snapshot(None));
+    }
+    if let Some(rest) = s.strip_prefix("SNAPSHOT ") {
+        return parse_number(rest.trim()).map(|id| Message::Snapshot(Some(id)));
+    }
+    if let Some(rest) = s.strip_prefix("RESTORE ") {
+        return parse_number(rest.trim()).map(Message::Restore);
+    }
     if s == "OK" {
-        return Ok(Message::Ok);
+        return Ok(Message::Ok(None));
+    }
+    if let Some(rest) = s.strip_prefix("OK ") {
+        return parse_version(rest.trim()).map(|v| Message::Ok(Some(v)));
     }
     if let Some(rest) = s.strip_prefix("OP ") {
         return parse_op(rest);
@@ -64,13 +280,96 @@ pub fn parse_message(line: &str) -> Result<Message, String> {
     if let Some(rest) = s.strip_prefix("VALUE ") {
         return parse_value(rest);
     }
+    if let Some(rest) = s.strip_prefix("UPDATE ") {
+        return match parse_value(rest)? {
+            Message::Value(v, version) => Ok(Message::Update(v, version)),
+            _ => Err("Formato UPDATE invalido".to_string()),
+        };
+    }
+    if let Some(rest) = s.strip_prefix("AUTH ") {
+        return parse_auth(rest);
+    }
+    if let Some(rest) = s.strip_prefix("CAS ") {
+        return parse_cas(rest);
+    }
+    if let Some(rest) = s.strip_prefix("OPIF ") {
+        return parse_opif(rest);
+    }
+    if let Some(rest) = s.strip_prefix("BATCH ") {
+        return parse_batch(rest);
+    }
+    if s == "OPS" {
+        return Ok(Message::Ops(Vec::new()));
+    }
+    if let Some(rest) = s.strip_prefix("OPS ") {
+        return parse_ops(rest);
+    }
+    if s == "LINES" {
+        return Ok(Message::Lines(Vec::new()));
+    }
+    if let Some(rest) = s.strip_prefix("LINES ") {
+        return parse_lines(rest);
+    }
+    if s == "RESULTS" {
+        return Ok(Message::Results(Vec::new()));
+    }
+    if let Some(rest) = s.strip_prefix("RESULTS ") {
+        return parse_results(rest);
+    }
+    if let Some(rest) = s.strip_prefix("HELLO ") {
+        return parse_hello(rest);
+    }
 
-    Err("Mensaje desconocido".to_string())
+    Err(UNKNOWN_MESSAGE.to_string())
 }
 
-/// Parsea un mensaje de operación "OP <operador> <numero>".
+/// Parsea un mensaje de operación "OP <operador> <numero> [@version]".
+///
+/// El operando se acepta en todo el rango de `u64`; es el servidor quien
+/// valida que entre en su tipo numérico configurado.
 fn parse_op(rest: &str) -> Result<Message, String> {
+    let (rest, version) = split_version(rest)?;
     let parts: Vec<&str> = rest.split_whitespace().collect();
+    parse_operation(&parts).map(|op| Message::Op(op, version))
+}
+
+/// Separa un sufijo opcional ` @<version>` del resto del mensaje.
+fn split_version(rest: &str) -> Result<(&str, Option<u64>), String> {
+    match rest.trim_end().rsplit_once(char::is_whitespace) {
+        Some((head, last)) if last.starts_with('@') => Ok((head, Some(parse_version(last)?))),
+        _ => Ok((rest, None)),
+    }
+}
+
+/// Parsea una versión con el formato `@<numero>`.
+fn parse_version(s: &str) -> Result<u64, String> {
+    s.strip_prefix('@')
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| "Version invalida".to_string())
+}
+
+/// Parsea el argumento de `GET AT`. Si no es válido, el error (que empieza
+/// con `INVALID_GET_AT`) lo nombra, para que el servidor lo informe tal cual.
+fn parse_get_at(arg: &str) -> Result<Message, String> {
+    const EXPECTED: &str = "expected @<version> or <seconds>";
+    if arg.is_empty() {
+        return Err(format!("{}: missing; {}", INVALID_GET_AT, EXPECTED));
+    }
+    parse_point_in_time(arg)
+        .map(Message::GetAt)
+        .map_err(|_| format!("{} '{}'; {}", INVALID_GET_AT, arg, EXPECTED))
+}
+
+/// Parsea `@<version>` o `<segundos>`, el argumento de `GET AT`.
+fn parse_point_in_time(s: &str) -> Result<PointInTime, String> {
+    if s.starts_with('@') {
+        return parse_version(s).map(PointInTime::Version);
+    }
+    parse_number(s).map(PointInTime::Time)
+}
+
+/// Parsea los tokens `<operador> <numero>` de una operación.
+fn parse_operation(parts: &[&str]) -> Result<Operation, String> {
     if parts.len() != 2 {
         return Err("Formato de operacion invalido".to_string());
     }
@@ -79,57 +378,271 @@ fn parse_op(rest: &str) -> Result<Message, String> {
         .parse::<Operator>()
         .map_err(|_| "Operacion invalida".to_string())?;
 
-    let num = parts[1]
-        .parse::<u16>()
+    let operand = parts[1]
+        .parse::<u64>()
         .map_err(|_| "Numero invalido".to_string())?;
-    if num > u8::MAX as u16 {
-        return Err("Operando fuera de rango".to_string());
-    }
 
-    Ok(Message::Op(Operation {
-        op,
-        operand: num as u8,
-    }))
+    Ok(Operation { op, operand })
 }
 
 /// Parsea un mensaje de error "ERROR \"motivo\"".
+///
+/// El motivo puede contener escapes (ver `Quoted`). El caso
+/// `ERROR "conflict" VALUE <numero>` se interpreta como `Message::Conflict`;
+/// cualquier otro texto después de las comillas es un error.
 fn parse_error(rest: &str) -> Result<Message, String> {
-    if let Some(start) = rest.find('"')
-        && let Some(end) = rest.rfind('"')
-        && end > start
+    let (motivo, after) = parse_quoted(rest.trim_start())?;
+    let after = after.trim();
+    if after.is_empty() {
+        return Ok(Message::Err(motivo));
+    }
+    if motivo == "conflict"
+        && let Some(value) = after.strip_prefix("VALUE ")
     {
-        let motivo = &rest[start + 1..end];
-        return Ok(Message::Err(motivo.to_string()));
+        return match parse_value(value)? {
+            Message::Value(v, version) => Ok(Message::Conflict(v, version)),
+            _ => Err("Formato ERROR invalido".to_string()),
+        };
     }
 
     Err("Formato ERROR invalido".to_string())
 }
 
-/// Parsea un mensaje de valor "VALUE <numero>".
+/// Lee un texto entre comillas al inicio de `s`, resolviendo sus escapes.
+///
+/// Retorna el texto y lo que sigue a la comilla de cierre.
+///
+/// # Errores
+/// - `"Formato ERROR invalido"` si `s` no empieza con comillas.
+/// - `"Escape invalido en ERROR: \\<c>"` si un escape no es `\\`, `\"`,
+///   `\n` o `\r`.
+/// - `"ERROR sin comilla de cierre"` si las comillas no se cierran.
+fn parse_quoted(s: &str) -> Result<(String, &str), String> {
+    let body = s
+        .strip_prefix('"')
+        .ok_or_else(|| "Formato ERROR invalido".to_string())?;
+    let mut text = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((text, &body[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '\\')) => text.push('\\'),
+                Some((_, '"')) => text.push('"'),
+                Some((_, 'n')) => text.push('\n'),
+                Some((_, 'r')) => text.push('\r'),
+                Some((_, other)) => return Err(format!("Escape invalido en ERROR: \\{}", other)),
+                None => break,
+            },
+            c => text.push(c),
+        }
+    }
+    Err("ERROR sin comilla de cierre".to_string())
+}
+
+/// Parsea un mensaje de valor "VALUE <numero> [@version]".
 fn parse_value(rest: &str) -> Result<Message, String> {
-    let val_str = rest.trim();
+    let (val_str, version) = split_version(rest)?;
     let v = val_str
-        .parse::<u16>()
+        .trim()
+        .parse::<u64>()
         .map_err(|_| "VALUE invalido".to_string())?;
-    if v > u8::MAX as u16 {
-        return Err("VALUE fuera de rango".to_string());
+    Ok(Message::Value(v, version))
+}
+
+/// Parsea un mensaje "CAS <esperado> <nuevo>".
+fn parse_cas(rest: &str) -> Result<Message, String> {
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    if parts.len() != 2 {
+        return Err("Formato CAS invalido".to_string());
+    }
+    let expected = parse_number(parts[0])?;
+    let new = parse_number(parts[1])?;
+    Ok(Message::Cas { expected, new })
+}
+
+/// Parsea un mensaje "OPIF <esperado> <operador> <numero>".
+fn parse_opif(rest: &str) -> Result<Message, String> {
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    let Some((expected, op)) = parts.split_first() else {
+        return Err("Formato OPIF invalido".to_string());
+    };
+    let expected = parse_number(expected)?;
+    let op = parse_operation(op)?;
+    Ok(Message::OpIf { expected, op })
+}
+
+/// Parsea un mensaje "BATCH <cantidad>".
+///
+/// La cantidad no se limita aquí: quien recibe el mensaje debe rechazar las
+/// que superen `MAX_BATCH_SIZE` antes de consumir las líneas del lote.
+fn parse_batch(rest: &str) -> Result<Message, String> {
+    rest.trim()
+        .parse::<usize>()
+        .map(Message::Batch)
+        .map_err(|_| "Formato BATCH invalido".to_string())
+}
+
+/// Parsea un mensaje "OPS <operador> <numero>, <operador> <numero>, ...".
+fn parse_ops(rest: &str) -> Result<Message, String> {
+    let ops = rest
+        .split(',')
+        .map(|op| parse_operation(&op.split_whitespace().collect::<Vec<_>>()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if ops.len() > MAX_BATCH_SIZE {
+        return Err("batch too large".to_string());
+    }
+    Ok(Message::Ops(ops))
+}
+
+/// Parsea un mensaje "LINES <operaciones>; <operaciones>; ...", con las
+/// operaciones de cada línea separadas por comas como en `OPS`.
+fn parse_lines(rest: &str) -> Result<Message, String> {
+    let lines = rest
+        .split(';')
+        .map(|line| {
+            line.split(',')
+                .map(|op| parse_operation(&op.split_whitespace().collect::<Vec<_>>()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if lines.iter().map(Vec::len).sum::<usize>() > MAX_BATCH_SIZE {
+        return Err("batch too large".to_string());
+    }
+    Ok(Message::Lines(lines))
+}
+
+/// Parsea los resultados de "RESULTS <resultado>; <resultado>; ...".
+///
+/// Cada resultado es un `OK`, un `ERROR` o un conflicto; los `;` dentro de
+/// las comillas de un `ERROR` no separan resultados.
+fn parse_results(rest: &str) -> Result<Message, String> {
+    let mut results = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                results.push(parse_result(&rest[start..i])?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    results.push(parse_result(&rest[start..])?);
+    Ok(Message::Results(results))
+}
+
+/// Parsea un resultado individual de `RESULTS`.
+fn parse_result(s: &str) -> Result<Message, String> {
+    match parse_message(s)? {
+        m @ (Message::Ok(_) | Message::Err(_) | Message::Conflict(..)) => Ok(m),
+        _ => Err("Formato RESULTS invalido".to_string()),
+    }
+}
+
+/// Parsea un mensaje "HELLO <version> [<capacidad>...]".
+fn parse_hello(rest: &str) -> Result<Message, String> {
+    let mut parts = rest.split_whitespace();
+    let version = parts
+        .next()
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or_else(|| "Formato HELLO invalido".to_string())?;
+    Ok(Message::Hello {
+        version,
+        capabilities: parts.map(str::to_string).collect(),
+    })
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    s.parse::<u64>().map_err(|_| "Numero invalido".to_string())
+}
+
+/// Parsea un mensaje de autenticación "AUTH <usuario> <contraseña>".
+fn parse_auth(rest: &str) -> Result<Message, String> {
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    if parts.len() != 2 {
+        return Err("Formato AUTH invalido".to_string());
+    }
+    Ok(Message::Auth {
+        user: parts[0].to_string(),
+        password: parts[1].to_string(),
+    })
+}
+
+/// Versión de `line` apta para el log: en una línea `AUTH` se conserva
+/// sólo el usuario, y el resto se reemplaza por `***`.
+///
+/// Se aplica a cualquier línea que empiece con `AUTH`, sea válida o no,
+/// porque una contraseña mal escrita sigue siendo secreta.
+pub fn redact_credentials(line: &str) -> Cow<'_, str> {
+    let mut words = line.split_whitespace();
+    if words.next() != Some("AUTH") {
+        return Cow::Borrowed(line);
+    }
+    match words.next() {
+        Some(user) => Cow::Owned(format!("AUTH {} ***", user)),
+        None => Cow::Borrowed(line),
     }
-    Ok(Message::Value(v as u8))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::operator::Operator;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_redact_credentials() {
+        assert_eq!(redact_credentials("AUTH ana secreto"), "AUTH ana ***");
+        assert_eq!(
+            redact_credentials(" AUTH ana secreto extra"),
+            "AUTH ana ***"
+        );
+        assert_eq!(redact_credentials("AUTH"), "AUTH");
+        assert_eq!(redact_credentials("OP + 5"), "OP + 5");
+        assert_eq!(redact_credentials("AUTHOR x"), "AUTHOR x");
+    }
 
     #[test]
     fn test_parse_get() {
         assert_eq!(parse_message("GET").unwrap(), Message::Get);
     }
 
+    #[test]
+    fn test_parse_history() {
+        assert_eq!(
+            parse_message("GET AT @3").unwrap(),
+            Message::GetAt(PointInTime::Version(3))
+        );
+        assert_eq!(
+            parse_message("GET AT 1760000000").unwrap(),
+            Message::GetAt(PointInTime::Time(1_760_000_000))
+        );
+        assert_eq!(
+            parse_message("GET AT").unwrap_err(),
+            "invalid GET AT argument: missing; expected @<version> or <seconds>"
+        );
+        assert_eq!(
+            parse_message("GET AT @x").unwrap_err(),
+            "invalid GET AT argument '@x'; expected @<version> or <seconds>"
+        );
+        assert_eq!(parse_message("SNAPSHOT").unwrap(), Message::Snapshot(None));
+        assert_eq!(
+            parse_message("SNAPSHOT 2").unwrap(),
+            Message::Snapshot(Some(2))
+        );
+        assert_eq!(parse_message("RESTORE 2").unwrap(), Message::Restore(2));
+        assert!(parse_message("RESTORE").is_err());
+    }
+
     #[test]
     fn test_parse_ok() {
-        assert_eq!(parse_message("OK").unwrap(), Message::Ok);
+        assert_eq!(parse_message("OK").unwrap(), Message::Ok(None));
     }
 
     #[test]
@@ -137,10 +650,13 @@ mod tests {
         let msg = parse_message("OP + 5").unwrap();
         assert_eq!(
             msg,
-            Message::Op(Operation {
-                op: Operator::Add,
-                operand: 5
-            })
+            Message::Op(
+                Operation {
+                    op: Operator::Add,
+                    operand: 5
+                },
+                None
+            )
         );
     }
 
@@ -151,20 +667,89 @@ mod tests {
 
     #[test]
     fn test_parse_op_invalid_number() {
-        assert!(parse_message("OP + 300").is_err());
+        assert!(parse_message("OP + 18446744073709551616").is_err());
+        assert!(parse_message("OP + -1").is_err());
         assert!(parse_message("OP + abc").is_err());
     }
 
+    #[test]
+    fn test_parse_op_wide_operand() {
+        let msg = parse_message("OP * 300").unwrap();
+        assert_eq!(
+            msg,
+            Message::Op(
+                Operation {
+                    op: Operator::Mul,
+                    operand: 300
+                },
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_auth() {
+        let msg = parse_message("AUTH ana secreto").unwrap();
+        assert_eq!(
+            msg,
+            Message::Auth {
+                user: "ana".to_string(),
+                password: "secreto".to_string()
+            }
+        );
+        assert_eq!(msg.to_string(), "AUTH ana secreto");
+        assert!(parse_message("AUTH ana").is_err());
+    }
+
     #[test]
     fn test_parse_error() {
         let msg = parse_message(r#"ERROR "Algo fallo""#).unwrap();
         assert_eq!(msg, Message::Err("Algo fallo".to_string()));
     }
 
+    #[test]
+    fn test_error_escapes() {
+        let msg = Message::Err("dijo \"hola\"\nen C:\\tmp".to_string());
+        let text = msg.to_string();
+        assert_eq!(text, r#"ERROR "dijo \"hola\"\nen C:\\tmp""#);
+        assert!(!text.contains('\n'));
+        assert_eq!(parse_message(&text).unwrap(), msg);
+
+        let results =
+            Message::Results(vec![Message::Err("a\"; OK".to_string()), Message::Ok(None)]);
+        assert_eq!(parse_message(&results.to_string()).unwrap(), results);
+    }
+
+    #[test]
+    fn test_error_malformed_escapes() {
+        assert_eq!(
+            parse_message(r#"ERROR "mal \x""#).unwrap_err(),
+            "Escape invalido en ERROR: \\x"
+        );
+        assert_eq!(
+            parse_message(r#"ERROR "sin cierre"#).unwrap_err(),
+            "ERROR sin comilla de cierre"
+        );
+        assert_eq!(
+            parse_message(r#"ERROR "barra final\"#).unwrap_err(),
+            "ERROR sin comilla de cierre"
+        );
+        assert!(parse_message(r#"ERROR "a"b""#).is_err());
+        assert!(parse_message("ERROR sin comillas").is_err());
+    }
+
     #[test]
     fn test_parse_value() {
         let msg = parse_message("VALUE 123").unwrap();
-        assert_eq!(msg, Message::Value(123));
+        assert_eq!(msg, Message::Value(123, None));
+    }
+
+    #[test]
+    fn test_parse_update() {
+        let msg = parse_message("UPDATE 7 @3").unwrap();
+        assert_eq!(msg, Message::Update(7, Some(3)));
+        assert_eq!(msg.to_string(), "UPDATE 7 @3");
+        assert!(parse_message("UPDATE x").is_err());
     }
 
     #[test]
@@ -175,12 +760,259 @@ mod tests {
 
     #[test]
     fn test_display() {
-        let ok = Message::Ok;
+        let ok = Message::Ok(None);
         let err = Message::Err("fail".to_string());
-        let val = Message::Value(42);
+        let val = Message::Value(42, None);
 
         assert_eq!(ok.to_string(), "OK");
         assert_eq!(err.to_string(), "ERROR \"fail\"");
         assert_eq!(val.to_string(), "VALUE 42");
     }
+
+    #[test]
+    fn test_parse_cas() {
+        let msg = parse_message("CAS 3 10").unwrap();
+        assert_eq!(
+            msg,
+            Message::Cas {
+                expected: 3,
+                new: 10
+            }
+        );
+        assert_eq!(msg.to_string(), "CAS 3 10");
+        assert!(parse_message("CAS 3").is_err());
+        assert!(parse_message("CAS a 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_opif() {
+        let msg = parse_message("OPIF 7 + 5").unwrap();
+        assert_eq!(
+            msg,
+            Message::OpIf {
+                expected: 7,
+                op: Operation {
+                    op: Operator::Add,
+                    operand: 5
+                }
+            }
+        );
+        assert_eq!(msg.to_string(), "OPIF 7 + 5");
+        assert!(parse_message("OPIF 7 % 5").is_err());
+        assert!(parse_message("OPIF 7").is_err());
+    }
+
+    #[test]
+    fn test_parse_conflict() {
+        let msg = parse_message(r#"ERROR "conflict" VALUE 12"#).unwrap();
+        assert_eq!(msg, Message::Conflict(12, None));
+        assert_eq!(msg.to_string(), r#"ERROR "conflict" VALUE 12"#);
+        assert_eq!(
+            parse_message(r#"ERROR "conflict""#).unwrap(),
+            Message::Err("conflict".to_string())
+        );
+        assert!(parse_message(r#"ERROR "conflict" VALUE x"#).is_err());
+    }
+
+    #[test]
+    fn test_versions() {
+        let op = parse_message("OP + 5 @3").unwrap();
+        assert_eq!(
+            op,
+            Message::Op(
+                Operation {
+                    op: Operator::Add,
+                    operand: 5
+                },
+                Some(3)
+            )
+        );
+        assert_eq!(parse_message("OK @7").unwrap(), Message::Ok(Some(7)));
+        assert_eq!(
+            parse_message("VALUE 9 @7").unwrap(),
+            Message::Value(9, Some(7))
+        );
+        assert_eq!(
+            parse_message(r#"ERROR "conflict" VALUE 9 @7"#).unwrap(),
+            Message::Conflict(9, Some(7))
+        );
+
+        assert_eq!(Message::Ok(Some(7)).to_string(), "OK @7");
+        assert_eq!(Message::Value(9, Some(7)).to_string(), "VALUE 9 @7");
+        assert_eq!(
+            Message::Conflict(9, Some(7)).to_string(),
+            r#"ERROR "conflict" VALUE 9 @7"#
+        );
+
+        assert!(parse_message("OP + 5 @x").is_err());
+        assert!(parse_message("OK 7").is_err());
+        assert!(parse_message("VALUE 9 @").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        assert_eq!(parse_message("BATCH 3").unwrap(), Message::Batch(3));
+        assert_eq!(Message::Batch(3).to_string(), "BATCH 3");
+        assert!(parse_message("BATCH x").is_err());
+        assert!(parse_message("BATCH -1").is_err());
+    }
+
+    #[test]
+    fn test_parse_ops() {
+        let msg = parse_message("OPS + 5, * 2,- 1").unwrap();
+        assert_eq!(
+            msg,
+            Message::Ops(vec![
+                Operation {
+                    op: Operator::Add,
+                    operand: 5
+                },
+                Operation {
+                    op: Operator::Mul,
+                    operand: 2
+                },
+                Operation {
+                    op: Operator::Sub,
+                    operand: 1
+                },
+            ])
+        );
+        assert_eq!(msg.to_string(), "OPS + 5, * 2, - 1");
+        assert!(parse_message("OPS + 5, % 2").is_err());
+        assert!(parse_message("OPS + 5,").is_err());
+        assert_eq!(parse_message("OPS").unwrap(), Message::Ops(vec![]));
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let op = |op, operand| Operation { op, operand };
+        let msg = parse_message("LINES * 7, + 1;+ 5").unwrap();
+        assert_eq!(
+            msg,
+            Message::Lines(vec![
+                vec![op(Operator::Mul, 7), op(Operator::Add, 1)],
+                vec![op(Operator::Add, 5)],
+            ])
+        );
+        assert_eq!(msg.to_string(), "LINES * 7, + 1; + 5");
+        assert_eq!(parse_message("LINES").unwrap(), Message::Lines(vec![]));
+        assert!(parse_message("LINES + 5; ; * 2").is_err());
+        assert!(parse_message("LINES + 5, % 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let msg = Message::Results(vec![
+            Message::Ok(Some(1)),
+            Message::Err("retry; later".to_string()),
+            Message::Conflict(4, Some(1)),
+        ]);
+        let text = msg.to_string();
+        assert_eq!(
+            text,
+            r#"RESULTS OK @1; ERROR "retry; later"; ERROR "conflict" VALUE 4 @1"#
+        );
+        assert_eq!(parse_message(&text).unwrap(), msg);
+        assert_eq!(parse_message("RESULTS").unwrap(), Message::Results(vec![]));
+        assert!(parse_message("RESULTS OK; GET").is_err());
+    }
+
+    #[test]
+    fn test_parse_hello() {
+        let msg = parse_message("HELLO 1 batch numeric=u16").unwrap();
+        assert_eq!(
+            msg,
+            Message::Hello {
+                version: 1,
+                capabilities: vec!["batch".to_string(), "numeric=u16".to_string()]
+            }
+        );
+        assert_eq!(msg.to_string(), "HELLO 1 batch numeric=u16");
+        assert_eq!(
+            parse_message("HELLO 2").unwrap(),
+            Message::Hello {
+                version: 2,
+                capabilities: vec![]
+            }
+        );
+        assert!(parse_message("HELLO x").is_err());
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        let op = prop_oneof![
+            Just(Operator::Add),
+            Just(Operator::Sub),
+            Just(Operator::Mul),
+            Just(Operator::Div),
+        ];
+        (op, any::<u64>()).prop_map(|(op, operand)| Operation { op, operand })
+    }
+
+    /// Palabra sin espacios, como un usuario o una capacidad.
+    fn word() -> impl Strategy<Value = String> {
+        "\\S{1,12}"
+    }
+
+    /// Motivo de un `ERROR`, con cualquier carácter.
+    fn reason() -> impl Strategy<Value = String> {
+        ".{0,24}|[\"\\\\\\n\\r; a]{0,24}"
+    }
+
+    fn version() -> impl Strategy<Value = Option<u64>> {
+        proptest::option::of(any::<u64>())
+    }
+
+    /// Resultado individual de un `RESULTS`.
+    fn result() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            version().prop_map(Message::Ok),
+            reason().prop_map(Message::Err),
+            (any::<u64>(), version()).prop_map(|(v, ver)| Message::Conflict(v, ver)),
+        ]
+    }
+
+    fn message() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            (operation(), version()).prop_map(|(op, v)| Message::Op(op, v)),
+            Just(Message::Get),
+            any::<u64>().prop_map(|v| Message::GetAt(PointInTime::Version(v))),
+            any::<u64>().prop_map(|t| Message::GetAt(PointInTime::Time(t))),
+            proptest::option::of(any::<u64>()).prop_map(Message::Snapshot),
+            any::<u64>().prop_map(Message::Restore),
+            (word(), word()).prop_map(|(user, password)| Message::Auth { user, password }),
+            (any::<u64>(), any::<u64>()).prop_map(|(expected, new)| Message::Cas { expected, new }),
+            (any::<u64>(), operation()).prop_map(|(expected, op)| Message::OpIf { expected, op }),
+            any::<usize>().prop_map(Message::Batch),
+            proptest::collection::vec(operation(), 0..20).prop_map(Message::Ops),
+            proptest::collection::vec(proptest::collection::vec(operation(), 1..5), 0..5)
+                .prop_map(Message::Lines),
+            proptest::collection::vec(result(), 0..8).prop_map(Message::Results),
+            (any::<u32>(), proptest::collection::vec(word(), 0..5)).prop_map(
+                |(version, capabilities)| Message::Hello {
+                    version,
+                    capabilities
+                }
+            ),
+            result(),
+            (any::<u64>(), version()).prop_map(|(v, ver)| Message::Value(v, ver)),
+            (any::<u64>(), version()).prop_map(|(v, ver)| Message::Update(v, ver)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_display_round_trip(msg in message()) {
+            prop_assert_eq!(parse_message(&msg.to_string()), Ok(msg));
+        }
+
+        #[test]
+        fn prop_parsed_messages_round_trip(
+            line in "(OP|GET|GET AT|SNAPSHOT|RESTORE|OK|ERROR|VALUE|UPDATE|AUTH|CAS|OPIF|BATCH|OPS|RESULTS|HELLO)( .{0,40})?|.{0,40}"
+        ) {
+            // nunca entra en pánico, y lo que acepta se vuelve a escribir igual
+            if let Ok(msg) = parse_message(&line) {
+                prop_assert_eq!(parse_message(&msg.to_string()), Ok(msg));
+            }
+        }
+    }
 }
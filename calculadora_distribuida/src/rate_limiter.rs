@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Cantidad de claves a partir de la cual se descartan los buckets llenos,
+/// para que clientes que ya no se conectan no ocupen memoria indefinidamente.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Parámetros de un token bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitConfig {
+    /// Cantidad máxima de tokens acumulables (ráfaga permitida).
+    pub capacity: u32,
+    /// Tokens que se reponen por segundo.
+    pub refill_per_sec: f64,
+}
+
+/// Estado del bucket de un cliente.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Limitador de tasa por cliente basado en token buckets.
+///
+/// Cada clave (dirección del peer o usuario autenticado) tiene su propio
+/// bucket; cada mensaje consume un token, y un lote uno por operación.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Crea un limitador con la configuración dada; la tasa de reposición
+    /// debe ser positiva (ver `config::parse_rate`).
+    pub fn new(config: RateLimitConfig) -> Self {
+        debug_assert!(config.refill_per_sec > 0.0);
+        RateLimiter {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Intenta consumir un token para `key`.
+    ///
+    /// # Retorno
+    /// - `Ok(())` si el cliente puede continuar.
+    /// - `Err(Duration)` con el tiempo a esperar hasta disponer de un token.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    /// Igual que `check`, pero usando `now` como instante actual.
+    pub fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        self.check_n_at(key, 1, now)
+    }
+
+    /// Intenta consumir `n` tokens para `key` de una vez: si no alcanzan no
+    /// consume ninguno.
+    ///
+    /// # Retorno
+    /// Como `check`; si `n` supera la capacidad nunca alcanzan y se retorna
+    /// `Err(Duration::MAX)`, igual que si la espera no entra en un
+    /// `Duration`.
+    pub fn check_n(&self, key: &str, n: u32) -> Result<(), Duration> {
+        self.check_n_at(key, n, Instant::now())
+    }
+
+    /// Igual que `check_n`, pero usando `now` como instante actual.
+    pub fn check_n_at(&self, key: &str, n: u32, now: Instant) -> Result<(), Duration> {
+        let needed = f64::from(n);
+        let capacity = f64::from(self.config.capacity);
+        let rate = self.config.refill_per_sec;
+        let mut buckets = match self.buckets.lock() {
+            Ok(b) => b,
+            // Un bucket a medio actualizar no compromete la seguridad: se sigue usando.
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, b| {
+                let elapsed = now.saturating_duration_since(b.last_refill).as_secs_f64();
+                b.tokens + elapsed * rate < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket {
+            tokens: capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= needed {
+            bucket.tokens -= needed;
+            return Ok(());
+        }
+
+        if needed > capacity {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::try_from_secs_f64((needed - bucket.tokens) / rate).unwrap_or(Duration::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(capacity: u32, refill_per_sec: f64) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            capacity,
+            refill_per_sec,
+        })
+    }
+
+    #[test]
+    fn test_burst_then_limited() {
+        let rl = limiter(3, 1.0);
+        let now = Instant::now();
+        assert!(rl.check_at("a", now).is_ok());
+        assert!(rl.check_at("a", now).is_ok());
+        assert!(rl.check_at("a", now).is_ok());
+        let wait = rl.check_at("a", now).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_refill() {
+        let rl = limiter(1, 10.0);
+        let now = Instant::now();
+        assert!(rl.check_at("a", now).is_ok());
+        assert!(rl.check_at("a", now).is_err());
+        assert!(rl.check_at("a", now + Duration::from_millis(100)).is_ok());
+    }
+
+    #[test]
+    fn test_check_n_is_all_or_nothing() {
+        let rl = limiter(5, 1.0);
+        let now = Instant::now();
+        assert!(rl.check_n_at("a", 3, now).is_ok());
+        assert_eq!(rl.check_n_at("a", 3, now), Err(Duration::from_secs(1)));
+        // el lote rechazado no consumió nada
+        assert!(rl.check_n_at("a", 2, now).is_ok());
+        assert!(rl.check_at("a", now).is_err());
+        assert_eq!(
+            rl.check_n_at("b", 6, now + Duration::from_secs(60)),
+            Err(Duration::MAX)
+        );
+    }
+
+    #[test]
+    fn test_tiny_rate_does_not_overflow() {
+        let rl = limiter(1, 1e-20);
+        let now = Instant::now();
+        assert!(rl.check_at("a", now).is_ok());
+        assert_eq!(rl.check_at("a", now), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn test_keys_are_independent() {
+        let rl = limiter(1, 1.0);
+        let now = Instant::now();
+        assert!(rl.check_at("a", now).is_ok());
+        assert!(rl.check_at("a", now).is_err());
+        assert!(rl.check_at("b", now).is_ok());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::protocol::{Message, Operation, parse_message};
+
+/// Ayuda del modo interactivo.
+pub const HELP: &str = "\
+Comandos:
+  <op> <numero>   envia una operacion, por ejemplo: + 5, * 3, / 2
+  get             consulta el valor actual del servidor (el prompt muestra
+                  ? si otro cliente lo cambio desde el ultimo conocido)
+  history         lista los comandos ingresados
+  !!              repite el ultimo comando
+  !<n>            repite el comando numero <n> del historial
+  help            muestra esta ayuda
+  quit, exit      termina la sesion
+En una terminal: flechas, Inicio y Fin para moverse, arriba y abajo para
+recorrer el historial, Ctrl-U y Ctrl-K para borrar, Ctrl-C descarta la
+linea y Ctrl-D en una linea vacia termina.";
+
+/// Comando ingresado en el modo interactivo.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Operación a enviar con `OP`.
+    Op(Operation),
+    /// Consultar el valor con `GET`.
+    Get,
+    /// Mostrar el historial.
+    History,
+    /// Repetir una entrada del historial; `None` es la última.
+    Recall(Option<usize>),
+    /// Mostrar la ayuda.
+    Help,
+    /// Terminar la sesión.
+    Quit,
+    /// Línea vacía.
+    Empty,
+}
+
+/// Interpreta una línea ingresada por el usuario.
+///
+/// Las operaciones siguen las mismas reglas que el mensaje `OP` del
+/// protocolo (`<operador> <numero>`).
+///
+/// # Errores
+/// Retorna `Err(String)` si la línea no corresponde a ningún comando.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let s = line.trim();
+    match s.to_lowercase().as_str() {
+        "" => return Ok(Command::Empty),
+        "get" => return Ok(Command::Get),
+        "history" => return Ok(Command::History),
+        "help" | "?" => return Ok(Command::Help),
+        "quit" | "exit" => return Ok(Command::Quit),
+        "!!" => return Ok(Command::Recall(None)),
+        _ => {}
+    }
+
+    if let Some(n) = s.strip_prefix('!') {
+        return n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .map(|n| Command::Recall(Some(n)))
+            .ok_or_else(|| format!("Entrada de historial invalida: {}", n));
+    }
+
+    match parse_message(&format!("OP {}", s)) {
+        Ok(Message::Op(op, None)) => Ok(Command::Op(op)),
+        Ok(_) => Err(format!("Comando desconocido: {}", s)),
+        Err(e) => Err(format!("{} (escriba 'help' para ver los comandos)", e)),
+    }
+}
+
+/// Prompt que muestra el último valor conocido del servidor.
+pub fn prompt(value: Option<u64>) -> String {
+    match value {
+        Some(v) => format!("[{}]> ", v),
+        None => "[?]> ".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::Operator;
+
+    #[test]
+    fn test_parse_op_command() {
+        assert_eq!(
+            parse_command("+ 5").unwrap(),
+            Command::Op(Operation {
+                op: Operator::Add,
+                operand: 5
+            })
+        );
+        assert!(parse_command("% 5").is_err());
+        assert!(parse_command("+").is_err());
+    }
+
+    #[test]
+    fn test_parse_builtin_commands() {
+        assert_eq!(parse_command("GET").unwrap(), Command::Get);
+        assert_eq!(parse_command(" history ").unwrap(), Command::History);
+        assert_eq!(parse_command("help").unwrap(), Command::Help);
+        assert_eq!(parse_command("exit").unwrap(), Command::Quit);
+        assert_eq!(parse_command("").unwrap(), Command::Empty);
+    }
+
+    #[test]
+    fn test_parse_recall() {
+        assert_eq!(parse_command("!!").unwrap(), Command::Recall(None));
+        assert_eq!(parse_command("!3").unwrap(), Command::Recall(Some(3)));
+        assert!(parse_command("!0").is_err());
+        assert!(parse_command("!x").is_err());
+    }
+
+    #[test]
+    fn test_prompt() {
+        assert_eq!(prompt(Some(42)), "[42]> ");
+        assert_eq!(prompt(None), "[?]> ");
+    }
+}
@@ -0,0 +1,325 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::json::Json;
+use crate::protocol::Message;
+
+/// Formato del reporte que imprime el cliente al procesar un archivo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("Formato desconocido: {} (text, json o csv)", s)),
+        }
+    }
+}
+
+/// Resultado de una línea del archivo de operaciones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineResult {
+    /// Número de línea, desde 1.
+    pub line: usize,
+    pub input: String,
+    /// La versión del estado tras la última operación de la línea, si el
+    /// servidor la informa, o el motivo del primer error.
+    pub result: Result<Option<u64>, String>,
+}
+
+/// Resultados de un archivo completo y el valor final del servidor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub lines: Vec<LineResult>,
+    /// `None` si no se consultó (por ejemplo, al enviar por UDP).
+    pub value: Option<u64>,
+}
+
+/// Reporte de uno de varios archivos de operaciones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// El archivo como se indicó, o `-` para `stdin`.
+    pub file: String,
+    /// Su `value` es el valor del servidor al terminar el archivo.
+    pub report: Report,
+}
+
+impl Report {
+    /// Cantidad de líneas con error.
+    pub fn errors(&self) -> usize {
+        self.lines.iter().filter(|l| l.result.is_err()).count()
+    }
+
+    /// Genera el reporte en el formato pedido.
+    ///
+    /// - `text`: `linea <n>: <entrada> -> <resultado>` por línea, seguidas de
+    ///   `errores: <n>` y `valor: <v>`.
+    /// - `json`: `{"lines": [...], "errors": n, "value": v}`.
+    /// - `csv`: `line,input,status,detail` con una fila por línea (el
+    ///   detalle es la versión o el motivo del error) y una fila final
+    ///   `,,value,<v>`.
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.render_text(),
+            Format::Json => self.render_json().to_string() + "\n",
+            Format::Csv => self.render_csv(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            let result = match &line.result {
+                Ok(version) => Message::Ok(*version),
+                Err(motivo) => Message::Err(motivo.clone()),
+            };
+            let _ = writeln!(out, "linea {}: {} -> {}", line.line, line.input, result);
+        }
+        let _ = writeln!(out, "errores: {}", self.errors());
+        if let Some(value) = self.value {
+            let _ = writeln!(out, "valor: {}", value);
+        }
+        out
+    }
+
+    fn render_json(&self) -> Json {
+        Json::Object(self.json_pairs())
+    }
+
+    fn json_pairs(&self) -> Vec<(String, Json)> {
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| {
+                let mut pairs = vec![
+                    ("line".to_string(), Json::number(line.line as u64)),
+                    ("input".to_string(), Json::String(line.input.clone())),
+                ];
+                match &line.result {
+                    Ok(version) => {
+                        pairs.push(("status".to_string(), Json::String("ok".to_string())));
+                        if let Some(v) = version {
+                            pairs.push(("version".to_string(), Json::number(*v)));
+                        }
+                    }
+                    Err(motivo) => {
+                        pairs.push(("status".to_string(), Json::String("error".to_string())));
+                        pairs.push(("error".to_string(), Json::String(motivo.clone())));
+                    }
+                }
+                Json::Object(pairs)
+            })
+            .collect();
+        vec![
+            ("lines".to_string(), Json::Array(lines)),
+            ("errors".to_string(), Json::number(self.errors() as u64)),
+            (
+                "value".to_string(),
+                self.value.map_or(Json::Null, Json::number),
+            ),
+        ]
+    }
+
+    fn render_csv(&self) -> String {
+        let mut out = String::from("line,input,status,detail\n");
+        self.csv_rows("", &mut out);
+        out
+    }
+
+    /// Agrega a `out` las filas CSV del reporte, cada una precedida por
+    /// `prefix`.
+    fn csv_rows(&self, prefix: &str, out: &mut String) {
+        for line in &self.lines {
+            let (status, detail) = match &line.result {
+                Ok(version) => ("ok", version.map(|v| v.to_string()).unwrap_or_default()),
+                Err(motivo) => ("error", motivo.clone()),
+            };
+            let _ = writeln!(
+                out,
+                "{}{},{},{},{}",
+                prefix,
+                line.line,
+                csv_field(&line.input),
+                status,
+                csv_field(&detail)
+            );
+        }
+        if let Some(value) = self.value {
+            let _ = writeln!(out, "{},,value,{}", prefix, value);
+        }
+    }
+}
+
+/// Genera el reporte de varios archivos en el formato pedido.
+///
+/// - `text`: el reporte de cada archivo precedido por `== <archivo> ==`,
+///   seguidos de `archivos: <n>, errores: <n>`.
+/// - `json`: `{"files": [{"file": ..., "lines": [...], "errors": n,
+///   "value": v}, ...], "errors": n}`.
+/// - `csv`: como el de un archivo, con una primera columna `file`.
+pub fn render_files(files: &[FileReport], format: Format) -> String {
+    let errors: usize = files.iter().map(|f| f.report.errors()).sum();
+    match format {
+        Format::Text => {
+            let mut out = String::new();
+            for f in files {
+                let _ = write!(out, "== {} ==\n{}", f.file, f.report.render_text());
+            }
+            let _ = writeln!(out, "archivos: {}, errores: {}", files.len(), errors);
+            out
+        }
+        Format::Json => {
+            let files = files
+                .iter()
+                .map(|f| {
+                    let mut pairs = vec![("file".to_string(), Json::String(f.file.clone()))];
+                    pairs.extend(f.report.json_pairs());
+                    Json::Object(pairs)
+                })
+                .collect();
+            let json = Json::object([
+                ("files", Json::Array(files)),
+                ("errors", Json::number(errors as u64)),
+            ]);
+            json.to_string() + "\n"
+        }
+        Format::Csv => {
+            let mut out = String::from("file,line,input,status,detail\n");
+            for f in files {
+                f.report
+                    .csv_rows(&format!("{},", csv_field(&f.file)), &mut out);
+            }
+            out
+        }
+    }
+}
+
+/// Campo CSV (RFC 4180): entre comillas si contiene comas, comillas o
+/// saltos de línea.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        Report {
+            lines: vec![
+                LineResult {
+                    line: 1,
+                    input: "+ 5".to_string(),
+                    result: Ok(Some(1)),
+                },
+                LineResult {
+                    line: 3,
+                    input: "x = x / 0".to_string(),
+                    result: Err("division by zero".to_string()),
+                },
+                LineResult {
+                    line: 4,
+                    input: "x, y".to_string(),
+                    result: Err("columna 2: caracter inesperado ','".to_string()),
+                },
+            ],
+            value: Some(5),
+        }
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_render_text() {
+        assert_eq!(
+            report().render(Format::Text),
+            "linea 1: + 5 -> OK @1\n\
+             linea 3: x = x / 0 -> ERROR \"division by zero\"\n\
+             linea 4: x, y -> ERROR \"columna 2: caracter inesperado ','\"\n\
+             errores: 2\n\
+             valor: 5\n"
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json = crate::json::parse(&report().render(Format::Json)).unwrap();
+        assert_eq!(json.get("errors").and_then(Json::as_u64), Some(2));
+        assert_eq!(json.get("value").and_then(Json::as_u64), Some(5));
+        let lines = json.get("lines").and_then(Json::as_array).unwrap();
+        assert_eq!(lines[0].get("version").and_then(Json::as_u64), Some(1));
+        assert_eq!(
+            lines[1].get("error").and_then(Json::as_str),
+            Some("division by zero")
+        );
+
+        let empty = Report {
+            lines: Vec::new(),
+            value: None,
+        };
+        assert_eq!(
+            empty.render(Format::Json),
+            "{\"lines\":[],\"errors\":0,\"value\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_render_csv() {
+        assert_eq!(
+            report().render(Format::Csv),
+            "line,input,status,detail\n\
+             1,+ 5,ok,1\n\
+             3,x = x / 0,error,division by zero\n\
+             4,\"x, y\",error,\"columna 2: caracter inesperado ','\"\n\
+             ,,value,5\n"
+        );
+    }
+
+    #[test]
+    fn test_render_files() {
+        let files = [
+            FileReport {
+                file: "a.txt".to_string(),
+                report: report(),
+            },
+            FileReport {
+                file: "-".to_string(),
+                report: Report {
+                    lines: Vec::new(),
+                    value: Some(7),
+                },
+            },
+        ];
+
+        let text = render_files(&files, Format::Text);
+        assert!(text.starts_with("== a.txt ==\nlinea 1: + 5 -> OK @1\n"));
+        assert!(text.ends_with("== - ==\nerrores: 0\nvalor: 7\narchivos: 2, errores: 2\n"));
+
+        let json = crate::json::parse(&render_files(&files, Format::Json)).unwrap();
+        assert_eq!(json.get("errors").and_then(Json::as_u64), Some(2));
+        let reports = json.get("files").and_then(Json::as_array).unwrap();
+        assert_eq!(reports[1].get("file").and_then(Json::as_str), Some("-"));
+        assert_eq!(reports[1].get("value").and_then(Json::as_u64), Some(7));
+
+        let csv = render_files(&files, Format::Csv);
+        assert!(csv.starts_with("file,line,input,status,detail\na.txt,1,+ 5,ok,1\n"));
+        assert!(csv.ends_with("a.txt,,,value,5\n-,,,value,7\n"));
+    }
+}
@@ -0,0 +1,396 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use crate::linearizability::Call;
+
+/// Ticks que un cliente espera una respuesta antes de darla por perdida y
+/// reconectarse.
+pub const RESPONSE_TIMEOUT: u64 = 1000;
+
+/// Servicio que atiende conexiones línea a línea, independiente del medio
+/// de transporte.
+pub trait Service {
+    type Session;
+
+    /// Abre la sesión de un cliente recién conectado.
+    fn connect(&self, peer: &str) -> Self::Session;
+
+    /// Procesa una línea del cliente y escribe las respuestas en `out`.
+    /// Retorna `false` si el servicio cierra la conexión.
+    fn handle(&self, session: &mut Self::Session, line: &str, out: &mut Vec<u8>) -> bool;
+}
+
+/// Fallas de red que inyecta la simulación.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkFaults {
+    /// Demora máxima de cada mensaje, en ticks.
+    pub max_delay: u64,
+    /// Probabilidad de que se pierda un pedido o una respuesta.
+    pub drop_rate: f64,
+    /// Probabilidad de que la conexión se reinicie al llegar un pedido.
+    pub reset_rate: f64,
+}
+
+impl Default for NetworkFaults {
+    fn default() -> Self {
+        NetworkFaults {
+            max_delay: 10,
+            drop_rate: 0.0,
+            reset_rate: 0.0,
+        }
+    }
+}
+
+/// Generador pseudoaleatorio xorshift64, suficiente para que una corrida
+/// sea reproducible a partir de su semilla.
+#[derive(Debug, Clone)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // el estado no puede ser cero
+        XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Número uniforme en `0..=max`.
+    pub fn up_to(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(n) => self.next_u64() % n,
+            None => self.next_u64(),
+        }
+    }
+
+    /// `true` con probabilidad `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && (self.next_u64() as f64 / u64::MAX as f64) < p
+    }
+}
+
+/// Ejecuta los guiones de varios clientes concurrentes contra `service` en
+/// un único hilo, con tiempo virtual y un planificador determinado por
+/// `seed`.
+///
+/// Cada cliente envía las líneas de su guion de a una, esperando la
+/// respuesta antes de enviar la siguiente; cada pedido debe producir
+/// exactamente una línea de respuesta. Los mensajes se demoran, pierden y
+/// las conexiones se reinician según `faults`. Si la respuesta no llega
+/// (por una pérdida o un reinicio), el cliente se reconecta con una sesión
+/// nueva y la operación queda registrada sin respuesta.
+///
+/// La misma semilla produce siempre la misma historia, de modo que una
+/// falla encontrada se reproduce corriendo de nuevo con esa semilla.
+///
+/// # Retorno
+/// Las operaciones en el orden en que terminaron, con los tiempos en ticks
+/// virtuales expresados como milisegundos.
+pub fn run<S: Service>(
+    service: &S,
+    scripts: &[Vec<String>],
+    faults: &NetworkFaults,
+    seed: u64,
+) -> Vec<Call<String, String>> {
+    let mut world = World {
+        service,
+        faults: *faults,
+        rng: XorShift::new(seed),
+        now: 0,
+        seq: 0,
+        events: BinaryHeap::new(),
+        clients: Vec::new(),
+        history: Vec::new(),
+    };
+    for (index, script) in scripts.iter().enumerate() {
+        let client = Client {
+            conn: 0,
+            session: service.connect(&peer(index, 0)),
+            script: script.clone().into_iter(),
+            pending: None,
+        };
+        world.clients.push(client);
+        world.send_next(index);
+    }
+
+    while let Some(Reverse((now, _, event))) = world.events.pop() {
+        world.now = now;
+        match event {
+            Event::Deliver {
+                client,
+                conn,
+                request,
+                line,
+            } => world.deliver(client, conn, request, &line),
+            Event::Respond {
+                client,
+                request,
+                output,
+            } => world.complete(client, request, Some(output)),
+            Event::Lost { client, request } => world.complete(client, request, None),
+        }
+    }
+    world.history
+}
+
+/// Identifica la conexión `conn` del cliente `index` ante el servicio.
+fn peer(index: usize, conn: u64) -> String {
+    format!("sim-{}-{}", index, conn)
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    /// El pedido llega al servicio por la conexión `conn`.
+    Deliver {
+        client: usize,
+        conn: u64,
+        request: u64,
+        line: String,
+    },
+    /// La respuesta llega al cliente.
+    Respond {
+        client: usize,
+        request: u64,
+        output: String,
+    },
+    /// El cliente deja de esperar la respuesta: venció el timeout o vio la
+    /// conexión cerrada.
+    Lost { client: usize, request: u64 },
+}
+
+/// Pedido enviado cuya respuesta el cliente todavía espera.
+struct Pending {
+    request: u64,
+    line: String,
+    invoke: u64,
+}
+
+struct Client<T> {
+    /// Conexión actual; los mensajes de conexiones anteriores se descartan.
+    conn: u64,
+    session: T,
+    script: std::vec::IntoIter<String>,
+    pending: Option<Pending>,
+}
+
+struct World<'a, S: Service> {
+    service: &'a S,
+    faults: NetworkFaults,
+    rng: XorShift,
+    now: u64,
+    /// Desempata eventos del mismo tick por orden de creación.
+    seq: u64,
+    events: BinaryHeap<Reverse<(u64, u64, Event)>>,
+    clients: Vec<Client<S::Session>>,
+    history: Vec<Call<String, String>>,
+}
+
+impl<S: Service> World<'_, S> {
+    fn schedule(&mut self, at: u64, event: Event) {
+        self.seq += 1;
+        self.events.push(Reverse((at, self.seq, event)));
+    }
+
+    fn delay(&mut self) -> u64 {
+        self.now + 1 + self.rng.up_to(self.faults.max_delay)
+    }
+
+    /// Envía la próxima línea del guion del cliente, si queda alguna.
+    fn send_next(&mut self, client: usize) {
+        let Some(line) = self.clients[client].script.next() else {
+            return;
+        };
+        self.seq += 1;
+        let request = self.seq;
+        let conn = self.clients[client].conn;
+        self.clients[client].pending = Some(Pending {
+            request,
+            line: line.clone(),
+            invoke: self.now,
+        });
+
+        if !self.rng.chance(self.faults.drop_rate) {
+            let at = self.delay();
+            self.schedule(
+                at,
+                Event::Deliver {
+                    client,
+                    conn,
+                    request,
+                    line,
+                },
+            );
+        }
+        self.schedule(self.now + RESPONSE_TIMEOUT, Event::Lost { client, request });
+    }
+
+    fn deliver(&mut self, client: usize, conn: u64, request: u64, line: &str) {
+        if self.clients[client].conn != conn {
+            return;
+        }
+
+        // Un reinicio puede ocurrir antes o después de procesar el pedido;
+        // en ambos casos la respuesta no llega.
+        let reset = self.rng.chance(self.faults.reset_rate);
+        let processed = !reset || self.rng.chance(0.5);
+        let mut out = Vec::new();
+        let open = !processed
+            || self
+                .service
+                .handle(&mut self.clients[client].session, line, &mut out);
+
+        if reset {
+            self.reconnect(client);
+            let at = self.delay();
+            self.schedule(at, Event::Lost { client, request });
+            return;
+        }
+        if !open {
+            self.reconnect(client);
+        }
+        if !self.rng.chance(self.faults.drop_rate) {
+            let output = String::from_utf8_lossy(&out).trim_end().to_string();
+            let at = self.delay();
+            self.schedule(
+                at,
+                Event::Respond {
+                    client,
+                    request,
+                    output,
+                },
+            );
+        }
+    }
+
+    /// Termina el pedido `request` si el cliente todavía lo espera.
+    fn complete(&mut self, client: usize, request: u64, output: Option<String>) {
+        let pending = &mut self.clients[client].pending;
+        if pending.as_ref().is_none_or(|p| p.request != request) {
+            return;
+        }
+        let Some(Pending { line, invoke, .. }) = pending.take() else {
+            return;
+        };
+
+        let lost = output.is_none();
+        self.history.push(Call {
+            client,
+            input: line,
+            invoke: Duration::from_millis(invoke),
+            response: match output {
+                Some(_) => Duration::from_millis(self.now),
+                None => Duration::MAX,
+            },
+            output,
+        });
+        if lost {
+            self.reconnect(client);
+        }
+        self.send_next(client);
+    }
+
+    fn reconnect(&mut self, client: usize) {
+        let conn = self.clients[client].conn + 1;
+        self.clients[client].conn = conn;
+        self.clients[client].session = self.service.connect(&peer(client, conn));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Contador compartido: cada línea le suma su número y responde el
+    /// total.
+    #[derive(Default)]
+    struct Counter {
+        total: Cell<u64>,
+        connections: Cell<u64>,
+    }
+
+    impl Service for Counter {
+        type Session = ();
+
+        fn connect(&self, _peer: &str) {
+            self.connections.set(self.connections.get() + 1);
+        }
+
+        fn handle(&self, _session: &mut (), line: &str, out: &mut Vec<u8>) -> bool {
+            let total = self.total.get() + line.parse::<u64>().unwrap();
+            self.total.set(total);
+            out.extend_from_slice(format!("{}\n", total).as_bytes());
+            true
+        }
+    }
+
+    fn scripts(clients: usize, requests: usize) -> Vec<Vec<String>> {
+        (0..clients)
+            .map(|c| (0..requests).map(|i| (c + i).to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_run_without_faults() {
+        let counter = Counter::default();
+        let history = run(&counter, &scripts(3, 5), &NetworkFaults::default(), 1);
+        assert_eq!(history.len(), 15);
+        assert!(history.iter().all(|c| c.output.is_some()));
+        assert!(history.iter().all(|c| c.invoke < c.response));
+        assert_eq!(counter.total.get(), 45);
+        assert_eq!(counter.connections.get(), 3);
+    }
+
+    #[test]
+    fn test_run_is_deterministic() {
+        let faults = NetworkFaults {
+            max_delay: 20,
+            drop_rate: 0.1,
+            reset_rate: 0.1,
+        };
+        let a = run(&Counter::default(), &scripts(4, 20), &faults, 42);
+        let b = run(&Counter::default(), &scripts(4, 20), &faults, 42);
+        let c = run(&Counter::default(), &scripts(4, 20), &faults, 43);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_faults_lose_responses() {
+        let faults = NetworkFaults {
+            max_delay: 5,
+            drop_rate: 0.2,
+            reset_rate: 0.2,
+        };
+        let counter = Counter::default();
+        let history = run(&counter, &scripts(2, 50), &faults, 7);
+        // Ningún pedido queda sin registrar, aunque se pierda la respuesta.
+        assert_eq!(history.len(), 100);
+        let lost = history.iter().filter(|c| c.output.is_none()).count();
+        assert!(lost > 0);
+        assert!(
+            history
+                .iter()
+                .filter(|c| c.output.is_none())
+                .all(|c| c.response == Duration::MAX)
+        );
+        // Cada pérdida abre una sesión nueva.
+        assert!(counter.connections.get() > 2);
+    }
+
+    #[test]
+    fn test_xorshift() {
+        let mut rng = XorShift::new(0);
+        assert!((0..100).all(|_| rng.up_to(3) <= 3));
+        assert!(!rng.chance(0.0));
+        assert!(rng.chance(1.0));
+        assert_eq!(XorShift::new(5).next_u64(), XorShift::new(5).next_u64());
+    }
+}
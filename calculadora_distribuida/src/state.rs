@@ -0,0 +1,512 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use crate::calculator::{Arithmetic, NumericType, OverflowPolicy};
+use crate::operator::Operator;
+use crate::protocol::Operation;
+
+/// Cómo se sincroniza el valor central entre conexiones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Un `Mutex`: cada modificación toma el lock (comportamiento original).
+    #[default]
+    Mutex,
+    /// Un `AtomicU64` con valor y versión, modificado con compare-exchange.
+    Atomic,
+    /// Un `Mutex`, pero las sumas y restas se acumulan por hilo sin tomarlo
+    /// (ver `AggregateState`).
+    Aggregate,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mutex" => Ok(Backend::Mutex),
+            "atomic" => Ok(Backend::Atomic),
+            "aggregate" => Ok(Backend::Aggregate),
+            _ => Err(format!(
+                "Backend invalido: {} (se esperaba mutex, atomic o aggregate)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Mutex => write!(f, "mutex"),
+            Backend::Atomic => write!(f, "atomic"),
+            Backend::Aggregate => write!(f, "aggregate"),
+        }
+    }
+}
+
+/// Valor central de la calculadora.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct State {
+    pub value: u64,
+    /// Aumenta en uno con cada modificación exitosa del valor.
+    pub version: u64,
+}
+
+/// Estado guardado en un único `AtomicU64`: el valor en los bits bajos y la
+/// versión en los altos, para que ambos cambien juntos con un solo
+/// compare-exchange.
+///
+/// Por eso sólo admite tipos numéricos de hasta 16 bits, que dejan 48 bits
+/// para la versión.
+#[derive(Debug)]
+pub struct AtomicState {
+    packed: AtomicU64,
+    value_bits: u32,
+}
+
+impl AtomicState {
+    /// Estado atómico que empieza en `initial`.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si el tipo numérico es de más de 16 bits o si
+    /// la versión inicial no entra en los bits restantes.
+    pub fn new(initial: State, numeric_type: NumericType) -> Result<Self, String> {
+        let value_bits = match numeric_type {
+            NumericType::U8 => 8,
+            NumericType::U16 => 16,
+            NumericType::U32 | NumericType::U64 => {
+                return Err(format!(
+                    "El backend atomic no admite el tipo numerico {} (usar u8 o u16)",
+                    numeric_type
+                ));
+            }
+        };
+        let state = AtomicState {
+            packed: AtomicU64::new(0),
+            value_bits,
+        };
+        let packed = state.pack(initial)?;
+        state.packed.store(packed, Ordering::Release);
+        Ok(state)
+    }
+
+    /// Lee el estado actual.
+    pub fn load(&self) -> State {
+        self.unpack(self.packed.load(Ordering::Acquire))
+    }
+
+    /// Ver `SharedState::update`. `f` puede ejecutarse varias veces, una por
+    /// cada intento que pierde la carrera con otra modificación.
+    pub fn update<R>(
+        &self,
+        mut f: impl FnMut(&mut State) -> R,
+        commit: impl FnOnce(&State, &R),
+    ) -> Result<R, String> {
+        let mut current = self.packed.load(Ordering::Acquire);
+        loop {
+            let state = self.unpack(current);
+            let mut next = state;
+            let result = f(&mut next);
+            if next.version == state.version {
+                return Ok(result);
+            }
+            let packed = self.pack(next)?;
+            match self.packed.compare_exchange_weak(
+                current,
+                packed,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    commit(&next, &result);
+                    return Ok(result);
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn pack(&self, state: State) -> Result<u64, String> {
+        let value_mask = (1 << self.value_bits) - 1;
+        if state.value > value_mask {
+            return Err(format!("El valor {} no entra en el estado", state.value));
+        }
+        if state.version >> (64 - self.value_bits) != 0 {
+            return Err("Versiones agotadas".to_string());
+        }
+        Ok(state.version << self.value_bits | state.value)
+    }
+
+    fn unpack(&self, packed: u64) -> State {
+        State {
+            value: packed & ((1 << self.value_bits) - 1),
+            version: packed >> self.value_bits,
+        }
+    }
+}
+
+/// Sumas y restas todavía no aplicadas al valor.
+#[derive(Debug, Clone, Copy, Default)]
+struct Pending {
+    /// Suma de los operandos, módulo 2^64; una resta de `n` suma
+    /// `n.wrapping_neg()`.
+    sum: u64,
+    /// Operaciones acumuladas; cada una es una versión.
+    count: u64,
+}
+
+/// Acumulador de un grupo de hilos, alineado para que no comparta línea de
+/// caché con los otros.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard(Mutex<Pending>);
+
+/// Próximo índice de acumulador a asignar a un hilo.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Índice de acumulador de este hilo, asignado la primera vez que lo usa.
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Estado con un `Mutex` en el que las sumas y restas no lo toman: se
+/// acumulan en un acumulador por hilo y se aplican al valor, todas juntas,
+/// antes de cualquier otra modificación.
+///
+/// Con aritmética wrapping las sumas y restas conmutan, así que el valor
+/// resultante es el mismo que aplicándolas de a una. Cada una cuenta como
+/// una versión, pero la versión que le corresponde recién se conoce al
+/// aplicarla.
+#[derive(Debug)]
+pub struct AggregateState {
+    state: Mutex<State>,
+    shards: Vec<Shard>,
+    /// Mayor valor del tipo numérico, que es una potencia de dos menos uno.
+    max: u64,
+}
+
+impl AggregateState {
+    /// Estado que empieza en `initial`, con un acumulador por núcleo.
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si la política de overflow no es wrapping: con
+    /// saturación o error las sumas y restas no conmutan.
+    pub fn new(initial: State, arithmetic: Arithmetic) -> Result<Self, String> {
+        if arithmetic.overflow != OverflowPolicy::Wrapping {
+            return Err("El backend aggregate requiere overflow wrapping".to_string());
+        }
+        let shards = thread::available_parallelism().map_or(1, |n| n.get());
+        Ok(AggregateState {
+            state: Mutex::new(initial),
+            shards: (0..shards).map(|_| Shard::default()).collect(),
+            max: arithmetic.numeric_type.max(),
+        })
+    }
+
+    /// Acumula `op` en el acumulador del hilo actual, si es una suma o una
+    /// resta con un operando que entra en el tipo numérico.
+    ///
+    /// Retorna `None` si no se acumuló y hay que aplicarla con `update`.
+    pub fn accumulate(&self, op: &Operation) -> Option<Result<(), String>> {
+        let delta = match op.op {
+            _ if op.operand > self.max => return None,
+            Operator::Add => op.operand,
+            Operator::Sub => op.operand.wrapping_neg(),
+            Operator::Mul | Operator::Div => return None,
+        };
+        let shard = &self.shards[SHARD.with(|i| *i) % self.shards.len()];
+        let accumulated = lock(&shard.0).map(|mut pending| {
+            pending.sum = pending.sum.wrapping_add(delta);
+            pending.count += 1;
+        });
+        Some(accumulated)
+    }
+
+    /// Lee el estado actual, incluidas las operaciones acumuladas, sin
+    /// aplicarlas.
+    pub fn load(&self) -> Result<State, String> {
+        let guard = lock(&self.state)?;
+        let mut state = *guard;
+        for pending in &self.lock_shards()? {
+            self.apply(&mut state, **pending);
+        }
+        Ok(state)
+    }
+
+    /// Ver `SharedState::update`. Antes de llamar a `f` se aplican las
+    /// operaciones acumuladas, que se guardan junto con el cambio de `f`.
+    pub fn update<R>(
+        &self,
+        f: impl FnOnce(&mut State) -> R,
+        commit: impl FnOnce(&State, &R),
+    ) -> Result<R, String> {
+        let mut guard = lock(&self.state)?;
+        let mut next = *guard;
+        for pending in &mut self.lock_shards()? {
+            self.apply(&mut next, std::mem::take(&mut **pending));
+        }
+        let result = f(&mut next);
+        if next.version != guard.version {
+            *guard = next;
+            commit(&next, &result);
+        }
+        Ok(result)
+    }
+
+    /// Toma los locks de todos los acumuladores a la vez, en orden de
+    /// índice, con el lock de `state` ya tomado.
+    ///
+    /// Leerlos de a uno no alcanza: una suma respondida en un acumulador ya
+    /// leído se perdería mientras se ve otra posterior en uno siguiente.
+    fn lock_shards(&self) -> Result<Vec<MutexGuard<'_, Pending>>, String> {
+        self.shards.iter().map(|shard| lock(&shard.0)).collect()
+    }
+
+    fn apply(&self, state: &mut State, pending: Pending) {
+        // `max + 1` divide a 2^64, así que alcanza con truncar la suma.
+        state.value = state.value.wrapping_add(pending.sum) & self.max;
+        state.version += pending.count;
+    }
+}
+
+/// Toma un lock del estado; si está envenenado, `"Estado inaccesible"`.
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>, String> {
+    mutex.lock().map_err(|_| "Estado inaccesible".to_string())
+}
+
+/// Estado compartido por todas las conexiones, con el backend elegido al
+/// iniciar el servidor.
+#[derive(Debug)]
+pub enum SharedState {
+    Mutex(Mutex<State>),
+    Atomic(AtomicState),
+    Aggregate(AggregateState),
+}
+
+impl SharedState {
+    /// Estado que empieza en `initial` con el backend indicado.
+    ///
+    /// # Errores
+    /// Ver `AtomicState::new` y `AggregateState::new`.
+    pub fn new(backend: Backend, initial: State, arithmetic: Arithmetic) -> Result<Self, String> {
+        match backend {
+            Backend::Mutex => Ok(SharedState::Mutex(Mutex::new(initial))),
+            Backend::Atomic => {
+                AtomicState::new(initial, arithmetic.numeric_type).map(SharedState::Atomic)
+            }
+            Backend::Aggregate => {
+                AggregateState::new(initial, arithmetic).map(SharedState::Aggregate)
+            }
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            SharedState::Mutex(_) => Backend::Mutex,
+            SharedState::Atomic(_) => Backend::Atomic,
+            SharedState::Aggregate(_) => Backend::Aggregate,
+        }
+    }
+
+    /// Con `Backend::Aggregate`, ver `AggregateState::accumulate`; con los
+    /// otros backends retorna `None`.
+    pub fn accumulate(&self, op: &Operation) -> Option<Result<(), String>> {
+        match self {
+            SharedState::Aggregate(state) => state.accumulate(op),
+            _ => None,
+        }
+    }
+
+    /// Lee el estado actual.
+    ///
+    /// # Errores
+    /// Retorna `"Estado inaccesible"` si el lock está envenenado.
+    pub fn load(&self) -> Result<State, String> {
+        match self {
+            SharedState::Mutex(state) => lock(state).map(|guard| *guard),
+            SharedState::Atomic(state) => Ok(state.load()),
+            SharedState::Aggregate(state) => state.load(),
+        }
+    }
+
+    /// Modifica el estado con `f`, que recibe una copia del estado actual y
+    /// retorna la respuesta para el cliente.
+    ///
+    /// Si `f` cambia el valor debe aumentar la versión, una vez por cada
+    /// modificación; si la versión no cambió, la copia se descarta. Si
+    /// cambió, se guarda y se llama a `commit` con el estado nuevo:
+    ///
+    /// - Con `Backend::Mutex`, `f` corre una vez y `commit` se llama con el
+    ///   lock tomado, así sigue el orden de las versiones.
+    /// - Con `Backend::Atomic`, `f` se reintenta hasta que el
+    ///   compare-exchange gane, así que no debe tener efectos; `commit` se
+    ///   llama después, sin orden respecto de los otros hilos.
+    /// - Con `Backend::Aggregate`, como con `Backend::Mutex`, pero la copia
+    ///   ya incluye las operaciones acumuladas.
+    ///
+    /// # Errores
+    /// Retorna `"Estado inaccesible"` si el lock está envenenado, o
+    /// `"Versiones agotadas"` si la versión no entra en el estado atómico.
+    pub fn update<R>(
+        &self,
+        mut f: impl FnMut(&mut State) -> R,
+        commit: impl FnOnce(&State, &R),
+    ) -> Result<R, String> {
+        match self {
+            SharedState::Mutex(state) => {
+                let mut guard = lock(state)?;
+                let mut next = *guard;
+                let result = f(&mut next);
+                if next.version != guard.version {
+                    *guard = next;
+                    commit(&next, &result);
+                }
+                Ok(result)
+            }
+            SharedState::Atomic(state) => state.update(f, commit),
+            SharedState::Aggregate(state) => state.update(f, commit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn state(value: u64, version: u64) -> State {
+        State { value, version }
+    }
+
+    /// Suma `n` y aumenta la versión, con wrapping en `u8`.
+    fn add(n: u64) -> impl FnMut(&mut State) -> u64 {
+        move |s: &mut State| {
+            s.value = (s.value + n) % 256;
+            s.version += 1;
+            s.version
+        }
+    }
+
+    #[test]
+    fn test_parse_backend() {
+        assert_eq!("mutex".parse(), Ok(Backend::Mutex));
+        assert_eq!("atomic".parse(), Ok(Backend::Atomic));
+        assert!("lockfree".parse::<Backend>().is_err());
+        assert_eq!(Backend::Atomic.to_string(), "atomic");
+        assert_eq!("aggregate".parse(), Ok(Backend::Aggregate));
+    }
+
+    #[test]
+    fn test_atomic_rejects_wide_types() {
+        assert!(AtomicState::new(State::default(), NumericType::U32).is_err());
+        assert!(AtomicState::new(State::default(), NumericType::U64).is_err());
+        assert!(AtomicState::new(state(0, 1 << 56), NumericType::U8).is_err());
+        let atomic = AtomicState::new(state(65535, 1 << 40), NumericType::U16).unwrap();
+        assert_eq!(atomic.load(), state(65535, 1 << 40));
+    }
+
+    #[test]
+    fn test_aggregate_applies_pending_on_update() {
+        let op = |op, operand| Operation { op, operand };
+        let mut arithmetic = Arithmetic::default();
+        let shared = SharedState::new(Backend::Aggregate, state(10, 0), arithmetic).unwrap();
+        assert_eq!(shared.accumulate(&op(Operator::Add, 250)), Some(Ok(())));
+        assert_eq!(shared.accumulate(&op(Operator::Sub, 5)), Some(Ok(())));
+        assert_eq!(shared.accumulate(&op(Operator::Mul, 2)), None);
+        assert_eq!(shared.accumulate(&op(Operator::Add, 256)), None);
+        // `load` ve lo acumulado sin aplicarlo
+        assert_eq!(shared.load(), Ok(state(255, 2)));
+
+        let mut commits = Vec::new();
+        let version = shared.update(add(1), |s, _| commits.push(*s)).unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(commits, [state(0, 3)]);
+        assert_eq!(shared.load(), Ok(state(0, 3)));
+
+        arithmetic.overflow = OverflowPolicy::Saturating;
+        assert!(SharedState::new(Backend::Aggregate, State::default(), arithmetic).is_err());
+        let mutex = SharedState::new(Backend::Mutex, State::default(), arithmetic).unwrap();
+        assert_eq!(mutex.accumulate(&op(Operator::Add, 1)), None);
+    }
+
+    #[test]
+    fn test_aggregate_load_holds_every_shard() {
+        let shared = Arc::new(AggregateState {
+            state: Mutex::new(State::default()),
+            shards: vec![Shard::default(), Shard::default()],
+            max: 255,
+        });
+        // con el segundo acumulador tomado, `load` queda esperándolo
+        let second = lock(&shared.shards[1].0).unwrap();
+        let loader = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.load())
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while shared.shards[0].0.try_lock().is_ok() {
+            assert!(Instant::now() < deadline, "load soltó el primer acumulador");
+            thread::yield_now();
+        }
+        // una suma en el primero no puede responderse mientras tanto: si
+        // pudiera, `load` vería la del segundo sin ver ésta, que fue antes
+        for _ in 0..100 {
+            assert!(shared.shards[0].0.try_lock().is_err());
+            thread::yield_now();
+        }
+        let mut second = second;
+        second.sum = 1;
+        second.count = 1;
+        drop(second);
+        assert_eq!(loader.join().unwrap(), Ok(state(1, 1)));
+    }
+
+    #[test]
+    fn test_update_commits_only_changes() {
+        for backend in [Backend::Mutex, Backend::Atomic, Backend::Aggregate] {
+            let shared = SharedState::new(backend, state(250, 3), Arithmetic::default()).unwrap();
+            let mut commits = Vec::new();
+            let version = shared.update(add(10), |s, _| commits.push(*s)).unwrap();
+            assert_eq!(version, 4);
+            assert_eq!(shared.load(), Ok(state(4, 4)));
+
+            let unchanged = shared.update(|s| s.value, |s, _| commits.push(*s));
+            assert_eq!(unchanged, Ok(4));
+            assert_eq!(commits, [state(4, 4)], "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        for backend in [Backend::Mutex, Backend::Atomic, Backend::Aggregate] {
+            let shared = SharedState::new(backend, State::default(), Arithmetic::default());
+            let shared = Arc::new(shared.unwrap());
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let shared = Arc::clone(&shared);
+                    thread::spawn(move || {
+                        let plus_one = Operation {
+                            op: Operator::Add,
+                            operand: 1,
+                        };
+                        for i in 0..1000 {
+                            // con aggregate, la mitad se acumula
+                            match (i % 2 == 0).then(|| shared.accumulate(&plus_one)).flatten() {
+                                Some(accumulated) => accumulated.unwrap(),
+                                None => shared.update(add(1), |_, _| {}).map(drop).unwrap(),
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(shared.load(), Ok(state(4000 % 256, 4000)), "{}", backend);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Pool de hilos de tamaño fijo que ejecuta tareas recibidas por un canal.
+///
+/// Además de las tareas en ejecución admite hasta `queue` esperando un hilo
+/// libre; quien encola puede consultar `is_full` para rechazar las demás.
+pub struct ThreadPool {
+    workers: Vec<JoinHandle<()>>,
+    sender: Option<Sender<Job>>,
+    /// Tareas encoladas o en ejecución.
+    load: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+impl ThreadPool {
+    /// Crea un pool con `size` hilos (al menos uno) y lugar para `queue`
+    /// tareas en espera.
+    pub fn new(size: usize, queue: usize) -> Self {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|_| {
+                let rx = Arc::clone(&receiver);
+                thread::spawn(move || worker_loop(rx))
+            })
+            .collect();
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+            load: Arc::new(AtomicUsize::new(0)),
+            capacity: size + queue,
+        }
+    }
+
+    /// Si todos los hilos están ocupados y la cola de espera está llena.
+    ///
+    /// Las tareas sólo terminan entre la consulta y `execute`, así que con
+    /// un único hilo que encola la respuesta sigue valiendo al encolar. Si
+    /// encolan varios hilos, cada uno puede pasarse en a lo sumo una tarea.
+    pub fn is_full(&self) -> bool {
+        self.load.load(Ordering::Acquire) >= self.capacity
+    }
+
+    /// Encola una tarea para que la ejecute el primer hilo libre, aunque el
+    /// pool esté lleno (ver `is_full`).
+    ///
+    /// # Errores
+    /// Retorna `Err(String)` si todos los hilos terminaron.
+    pub fn execute<F>(&self, job: F) -> Result<(), String>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let Some(tx) = &self.sender else {
+            return Err("Pool de hilos detenido".to_string());
+        };
+        self.load.fetch_add(1, Ordering::AcqRel);
+        let done = Done(Arc::clone(&self.load));
+        tx.send(Box::new(move || {
+            let _done = done;
+            job();
+        }))
+        .map_err(|_| "Pool de hilos detenido".to_string())
+    }
+}
+
+/// Descuenta una tarea de la carga del pool al terminar, aunque la tarea
+/// entre en pánico, o si no llegó a encolarse.
+struct Done(Arc<AtomicUsize>);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Drop for ThreadPool {
+    /// Cierra el canal y espera a que los hilos terminen sus tareas.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Toma tareas del canal hasta que éste se cierre.
+///
+/// Una tarea que entra en pánico no termina el hilo: si no, tras unas
+/// cuantas el pool se quedaría sin hilos y no ejecutaría nada más. El
+/// pánico ya se informó por `stderr` al ocurrir.
+fn worker_loop(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(rx) => rx.recv(),
+            Err(_) => return,
+        };
+        match job {
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_runs_all_jobs() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        {
+            let pool = ThreadPool::new(3, 0);
+            for _ in 0..10 {
+                let c = Arc::clone(&counter);
+                pool.execute(move || {
+                    c.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            }
+        } // drop espera a los hilos
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_survives_panicking_job() {
+        let pool = ThreadPool::new(1, 0);
+        pool.execute(|| panic!("tarea fallida")).unwrap();
+        let (done, ran) = mpsc::channel();
+        pool.execute(move || done.send(()).unwrap()).unwrap();
+        assert!(ran.recv_timeout(std::time::Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_is_full() {
+        let pool = ThreadPool::new(1, 1);
+        let (release, wait) = mpsc::channel::<()>();
+        let wait = Arc::new(Mutex::new(wait));
+        for _ in 0..2 {
+            assert!(!pool.is_full());
+            let wait = Arc::clone(&wait);
+            pool.execute(move || {
+                let _ = wait.lock().map(|rx| rx.recv());
+            })
+            .unwrap();
+        }
+        // una tarea en ejecución y otra en espera
+        assert!(pool.is_full());
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        drop(pool);
+    }
+}
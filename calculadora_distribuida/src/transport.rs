@@ -0,0 +1,384 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::connection::ConnectionLimits;
+
+/// Prefijo de las direcciones de sockets Unix (`unix:/ruta/al/socket`).
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// Dirección con la que el servidor atiende un único cliente por
+/// stdin/stdout.
+pub const STDIO_ADDRESS: &str = "stdio";
+
+/// Conexión aceptada por el servidor, independiente del medio de transporte.
+pub trait Connection: Send + 'static {
+    type Reader: Read + Send;
+    type Writer: Write + Send;
+
+    /// Identifica al cliente en los logs y en la limitación de tasa.
+    fn peer(&self) -> io::Result<String>;
+
+    /// Otro manejador de la conexión, para cerrarla desde otro hilo con
+    /// `Stream::shutdown`; `None` si no se puede cerrar así.
+    fn closer(&self) -> io::Result<Option<Stream>>;
+
+    /// Aplica los timeouts de `limits` y separa la conexión en sus mitades
+    /// de lectura y escritura.
+    fn split(self, limits: &ConnectionLimits) -> io::Result<(Self::Reader, Self::Writer)>;
+}
+
+/// Fuente de conexiones entrantes.
+pub trait Listener {
+    type Connection: Connection;
+
+    /// Espera la próxima conexión.
+    fn accept(&self) -> io::Result<Self::Connection>;
+}
+
+impl Connection for TcpStream {
+    type Reader = TcpStream;
+    type Writer = TcpStream;
+
+    /// La IP del cliente, sin el puerto, para que reconectar no cambie la
+    /// identidad.
+    fn peer(&self) -> io::Result<String> {
+        self.peer_addr().map(|addr| addr.ip().to_string())
+    }
+
+    fn closer(&self) -> io::Result<Option<Stream>> {
+        self.try_clone().map(|s| Some(Stream::Tcp(s)))
+    }
+
+    fn split(self, limits: &ConnectionLimits) -> io::Result<(TcpStream, TcpStream)> {
+        self.set_read_timeout(limits.read_timeout)?;
+        self.set_write_timeout(limits.write_timeout)?;
+        let writer = self.try_clone()?;
+        Ok((self, writer))
+    }
+}
+
+impl Listener for TcpListener {
+    type Connection = TcpStream;
+
+    fn accept(&self) -> io::Result<TcpStream> {
+        TcpListener::accept(self).map(|(stream, _)| stream)
+    }
+}
+
+/// Conexión por TCP o por socket Unix, según la dirección.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Se conecta a `address`: `unix:<ruta>` para un socket Unix o
+    /// `host:puerto` para TCP.
+    pub fn connect(address: &str) -> io::Result<Stream> {
+        match address.strip_prefix(UNIX_PREFIX) {
+            Some(path) => connect_unix(path),
+            None => TcpStream::connect(address).map(Stream::Tcp),
+        }
+    }
+
+    /// Otro manejador de la misma conexión.
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(s) => s.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.try_clone().map(Stream::Unix),
+        }
+    }
+
+    /// Cierra la conexión en ambos sentidos. Quien esté leyendo de otro
+    /// manejador de la misma conexión recibe el fin de los datos.
+    pub fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &str) -> io::Result<Stream> {
+    UnixStream::connect(path).map(Stream::Unix)
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &str) -> io::Result<Stream> {
+    Err(unix_unsupported())
+}
+
+#[cfg(not(unix))]
+fn unix_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "los sockets Unix no estan disponibles en esta plataforma",
+    )
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+impl Connection for Stream {
+    type Reader = Stream;
+    type Writer = Stream;
+
+    /// La IP del cliente TCP. Los clientes de un socket Unix no tienen
+    /// dirección propia, y sus credenciales (`SO_PEERCRED`) no se pueden
+    /// consultar con la biblioteca estándar: cada llamada retorna una
+    /// identidad nueva, `local:<n>`, para que cada conexión tenga su propio
+    /// límite de tasa. El servidor la consulta una sola vez por conexión.
+    fn peer(&self) -> io::Result<String> {
+        match self {
+            Stream::Tcp(s) => s.peer(),
+            #[cfg(unix)]
+            Stream::Unix(_) => {
+                static NEXT_LOCAL: AtomicU64 = AtomicU64::new(1);
+                Ok(format!(
+                    "local:{}",
+                    NEXT_LOCAL.fetch_add(1, Ordering::Relaxed)
+                ))
+            }
+        }
+    }
+
+    fn closer(&self) -> io::Result<Option<Stream>> {
+        self.try_clone().map(Some)
+    }
+
+    fn split(self, limits: &ConnectionLimits) -> io::Result<(Stream, Stream)> {
+        match &self {
+            Stream::Tcp(s) => {
+                s.set_read_timeout(limits.read_timeout)?;
+                s.set_write_timeout(limits.write_timeout)?;
+            }
+            #[cfg(unix)]
+            Stream::Unix(s) => {
+                s.set_read_timeout(limits.read_timeout)?;
+                s.set_write_timeout(limits.write_timeout)?;
+            }
+        }
+        let writer = self.try_clone()?;
+        Ok((self, writer))
+    }
+}
+
+/// Listener TCP o de socket Unix, según la dirección.
+#[derive(Debug)]
+pub enum StreamListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl StreamListener {
+    /// Escucha en `address`: `unix:<ruta>` para un socket Unix o
+    /// `host:puerto` para TCP.
+    ///
+    /// Si la ruta ya existe y es un socket abandonado por una ejecución
+    /// anterior (nadie acepta conexiones en él), se reemplaza. Si otro
+    /// servidor lo está usando, o es cualquier otro archivo, se respeta y se
+    /// reporta el error.
+    pub fn bind(address: &str) -> io::Result<StreamListener> {
+        match address.strip_prefix(UNIX_PREFIX) {
+            Some(path) => bind_unix(path),
+            None => TcpListener::bind(address).map(StreamListener::Tcp),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &str) -> io::Result<StreamListener> {
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("otro servidor escucha en {}", path),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path).map(StreamListener::Unix)
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &str) -> io::Result<StreamListener> {
+    Err(unix_unsupported())
+}
+
+impl Listener for StreamListener {
+    type Connection = Stream;
+
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            StreamListener::Tcp(l) => Listener::accept(l).map(Stream::Tcp),
+            #[cfg(unix)]
+            StreamListener::Unix(l) => l.accept().map(|(s, _)| Stream::Unix(s)),
+        }
+    }
+}
+
+/// La entrada y salida estándar del proceso como una única conexión, para
+/// usar el servidor como subproceso.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdio;
+
+impl Connection for Stdio {
+    type Reader = io::Stdin;
+    type Writer = io::Stdout;
+
+    fn peer(&self) -> io::Result<String> {
+        Ok(STDIO_ADDRESS.to_string())
+    }
+
+    /// stdin y stdout no se pueden cerrar desde otro hilo.
+    fn closer(&self) -> io::Result<Option<Stream>> {
+        Ok(None)
+    }
+
+    /// Los timeouts no se aplican: stdin y stdout no los admiten.
+    fn split(self, _limits: &ConnectionLimits) -> io::Result<(io::Stdin, io::Stdout)> {
+        Ok((io::stdin(), io::stdout()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::time::Duration;
+
+    #[test]
+    fn test_tcp_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let conn = Listener::accept(&listener).unwrap();
+        assert_eq!(conn.peer().unwrap(), "127.0.0.1");
+
+        let limits = ConnectionLimits {
+            read_timeout: Some(Duration::from_secs(5)),
+            ..ConnectionLimits::default()
+        };
+        let (reader, mut writer) = conn.split(&limits).unwrap();
+        assert_eq!(reader.read_timeout().unwrap(), limits.read_timeout);
+
+        client.write_all(b"GET\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).unwrap();
+        assert_eq!(line, "GET\n");
+        writer.write_all(b"VALUE 0\n").unwrap();
+    }
+
+    #[test]
+    fn test_stream_listener_tcp() {
+        let listener = StreamListener::bind("127.0.0.1:0").unwrap();
+        let StreamListener::Tcp(tcp) = &listener else {
+            panic!("se esperaba un listener TCP");
+        };
+        let addr = tcp.local_addr().unwrap().to_string();
+        let mut client = Stream::connect(&addr).unwrap();
+        let conn = listener.accept().unwrap();
+        assert_eq!(conn.peer().unwrap(), "127.0.0.1");
+
+        let closer = conn.closer().unwrap().unwrap();
+        let (mut reader, _writer) = conn.split(&ConnectionLimits::default()).unwrap();
+        client.write_all(b"GET\n").unwrap();
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"GET\n");
+
+        // Cerrar con otro manejador desbloquea la lectura.
+        closer.shutdown().unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stream_listener_unix() {
+        let path = std::env::temp_dir().join(format!("calc_test_{}.sock", std::process::id()));
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        // Un socket abandonado por otra ejecución se reemplaza.
+        drop(UnixListener::bind(&path).unwrap());
+        let listener = StreamListener::bind(&address).unwrap();
+
+        let mut client = Stream::connect(&address).unwrap();
+        let conn = listener.accept().unwrap();
+        let peer = conn.peer().unwrap();
+        assert!(peer.starts_with("local:"));
+        // cada conexión tiene su propia identidad
+        let _other = Stream::connect(&address).unwrap();
+        assert_ne!(listener.accept().unwrap().peer().unwrap(), peer);
+        let (reader, mut writer) = conn.split(&ConnectionLimits::default()).unwrap();
+
+        client.write_all(b"GET\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).unwrap();
+        assert_eq!(line, "GET\n");
+        writer.write_all(b"VALUE 0\n").unwrap();
+        line.clear();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line, "VALUE 0\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bind_unix_keeps_live_sockets() {
+        let path = std::env::temp_dir().join(format!("calc_live_{}.sock", std::process::id()));
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        let listener = StreamListener::bind(&address).unwrap();
+
+        let err = StreamListener::bind(&address).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        // el primer servidor sigue aceptando conexiones
+        let _client = Stream::connect(&address).unwrap();
+        assert!(listener.accept().is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bind_unix_keeps_regular_files() {
+        let path = std::env::temp_dir().join(format!("calc_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "datos").unwrap();
+        assert!(StreamListener::bind(&format!("{}{}", UNIX_PREFIX, path.display())).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "datos");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,468 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::base64;
+use crate::connection::LineError;
+use crate::http::{Request, Response};
+
+/// Ruta de la pasarela HTTP en la que se aceptan conexiones WebSocket.
+pub const PATH: &str = "/ws";
+
+/// Constante de RFC 6455 para calcular `Sec-WebSocket-Accept`.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Códigos de cierre de RFC 6455 (sección 7.4.1).
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_POLICY_VIOLATION: u16 = 1008;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+/// Mensaje WebSocket completo (los fragmentos ya están unidos).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// Cierre, con el código si el otro extremo lo indicó.
+    Close(Option<u16>),
+}
+
+/// Motivos por los que no se pudo leer un mensaje.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// Falló la lectura, venció el timeout o el mensaje supera el máximo.
+    Line(LineError),
+    /// El cliente violó el protocolo; se cierra con el código y el motivo.
+    Protocol(u16, String),
+}
+
+/// Responde el pedido de apertura de una conexión WebSocket.
+///
+/// # Retorno
+/// - `Ok(respuesta)` con `101 Switching Protocols` si el pedido es válido;
+///   después de enviarla la conexión habla WebSocket.
+/// - `Err(respuesta)` con el error a enviar si el pedido no es una apertura
+///   WebSocket válida.
+pub fn handshake(request: &Request) -> Result<Response, Response> {
+    if request.method != "GET" {
+        return Err(Response::error(405, "method not allowed").with_header("Allow", "GET"));
+    }
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+    let connection = request.header("connection").is_some_and(|c| {
+        c.split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+    });
+    if !upgrade || !connection {
+        return Err(
+            Response::error(426, "websocket upgrade required").with_header("Upgrade", "websocket")
+        );
+    }
+    if request.header("sec-websocket-version") != Some("13") {
+        return Err(Response::error(426, "unsupported websocket version")
+            .with_header("Sec-WebSocket-Version", "13"));
+    }
+    let key = request
+        .header("sec-websocket-key")
+        .filter(|key| base64::decode(key).is_some_and(|k| k.len() == 16))
+        .ok_or_else(|| Response::error(400, "invalid Sec-WebSocket-Key"))?;
+
+    Ok(Response {
+        status: 101,
+        headers: Vec::new(),
+        body: String::new(),
+    }
+    .with_header("Upgrade", "websocket")
+    .with_header("Connection", "Upgrade")
+    .with_header("Sec-WebSocket-Accept", &accept_key(key)))
+}
+
+/// Valor de `Sec-WebSocket-Accept` para la clave del cliente.
+pub fn accept_key(key: &str) -> String {
+    base64::encode(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+/// Lee mensajes de un cliente WebSocket.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner: R,
+    max_len: usize,
+    /// Mensaje fragmentado en curso: si es de texto y los datos recibidos.
+    message: Option<(bool, Vec<u8>)>,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Lector de mensajes de a lo sumo `max_len` bytes.
+    pub fn new(inner: R, max_len: usize) -> Self {
+        FrameReader {
+            inner,
+            max_len,
+            message: None,
+        }
+    }
+
+    /// Lee el próximo mensaje, uniendo los fragmentos. Los mensajes de
+    /// control (`Ping`, `Pong`, `Close`) pueden llegar entre fragmentos y se
+    /// retornan apenas llegan.
+    ///
+    /// # Retorno
+    /// - `Ok(Some(frame))` si se leyó un mensaje completo.
+    /// - `Ok(None)` si el cliente cerró la conexión entre mensajes.
+    /// - `Err(FrameError)` si falló la lectura o el cliente violó el
+    ///   protocolo.
+    pub fn read(&mut self) -> Result<Option<Frame>, FrameError> {
+        loop {
+            let Some((fin, opcode, payload)) = self.read_raw()? else {
+                return Ok(None);
+            };
+            match opcode {
+                0x8 => {
+                    let code = payload.get(..2).map(|c| u16::from_be_bytes([c[0], c[1]]));
+                    return Ok(Some(Frame::Close(code)));
+                }
+                0x9 => return Ok(Some(Frame::Ping(payload))),
+                0xA => return Ok(Some(Frame::Pong(payload))),
+                0x1 | 0x2 if self.message.is_none() => {
+                    self.message = Some((opcode == 0x1, payload));
+                }
+                0x0 => match &mut self.message {
+                    Some((_, data)) if data.len() + payload.len() > self.max_len => {
+                        return Err(FrameError::Line(LineError::TooLong));
+                    }
+                    Some((_, data)) => data.extend_from_slice(&payload),
+                    None => return Err(protocol_error("unexpected continuation frame")),
+                },
+                0x1 | 0x2 => return Err(protocol_error("expected continuation frame")),
+                _ => return Err(protocol_error("unknown opcode")),
+            }
+
+            if fin && let Some((text, data)) = self.message.take() {
+                if !text {
+                    return Ok(Some(Frame::Binary(data)));
+                }
+                return String::from_utf8(data)
+                    .map(|s| Some(Frame::Text(s)))
+                    .map_err(|_| {
+                        FrameError::Protocol(CLOSE_INVALID_DATA, "invalid UTF-8".to_string())
+                    });
+            }
+        }
+    }
+
+    /// Lee un frame: si es el último fragmento, su opcode y los datos ya
+    /// desenmascarados.
+    fn read_raw(&mut self) -> Result<Option<(bool, u8, Vec<u8>)>, FrameError> {
+        let mut head = [0; 2];
+        match self.inner.read(&mut head[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(io_error(e)),
+        }
+        self.read_exact(&mut head[1..])?;
+
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits set"));
+        }
+        if head[1] & 0x80 == 0 {
+            return Err(protocol_error("client frames must be masked"));
+        }
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut n = [0; 2];
+                self.read_exact(&mut n)?;
+                u64::from(u16::from_be_bytes(n))
+            }
+            127 => {
+                let mut n = [0; 8];
+                self.read_exact(&mut n)?;
+                u64::from_be_bytes(n)
+            }
+            n => u64::from(n),
+        };
+        if opcode >= 0x8 && (!fin || len > 125) {
+            return Err(protocol_error("invalid control frame"));
+        }
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= self.max_len)
+            .ok_or(FrameError::Line(LineError::TooLong))?;
+
+        let mut mask = [0; 4];
+        self.read_exact(&mut mask)?;
+        let mut payload = vec![0; len];
+        self.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok(Some((fin, opcode, payload)))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), FrameError> {
+        self.inner.read_exact(buf).map_err(io_error)
+    }
+}
+
+fn protocol_error(motivo: &str) -> FrameError {
+    FrameError::Protocol(CLOSE_PROTOCOL_ERROR, motivo.to_string())
+}
+
+fn io_error(e: io::Error) -> FrameError {
+    FrameError::Line(match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => LineError::Timeout,
+        _ => LineError::Io(e.to_string()),
+    })
+}
+
+/// Escribe un mensaje del servidor en un único frame, sin máscara.
+pub fn write_frame<W: Write>(writer: &mut W, frame: &Frame) -> io::Result<()> {
+    let (opcode, payload): (u8, &[u8]) = match frame {
+        Frame::Text(text) => (0x1, text.as_bytes()),
+        Frame::Binary(data) => (0x2, data),
+        Frame::Ping(data) => (0x9, data),
+        Frame::Pong(data) => (0xA, data),
+        Frame::Close(Some(code)) => (0x8, &code.to_be_bytes()),
+        Frame::Close(None) => (0x8, &[]),
+    };
+    let len = payload.len();
+
+    let mut head = vec![0x80 | opcode];
+    match len {
+        0..=125 => head.push(len as u8),
+        126..=0xFFFF => {
+            head.push(126);
+            head.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            head.push(127);
+            head.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    writer.write_all(&head)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// SHA-1 (RFC 3174). Sólo se usa para el handshake de WebSocket, que lo
+/// exige; no tiene uso criptográfico.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Frame enmascarado, como los que envía un cliente.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            n @ 0..=125 => frame.push(0x80 | n as u8),
+            n => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn read_all(bytes: Vec<u8>) -> Vec<Result<Option<Frame>, FrameError>> {
+        let mut reader = FrameReader::new(Cursor::new(bytes), 1000);
+        let mut frames = Vec::new();
+        loop {
+            let frame = reader.read();
+            let done = !matches!(frame, Ok(Some(_)));
+            frames.push(frame);
+            if done {
+                return frames;
+            }
+        }
+    }
+
+    #[test]
+    fn test_sha1() {
+        let hex = |d: [u8; 20]| d.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // Ejemplo de RFC 6455, sección 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_handshake() {
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: PATH.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: vec![
+                ("Upgrade".into(), "websocket".into()),
+                ("Connection".into(), "keep-alive, Upgrade".into()),
+                ("Sec-WebSocket-Version".into(), "13".into()),
+                (
+                    "Sec-WebSocket-Key".into(),
+                    "dGhlIHNhbXBsZSBub25jZQ==".into(),
+                ),
+            ],
+            body: Vec::new(),
+        };
+        let response = handshake(&request).unwrap();
+        assert_eq!(response.status, 101);
+        assert!(response.headers.contains(&(
+            "Sec-WebSocket-Accept".into(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".into()
+        )));
+
+        request.headers[2].1 = "8".into();
+        assert_eq!(handshake(&request).unwrap_err().status, 426);
+        request.headers[2].1 = "13".into();
+        request.headers[3].1 = "corta".into();
+        assert_eq!(handshake(&request).unwrap_err().status, 400);
+        request.headers.remove(0);
+        assert_eq!(handshake(&request).unwrap_err().status, 426);
+    }
+
+    #[test]
+    fn test_read_frames() {
+        let mut bytes = client_frame(true, 0x1, b"GET");
+        // Mensaje fragmentado con un ping en el medio.
+        bytes.extend(client_frame(false, 0x1, b"OP "));
+        bytes.extend(client_frame(true, 0x9, b"hola"));
+        bytes.extend(client_frame(true, 0x0, "+ 5 é".as_bytes()));
+        bytes.extend(client_frame(true, 0x1, &[b'x'; 300]));
+        bytes.extend(client_frame(true, 0x8, &1000u16.to_be_bytes()));
+        assert_eq!(
+            read_all(bytes),
+            [
+                Ok(Some(Frame::Text("GET".into()))),
+                Ok(Some(Frame::Ping(b"hola".to_vec()))),
+                Ok(Some(Frame::Text("OP + 5 é".into()))),
+                Ok(Some(Frame::Text("x".repeat(300)))),
+                Ok(Some(Frame::Close(Some(1000)))),
+                Ok(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_invalid_frames() {
+        let last = |bytes: Vec<u8>| read_all(bytes).pop().unwrap();
+        let protocol = |code: u16| move |r: Result<Option<Frame>, FrameError>| matches!(r, Err(FrameError::Protocol(c, _)) if c == code);
+
+        // Sin máscara.
+        assert!(protocol(CLOSE_PROTOCOL_ERROR)(last(vec![0x81, 0x00])));
+        assert!(protocol(CLOSE_PROTOCOL_ERROR)(last(client_frame(
+            true, 0x0, b"x"
+        ))));
+        assert!(protocol(CLOSE_PROTOCOL_ERROR)(last(client_frame(
+            true, 0x3, b""
+        ))));
+        assert!(protocol(CLOSE_PROTOCOL_ERROR)(last(client_frame(
+            false, 0x9, b""
+        ))));
+        assert!(protocol(CLOSE_INVALID_DATA)(last(client_frame(
+            true,
+            0x1,
+            &[0xFF]
+        ))));
+        let mut bytes = client_frame(false, 0x1, b"a");
+        bytes.extend(client_frame(true, 0x1, b"b"));
+        assert!(protocol(CLOSE_PROTOCOL_ERROR)(last(bytes)));
+        assert_eq!(
+            last(client_frame(true, 0x1, &[b'x'; 1001])),
+            Err(FrameError::Line(LineError::TooLong))
+        );
+        let mut bytes = client_frame(false, 0x1, &[b'x'; 600]);
+        bytes.extend(client_frame(true, 0x0, &[b'x'; 600]));
+        assert_eq!(last(bytes), Err(FrameError::Line(LineError::TooLong)));
+        assert!(matches!(
+            last(client_frame(true, 0x1, b"GET")[..4].to_vec()),
+            Err(FrameError::Line(LineError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn test_write_frame() {
+        let mut out = Vec::new();
+        write_frame(&mut out, &Frame::Text("OK @1".into())).unwrap();
+        assert_eq!(out, b"\x81\x05OK @1");
+
+        out.clear();
+        write_frame(&mut out, &Frame::Close(Some(CLOSE_NORMAL))).unwrap();
+        assert_eq!(out, [0x88, 0x02, 0x03, 0xE8]);
+
+        out.clear();
+        write_frame(&mut out, &Frame::Text("x".repeat(200))).unwrap();
+        assert_eq!(out[..4], [0x81, 126, 0, 200]);
+        assert_eq!(out.len(), 204);
+    }
+}